@@ -0,0 +1,109 @@
+//! JSON-RPC 2.0 response and error objects
+
+use orchestrator_core::constants::{error_code, field, rpc};
+use serde_json::{Value, json};
+
+/// A JSON-RPC error object
+#[derive(Debug, Clone)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self::new(error_code::PARSE_ERROR, message)
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(error_code::INVALID_REQUEST, message)
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(error_code::METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(error_code::INVALID_PARAMS, message)
+    }
+
+    /// Attach structured details, sent as `error.data`
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    /// Core errors keep their stable application code; anything else
+    /// (panicked task, serialization failure) is an internal error.
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<orchestrator_core::Error>() {
+            Some(core) => Self {
+                code: core.code(),
+                message: core.to_string(),
+                data: Some(json!({ "kind": core.kind() })),
+            },
+            None => Self::new(error_code::INTERNAL_ERROR, err.to_string()),
+        }
+    }
+}
+
+/// Build a success response
+pub fn success(id: Value, result: Value) -> Value {
+    json!({
+        "jsonrpc": rpc::VERSION,
+        field::ID: id,
+        field::RESULT: result
+    })
+}
+
+/// Build an error response
+pub fn failure(id: Value, error: RpcError) -> Value {
+    let mut body = json!({
+        field::CODE: error.code,
+        field::MESSAGE: error.message
+    });
+    if let Some(data) = error.data {
+        body[field::DATA] = data;
+    }
+
+    json!({
+        "jsonrpc": rpc::VERSION,
+        field::ID: id,
+        field::ERROR: body
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_error_keeps_code() {
+        let err: RpcError = anyhow::Error::from(orchestrator_core::Error::Cancelled).into();
+        assert_eq!(err.code, error_code::CANCELLED);
+        assert_eq!(err.data.unwrap()["kind"], "cancelled");
+
+        let err: RpcError = anyhow::anyhow!("boom").into();
+        assert_eq!(err.code, error_code::INTERNAL_ERROR);
+    }
+
+    #[test]
+    fn test_failure_shape() {
+        let resp = failure(json!(7), RpcError::method_not_found("nope"));
+        assert_eq!(resp[field::ID], 7);
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::METHOD_NOT_FOUND);
+        assert!(resp[field::ERROR].get(field::DATA).is_none());
+        assert!(resp.get(field::RESULT).is_none());
+    }
+}
@@ -23,29 +23,56 @@
 //!
 //! # Run tool server (called by OpenCode)
 //! orchestrator serve
+//!
+//! # Limit concurrently executing tool calls
+//! orchestrator serve --max-in-flight 8
+//!
+//! # Share one server between sessions
+//! orchestrator serve --listen unix:/tmp/orchestrator.sock
+//! orchestrator serve --listen tcp:127.0.0.1:7777
+//!
+//! # Build or refresh the trigram index of the current project
+//! orchestrator index
 //! ```
 
 use anyhow::{Context, Result};
 use orchestrator_core::hooks::Hook;
-use orchestrator_core::constants::{rpc, tool, agent, field};
+use orchestrator_core::constants::agent;
+use orchestrator_core::tools::index::INDEX_FILE;
+use orchestrator_core::tools::{CancelToken, TrigramIndex};
 use serde_json::{Value, json};
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
-use tracing::{debug, error, info};
-use tracing_subscriber::EnvFilter;
 
+mod jsonrpc;
+mod resources;
+mod server;
 mod tools;
+mod transport;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
 
     match args.get(1).map(|s| s.as_str()) {
-        Some("serve") => serve().await,
+        Some("serve") => {
+            let options = server::ServeOptions::from_args(&args[2..])?;
+            let code = match server::serve(options).await {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Error: {:?}", e);
+                    1
+                }
+            };
+            // Exit without dropping the runtime: the stdin reader is a
+            // blocking thread that cannot be interrupted, and runtime
+            // shutdown would wait on it after `exit` or SIGTERM.
+            std::process::exit(code);
+        }
         Some("hooks") => list_hooks(),
         Some("agents") => list_agents(),
+        Some("index") => build_index(args.get(2).map(PathBuf::from)),
         Some("install") => install().await,
         Some("uninstall") => uninstall().await,
         Some("--help") | Some("-h") | None => {
@@ -69,6 +96,10 @@ fn print_help() {
     eprintln!("  hooks      List available hooks");
     eprintln!("  agents     List available agents");
     eprintln!("  serve      Run tool server (called by OpenCode)");
+    eprintln!("               --max-in-flight <N>  Concurrent request limit (default: {})", server::DEFAULT_MAX_IN_FLIGHT);
+    eprintln!("               --listen <ADDR>      unix:/path.sock or tcp:HOST:PORT (default: stdio)");
+    eprintln!("               --shutdown-timeout <SECS>  Wait for running calls on shutdown (default: {})", server::DEFAULT_SHUTDOWN_TIMEOUT.as_secs());
+    eprintln!("  index [DIR]  Build or refresh the trigram index used by grep_search and mgrep");
     eprintln!("  install    Register plugin with OpenCode");
     eprintln!("  uninstall  Remove plugin from OpenCode");
     eprintln!("  --help     Show this help");
@@ -90,28 +121,41 @@ fn list_hooks() -> Result<()> {
     Ok(())
 }
 
+/// Build or refresh the trigram index of a project
+fn build_index(directory: Option<PathBuf>) -> Result<()> {
+    let root = match directory {
+        Some(dir) => dir,
+        None => env::current_dir()?,
+    };
+    let (index, stats) = TrigramIndex::update(&root, &CancelToken::new())?;
+
+    println!("🔎 Indexed {} files ({} re-read, {} removed, {} trigrams)", stats.files, stats.reindexed, stats.removed, stats.trigrams);
+    println!("   {}", index.root().join(INDEX_FILE).display());
+    Ok(())
+}
+
 /// List available agents
 fn list_agents() -> Result<()> {
     println!("🤖 Available Agents (4-Agent Architecture)");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!();
-    println!("  {:15} {}", "ID", "Role");
+    println!("  {:15} Role", "ID");
     println!("  {:15} {}", "─".repeat(15), "─".repeat(45));
     println!(
-        "  {:15} {}",
-        agent::COMMANDER, "Autonomous orchestrator - executes until mission complete"
+        "  {:15} Autonomous orchestrator - executes until mission complete",
+        agent::COMMANDER
     );
     println!(
-        "  {:15} {}",
-        agent::PLANNER, "Strategic planning and research specialist"
+        "  {:15} Strategic planning and research specialist",
+        agent::PLANNER
     );
     println!(
-        "  {:15} {}",
-        agent::WORKER, "Implementation and documentation specialist"
+        "  {:15} Implementation and documentation specialist",
+        agent::WORKER
     );
     println!(
-        "  {:15} {}",
-        agent::REVIEWER, "Verification and context management specialist"
+        "  {:15} Verification and context management specialist",
+        agent::REVIEWER
     );
     println!();
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -198,233 +242,6 @@ async fn uninstall() -> Result<()> {
     Ok(())
 }
 
-/// Serve: Run tool server on stdio
-async fn serve() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(io::stderr)
-        .init();
-
-    info!("OpenCode Orchestrator starting");
-
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    for line in stdin.lock().lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(e) => {
-                error!("Read error: {}", e);
-                continue;
-            }
-        };
-
-        if line.trim().is_empty() {
-            continue;
-        }
-
-        debug!("Received: {}", line);
-
-        let request: Value = match serde_json::from_str(&line) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Parse error: {}", e);
-                continue;
-            }
-        };
-
-        let response = handle_request(&request).await;
-
-        if let Some(resp) = response {
-            let resp_str = serde_json::to_string(&resp)?;
-            debug!("Sending: {}", resp_str);
-            writeln!(stdout, "{}", resp_str)?;
-            stdout.flush()?;
-        }
-    }
-
-    Ok(())
-}
-
-/// Handle JSON-RPC request
-async fn handle_request(request: &Value) -> Option<Value> {
-    let method = request.get(field::METHOD)?.as_str()?;
-    let id = request.get(field::ID).cloned();
-
-    let result = match method {
-        rpc::INITIALIZE => {
-            json!({
-                "protocolVersion": rpc::PROTOCOL_VERSION,
-                "serverInfo": {
-                    "name": "orchestrator",
-                    "version": env!("CARGO_PKG_VERSION")
-                },
-                "capabilities": { "tools": {} }
-            })
-        }
-        rpc::TOOLS_LIST => {
-            json!({
-                "tools": [
-                    {
-                        "name": tool::GREP_SEARCH,
-                        "description": "Fast regex search with timeout protection",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "pattern": {"type": "string", "description": "Regex pattern"},
-                                "directory": {"type": "string", "description": "Search directory"},
-                                "max_results": {"type": "number", "description": "Max results (default: 100)"},
-                                "timeout_ms": {"type": "number", "description": "Timeout in milliseconds (default: 30000)"}
-                            },
-                            "required": ["pattern"]
-                        }
-                    },
-                    {
-                        "name": tool::GLOB_SEARCH,
-                        "description": "Find files by glob pattern",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "pattern": {"type": "string", "description": "Glob pattern (e.g., **/*.rs)"},
-                                "directory": {"type": "string", "description": "Search directory"}
-                            },
-                            "required": ["pattern"]
-                        }
-                    },
-                    {
-                        "name": tool::MGREP,
-                        "description": "Search multiple patterns in parallel. Much faster than running grep multiple times.",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "patterns": {"type": "array", "items": {"type": "string"}, "description": "Array of regex patterns to search"},
-                                "directory": {"type": "string", "description": "Search directory (optional)"},
-                                "max_results_per_pattern": {"type": "number", "description": "Max results per pattern (default: 50)"},
-                                "timeout_ms": {"type": "number", "description": "Timeout in milliseconds"}
-                            },
-                            "required": ["patterns"]
-                        }
-                    },
-                    {
-                        "name": tool::SED_REPLACE,
-                        "description": "Find and replace patterns in files (sed-like)",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "pattern": {"type": "string", "description": "Regex pattern to find"},
-                                "replacement": {"type": "string", "description": "Replacement string"},
-                                "file": {"type": "string", "description": "Single file to modify"},
-                                "directory": {"type": "string", "description": "Directory to modify (recursive)"},
-                                "dry_run": {"type": "boolean", "description": "Preview changes without modifying (default: false)"},
-                                "backup": {"type": "boolean", "description": "Create .bak backup (default: false)"},
-                                "timeout_ms": {"type": "number", "description": "Timeout in milliseconds"}
-                            },
-                            "required": ["pattern", "replacement"]
-                        }
-                    },
-                    {
-                        "name": tool::DIFF,
-                        "description": "Compare two files or strings",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "file1": {"type": "string"},
-                                "file2": {"type": "string"},
-                                "content1": {"type": "string"},
-                                "content2": {"type": "string"}
-                            }
-                        }
-                    },
-                    {
-                        "name": tool::LSP_DIAGNOSTICS,
-                        "description": "Get LSP diagnostics (errors/warnings) for files",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "directory": {"type": "string", "description": "Directory to check"},
-                                "file": {"type": "string", "description": "Specific file or glob filter"},
-                                "include_warnings": {"type": "boolean", "description": "Include warnings (default: true)"}
-                            }
-                        }
-                    },
-                    {
-                        "name": tool::AST_SEARCH,
-                        "description": "Structural code search using ast-grep",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "pattern": {"type": "string", "description": "ast-grep pattern (e.g. 'const $X = $Y')"},
-                                "directory": {"type": "string", "description": "Directory to search"},
-                                "lang": {"type": "string", "description": "Language (typescript, javascript, rust, etc)"},
-                                "include": {"type": "string", "description": "Glob filter for files"}
-                            },
-                            "required": ["pattern"]
-                        }
-                    },
-                    {
-                        "name": tool::AST_REPLACE,
-                        "description": "Structural code replace using ast-grep",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "pattern": {"type": "string", "description": "ast-grep search pattern"},
-                                "rewrite": {"type": "string", "description": "ast-grep rewrite pattern"},
-                                "directory": {"type": "string", "description": "Directory to modify"},
-                                "lang": {"type": "string", "description": "Language"},
-                                "include": {"type": "string", "description": "Glob filter"}
-                            },
-                            "required": ["pattern", "rewrite"]
-                        }
-                    },
-                    {
-                        "name": tool::LIST_AGENTS,
-                        "description": "List available agents",
-                        "inputSchema": {"type": "object", "properties": {}}
-                    },
-                    {
-                        "name": tool::LIST_HOOKS,
-                        "description": "List available hooks",
-                        "inputSchema": {"type": "object", "properties": {}}
-                    }
-                ]
-            })
-        }
-        rpc::TOOLS_CALL => {
-            let params = request.get(field::PARAMS)?;
-            let tool_name = params.get("name")?.as_str()?;
-            let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
-
-            match tools::execute_tool(tool_name, arguments).await {
-                Ok(result) => json!({
-                    field::CONTENT: [{
-                        field::TYPE: field::TEXT,
-                        field::TEXT: result
-                    }]
-                }),
-                Err(e) => {
-                    json!({
-                        field::CONTENT: [{
-                            field::TYPE: field::TEXT,
-                            field::TEXT: format!("Error: {}", e)
-                        }],
-                        field::IS_ERROR: true
-                    })
-                }
-            }
-        }
-        _ => {
-            debug!("Unknown method: {}", method);
-            return None;
-        }
-    };
-
-    Some(json!({
-        "jsonrpc": rpc::VERSION,
-        field::ID: id,
-        field::RESULT: result
-    }))
-}
-
 fn get_opencode_config_path() -> Result<PathBuf> {
     if let Ok(xdg) = env::var("XDG_CONFIG_HOME") {
         return Ok(PathBuf::from(xdg).join("opencode").join("opencode.json"));
@@ -449,47 +266,3 @@ fn get_opencode_config_path() -> Result<PathBuf> {
         "Could not determine config path (checked XDG_CONFIG_HOME, HOME, USERPROFILE, APPDATA)"
     ))
 }
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
-
-    #[tokio::test]
-    async fn test_handle_initialize() {
-        let req = json!({
-            "jsonrpc": rpc::VERSION,
-            field::ID: 1,
-            field::METHOD: rpc::INITIALIZE
-        });
-        let resp = handle_request(&req).await.unwrap();
-        assert_eq!(resp[field::RESULT]["serverInfo"]["name"], "orchestrator");
-    }
-
-    #[tokio::test]
-    async fn test_handle_tools_list() {
-        let req = json!({
-            "jsonrpc": rpc::VERSION,
-            field::ID: 1,
-            field::METHOD: rpc::TOOLS_LIST
-        });
-        let resp = handle_request(&req).await.unwrap();
-        let tools = resp[field::RESULT]["tools"].as_array().unwrap();
-        assert!(tools.iter().any(|t| t["name"] == tool::GREP_SEARCH));
-        assert!(tools.iter().any(|t| t["name"] == tool::MGREP));
-    }
-
-    #[tokio::test]
-    async fn test_handle_tools_call_unknown() {
-        let req = json!({
-            "jsonrpc": rpc::VERSION,
-            field::ID: 1,
-            field::METHOD: rpc::TOOLS_CALL,
-            field::PARAMS: {
-                "name": "non_existent_tool",
-                "arguments": {}
-            }
-        });
-        let resp = handle_request(&req).await.unwrap();
-        assert!(resp[field::RESULT][field::CONTENT][0][field::TEXT].as_str().unwrap().contains("Unknown tool"));
-    }
-}
@@ -0,0 +1,412 @@
+//! MCP resources
+//!
+//! Project files are exposed as `file://` URIs, filtered like `glob_search`
+//! (hidden, gitignored and `.orchestratorignore`d files are left out). Tool
+//! artifacts live under `orchestrator://`:
+//!
+//! - `orchestrator://git/diff` and `orchestrator://git/diff/staged`: `git diff` output
+//! - `orchestrator://file-stats` and `orchestrator://file-stats/{path}`: `file_stats` summaries
+//!
+//! Artifacts are produced by the same registry entries as the matching
+//! tools, so a resource and a tool call never disagree, and a tool that is
+//! disabled also hides its resources. Only `file://` resources can be
+//! subscribed to; see [`crate::server`] for how changes are detected.
+
+use crate::tools;
+use orchestrator_core::constants::{field, tool};
+use orchestrator_core::tools::glob::GlobTool;
+use orchestrator_core::tools::{CancelToken, ToolContext};
+use orchestrator_core::{Error, Result};
+use serde_json::{Value, json};
+use std::path::{Component, Path, PathBuf};
+
+/// Number of resources returned per `resources/list` page
+pub const PAGE_SIZE: usize = 500;
+
+/// Files larger than this cannot be read as a resource
+const MAX_READ_BYTES: u64 = 10 * 1024 * 1024;
+
+const FILE_SCHEME: &str = "file://";
+const GIT_DIFF_URI: &str = "orchestrator://git/diff";
+const GIT_DIFF_STAGED_URI: &str = "orchestrator://git/diff/staged";
+const FILE_STATS_URI: &str = "orchestrator://file-stats";
+
+/// What a connection may see: the project root and which artifacts are enabled
+#[derive(Debug, Clone)]
+pub struct ResourceScope {
+    pub root: PathBuf,
+    pub git_diff: bool,
+    pub file_stats: bool,
+}
+
+/// `resources/list`: artifacts first, then project files, one page at a time
+pub async fn list(scope: ResourceScope, cursor: Option<String>, cancel: CancelToken) -> anyhow::Result<Value> {
+    let offset = match cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .map_err(|_| Error::invalid_arguments(format!("Invalid cursor: {}", cursor)))?,
+        None => 0,
+    };
+
+    // The walk is lazy and stops one file past the page
+    let mut page = tokio::task::spawn_blocking(move || -> Result<Vec<Value>> {
+        let files = project_files(&scope.root, &cancel)?;
+        let page = artifacts(&scope).into_iter().chain(files).skip(offset).take(PAGE_SIZE + 1).collect();
+        // A cancelled walk ends early; never pass that off as the last page
+        cancel.check()?;
+        Ok(page)
+    })
+    .await??;
+
+    let more = page.len() > PAGE_SIZE;
+    page.truncate(PAGE_SIZE);
+    let mut result = json!({ "resources": page });
+    if more {
+        result[field::NEXT_CURSOR] = json!((offset + PAGE_SIZE).to_string());
+    }
+    Ok(result)
+}
+
+/// `resources/templates/list`
+pub fn templates(scope: &ResourceScope) -> Value {
+    let mut templates = vec![json!({
+        "uriTemplate": format!("{}{}/{{path}}", FILE_SCHEME, encode_path(&scope.root)),
+        "name": "Project file",
+        "description": "A file in the project, by path relative to the project root"
+    })];
+    if scope.file_stats {
+        templates.push(json!({
+            "uriTemplate": format!("{}/{{path}}", FILE_STATS_URI),
+            "name": "Directory statistics",
+            "description": "file_stats summary of a directory relative to the project root",
+            field::MIME_TYPE: "application/json"
+        }));
+    }
+    json!({ "resourceTemplates": templates })
+}
+
+/// `resources/read`
+pub async fn read(scope: ResourceScope, uri: String, cancel: CancelToken) -> anyhow::Result<Value> {
+    let content = tokio::task::spawn_blocking(move || read_blocking(&scope, &uri, cancel)).await??;
+    Ok(json!({ "contents": [content] }))
+}
+
+/// File a `file://` subscription should watch
+pub fn subscription_path(scope: &ResourceScope, uri: &str) -> Result<PathBuf> {
+    if !uri.starts_with(FILE_SCHEME) {
+        return Err(Error::invalid_arguments(format!(
+            "Only file:// resources can be subscribed to: {}",
+            uri
+        )));
+    }
+    resolve_file(&scope.root, uri)
+}
+
+fn read_blocking(scope: &ResourceScope, uri: &str, cancel: CancelToken) -> Result<Value> {
+    let ctx = ToolContext::new(cancel);
+    let root = scope.root.to_string_lossy();
+
+    match uri {
+        GIT_DIFF_URI | GIT_DIFF_STAGED_URI if scope.git_diff => {
+            let staged = uri == GIT_DIFF_STAGED_URI;
+            let diff = tools::registry().call(
+                tool::GIT_DIFF,
+                json!({"directory": root, "staged_only": staged}),
+                &ctx,
+            )?;
+            return Ok(json!({
+                field::URI: uri,
+                field::MIME_TYPE: "text/x-diff",
+                field::TEXT: diff["diff"]
+            }));
+        }
+        _ => {}
+    }
+
+    if scope.file_stats
+        && let Some(rest) = uri.strip_prefix(FILE_STATS_URI)
+        && (rest.is_empty() || rest.starts_with('/'))
+    {
+        let directory = match rest.trim_start_matches('/') {
+            "" => scope.root.clone(),
+            relative => resolve_relative(&scope.root, &decode(relative))
+                .ok_or_else(|| Error::ResourceNotFound(uri.to_string()))?,
+        };
+        let stats = tools::registry().call(
+            tool::FILE_STATS,
+            json!({"directory": directory.to_string_lossy()}),
+            &ctx,
+        )?;
+        return Ok(json!({
+            field::URI: uri,
+            field::MIME_TYPE: "application/json",
+            field::TEXT: serde_json::to_string_pretty(&stats)?
+        }));
+    }
+
+    if uri.starts_with(FILE_SCHEME) {
+        let path = resolve_file(&scope.root, uri)?;
+        let size = path.metadata()?.len();
+        if size > MAX_READ_BYTES {
+            return Err(Error::tool(format!(
+                "{} is {} bytes, over the {} byte resource limit",
+                uri, size, MAX_READ_BYTES
+            )));
+        }
+        let bytes = std::fs::read(&path)?;
+        let mime = mime_type(&path);
+        return Ok(match String::from_utf8(bytes) {
+            Ok(text) => json!({
+                field::URI: uri,
+                field::MIME_TYPE: mime.unwrap_or("text/plain"),
+                field::TEXT: text
+            }),
+            Err(e) => json!({
+                field::URI: uri,
+                field::MIME_TYPE: mime.unwrap_or("application/octet-stream"),
+                "blob": base64(e.as_bytes())
+            }),
+        });
+    }
+
+    Err(Error::ResourceNotFound(uri.to_string()))
+}
+
+fn artifacts(scope: &ResourceScope) -> Vec<Value> {
+    let mut artifacts = Vec::new();
+    if scope.git_diff {
+        artifacts.push(json!({
+            field::URI: GIT_DIFF_URI,
+            "name": "git diff",
+            "description": "Unstaged changes in the project repository",
+            field::MIME_TYPE: "text/x-diff"
+        }));
+        artifacts.push(json!({
+            field::URI: GIT_DIFF_STAGED_URI,
+            "name": "git diff --staged",
+            "description": "Staged changes in the project repository",
+            field::MIME_TYPE: "text/x-diff"
+        }));
+    }
+    if scope.file_stats {
+        artifacts.push(json!({
+            field::URI: FILE_STATS_URI,
+            "name": "Project statistics",
+            "description": "File counts, sizes, lines and file types of the project",
+            field::MIME_TYPE: "application/json"
+        }));
+    }
+    artifacts
+}
+
+/// Every file `glob_search` would list under `root`, in a stable order
+fn project_files(root: &Path, cancel: &CancelToken) -> Result<impl Iterator<Item = Value> + use<>> {
+    let root = root.to_path_buf();
+    let files = GlobTool::default().walker()?.with_cancel(cancel.clone()).files(&root);
+
+    Ok(files.map(move |entry| {
+        let path = entry.path();
+        let name = path.strip_prefix(&root).unwrap_or(path).to_string_lossy().into_owned();
+        let mut resource = json!({
+            field::URI: file_uri(path),
+            "name": name
+        });
+        if let Some(mime) = mime_type(path) {
+            resource[field::MIME_TYPE] = json!(mime);
+        }
+        if let Ok(meta) = entry.metadata() {
+            resource["size"] = json!(meta.len());
+        }
+        resource
+    }))
+}
+
+/// Map a `file://` URI to a path inside `root` that `glob_search` would list
+fn resolve_file(root: &Path, uri: &str) -> Result<PathBuf> {
+    let not_found = || Error::ResourceNotFound(uri.to_string());
+    let path = PathBuf::from(decode(uri.strip_prefix(FILE_SCHEME).ok_or_else(not_found)?));
+    let root = root.canonicalize()?;
+    let path = path.canonicalize().map_err(|_| not_found())?;
+    let relative = path.strip_prefix(&root).map_err(|_| not_found())?;
+
+    if relative.as_os_str().is_empty() || !GlobTool::default().contains(&root, &path)? {
+        return Err(not_found());
+    }
+    Ok(path)
+}
+
+/// Join a client-supplied relative path to `root`, refusing to leave it
+fn resolve_relative(root: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let path = root.join(relative);
+    path.is_dir().then_some(path)
+}
+
+fn file_uri(path: &Path) -> String {
+    format!("{}{}", FILE_SCHEME, encode_path(path))
+}
+
+/// Percent-encode everything but unreserved characters and `/`
+fn encode_path(path: &Path) -> String {
+    let mut out = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = s.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "rs" => "text/x-rust",
+        "ts" | "tsx" => "text/typescript",
+        "js" | "mjs" | "cjs" | "jsx" => "text/javascript",
+        "py" => "text/x-python",
+        "go" => "text/x-go",
+        "json" => "application/json",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "md" => "text/markdown",
+        "html" => "text/html",
+        "css" => "text/css",
+        "txt" => "text/plain",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "pdf" => "application/pdf",
+        _ => return None,
+    })
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scope(root: &Path) -> ResourceScope {
+        ResourceScope {
+            root: root.to_path_buf(),
+            git_diff: false,
+            file_stats: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("my notes.md"), "# hi\n").unwrap();
+        fs::write(root.join(".env"), "SECRET=1\n").unwrap();
+        fs::write(root.join(".gitignore"), "node_modules/\n").unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+
+        let listed = list(scope(&root), None, CancelToken::new()).await.unwrap();
+        let uris: Vec<&str> = listed["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r[field::URI].as_str().unwrap())
+            .collect();
+        assert_eq!(uris.len(), 3);
+        assert_eq!(uris[0], FILE_STATS_URI);
+        assert!(uris.iter().any(|u| u.ends_with("/my%20notes.md")));
+        assert!(listed.get(field::NEXT_CURSOR).is_none());
+
+        let notes = file_uri(&root.join("my notes.md"));
+        let read_back = read(scope(&root), notes, CancelToken::new()).await.unwrap();
+        assert_eq!(read_back["contents"][0][field::TEXT], "# hi\n");
+        assert_eq!(read_back["contents"][0][field::MIME_TYPE], "text/markdown");
+
+        // Excluded and out-of-root files are not readable
+        for path in [root.join(".env"), root.join("node_modules/pkg/index.js"), dir.path().join("x")] {
+            let err = read(scope(&root), file_uri(&path), CancelToken::new()).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<Error>(), Some(Error::ResourceNotFound(_))));
+        }
+
+        let stats = read(scope(&root), FILE_STATS_URI.to_string(), CancelToken::new()).await.unwrap();
+        let text = stats["contents"][0][field::TEXT].as_str().unwrap();
+        assert!(serde_json::from_str::<Value>(text).unwrap()["total_files"].as_u64().unwrap() >= 2);
+
+        let escape = format!("{}/../..", FILE_STATS_URI);
+        assert!(read(scope(&root), escape, CancelToken::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        for i in 0..PAGE_SIZE + 20 {
+            fs::write(root.join(format!("src/f{i:04}.rs")), "").unwrap();
+        }
+
+        let first = list(scope(&root), None, CancelToken::new()).await.unwrap();
+        assert_eq!(first["resources"].as_array().unwrap().len(), PAGE_SIZE);
+        let cursor = first[field::NEXT_CURSOR].as_str().unwrap().to_string();
+        let second = list(scope(&root), Some(cursor), CancelToken::new()).await.unwrap();
+        let rest = second["resources"].as_array().unwrap();
+        // The artifact takes one place on the first page
+        assert_eq!(rest.len(), 21);
+        assert!(rest[20][field::URI].as_str().unwrap().ends_with("/src/f0519.rs"));
+        assert!(second.get(field::NEXT_CURSOR).is_none());
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(list(scope(&root), None, cancel).await.is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(&[0xff, 0x00, 0x80, 0x01]), "/wCAAQ==");
+    }
+}
@@ -0,0 +1,1284 @@
+//! JSON-RPC tool server
+//!
+//! Reads newline-delimited JSON-RPC messages and dispatches requests
+//! concurrently on the tokio runtime. Responses are written as soon as they
+//! are ready, so clients must match them to requests by `id`.
+//!
+//! Notifications and `initialize` are the exception: they are handled inline,
+//! in the order they were received, because the protocol requires lifecycle
+//! messages to take effect before the requests that follow them.
+//!
+//! Every request that carries an `id` gets exactly one response: either a
+//! result or a JSON-RPC error object (see [`crate::jsonrpc`]). Notifications never
+//! get a response, even when they fail.
+//!
+//! A `notifications/cancelled` message aborts the matching in-flight request.
+//! Its tool stops at the next cancellation check and, as the protocol asks,
+//! no response is sent for it.
+//!
+//! A `tools/call` whose `params._meta` carries a `progressToken` receives
+//! throttled `notifications/progress` messages while its tool runs.
+//! Files a tool changes are journaled under the call's `id` and session:
+//! `params._meta.sessionId` if the client sends one, so `undo_last` still
+//! finds them after a reconnect, otherwise an id made up per connection.
+//!
+//! `initialize` negotiates the protocol version: a version we support is
+//! echoed back, a newer one is answered with our latest, and one older than
+//! anything we support is rejected. Clients older than
+//! [`rpc::STRUCTURED_CONTENT_VERSION`] get neither `outputSchema` nor
+//! `structuredContent`. Tools disabled in config or the environment, or
+//! whose binary is missing on this host, are left out of `tools/list`.
+//!
+//! Resources (see [`crate::resources`]) are served from the working
+//! directory. Subscribed files are polled for changes every
+//! [`SUBSCRIPTION_POLL_INTERVAL`] and a `notifications/resources/updated`
+//! is sent when their modification time changes or they disappear.
+//!
+//! Lifecycle: `ping` answers `{}` immediately, even when every slot is busy.
+//! `shutdown` waits for in-flight requests (up to the shutdown timeout),
+//! answers `null` and rejects any later request; `exit`, EOF or a shutdown
+//! signal stop reading and drain the same way. Requests still running at
+//! the deadline are cancelled, which kills their child processes.
+
+use crate::jsonrpc::{self, RpcError};
+use crate::resources::{self, ResourceScope};
+use crate::tools;
+use crate::transport::{self, Listen};
+use anyhow::{Context, Result, bail};
+use orchestrator_core::config::load_config;
+use orchestrator_core::constants::{field, rpc, tool};
+use orchestrator_core::tools::{CancelToken, Progress, ProgressReporter, ToolContext};
+use serde_json::{Value, json};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Default number of requests allowed to execute at the same time
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// Default time to wait for in-flight requests when shutting down
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often subscribed resources are checked for changes
+pub const SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Environment variable that overrides the in-flight limit
+const MAX_IN_FLIGHT_ENV: &str = "ORCHESTRATOR_MAX_IN_FLIGHT";
+
+/// Environment variable listing tools to disable, comma separated
+const DISABLED_TOOLS_ENV: &str = "ORCHESTRATOR_DISABLED_TOOLS";
+
+/// Options for `orchestrator serve`
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// Maximum number of requests executing concurrently.
+    /// Requests over the limit are queued, not rejected.
+    /// With `--listen`, the limit applies to each connection.
+    pub max_in_flight: usize,
+    /// Transport to serve on
+    pub listen: Listen,
+    /// How long shutdown waits for running requests before cancelling them
+    pub shutdown_timeout: Duration,
+    /// Tools hidden from clients: disabled in config or the environment,
+    /// or unable to run on this host
+    pub disabled_tools: Vec<String>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            listen: Listen::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            disabled_tools: Vec::new(),
+        }
+    }
+}
+
+impl ServeOptions {
+    /// Parse options from the arguments following `serve`.
+    /// Command-line flags take precedence over the environment.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+
+        if let Ok(value) = env::var(MAX_IN_FLIGHT_ENV) {
+            options.max_in_flight = parse_limit(&value)?;
+        }
+        if let Ok(value) = env::var(DISABLED_TOOLS_ENV) {
+            options.disabled_tools.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from),
+            );
+        }
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--max-in-flight" => {
+                    let value = iter.next().context("--max-in-flight requires a value")?;
+                    options.max_in_flight = parse_limit(value)?;
+                }
+                "--shutdown-timeout" => {
+                    let value = iter.next().context("--shutdown-timeout requires a value")?;
+                    let secs: f64 = value
+                        .parse()
+                        .with_context(|| format!("Invalid shutdown timeout: {}", value))?;
+                    options.shutdown_timeout = Duration::try_from_secs_f64(secs)
+                        .with_context(|| format!("Invalid shutdown timeout: {}", value))?;
+                }
+                "--listen" => {
+                    let value = iter.next().context("--listen requires a value")?;
+                    options.listen = value.parse()?;
+                }
+                other => bail!("Unknown serve option: {}", other),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// What the client declared in `initialize`
+#[derive(Debug, Clone)]
+pub struct ClientSession {
+    /// Version both sides agreed on
+    pub protocol_version: &'static str,
+    pub capabilities: Value,
+    pub client_info: Value,
+}
+
+/// A subscribed file and the modification time last reported for it
+#[derive(Debug)]
+struct Subscription {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// State shared by every request on one connection
+#[derive(Debug, Default)]
+pub struct ServerState {
+    /// Cancellation tokens of requests that have not answered yet, keyed by id
+    pending: Mutex<HashMap<String, CancelToken>>,
+    /// Channel to the writer, for server-initiated notifications
+    outgoing: Option<mpsc::UnboundedSender<Value>>,
+    /// Set by `shutdown`; later requests are rejected
+    shutting_down: AtomicBool,
+    /// Tools that are neither listed nor callable
+    disabled_tools: HashSet<String>,
+    /// Set by `initialize`
+    session: Mutex<Option<ClientSession>>,
+    /// Project root for resources; the working directory when unset
+    root: Option<PathBuf>,
+    /// Subscribed resources, keyed by URI
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+    /// Session of tool calls that do not name one
+    connection_id: OnceLock<String>,
+}
+
+impl ServerState {
+    fn new(outgoing: mpsc::UnboundedSender<Value>) -> Self {
+        Self {
+            outgoing: Some(outgoing),
+            ..Self::default()
+        }
+    }
+
+    /// Hide `names` from `tools/list` and refuse to call them
+    fn with_disabled_tools(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.disabled_tools.extend(names);
+        self
+    }
+
+    fn is_disabled(&self, tool: &str) -> bool {
+        self.disabled_tools.contains(tool)
+    }
+
+    /// Serve resources from `root` instead of the working directory
+    #[cfg(test)]
+    fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    fn resource_scope(&self) -> Result<ResourceScope> {
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => env::current_dir()?,
+        };
+        Ok(ResourceScope {
+            root,
+            git_diff: !self.is_disabled(tool::GIT_DIFF),
+            file_stats: !self.is_disabled(tool::FILE_STATS),
+        })
+    }
+
+    fn subscribe(&self, uri: &str, path: PathBuf) {
+        let modified = modified_time(&path);
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .insert(uri.to_string(), Subscription { path, modified });
+    }
+
+    fn unsubscribe(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().remove(uri);
+    }
+
+    /// Send `notifications/resources/updated` for every subscribed file
+    /// that changed since the last poll
+    fn poll_subscriptions(&self) {
+        let Some(outgoing) = &self.outgoing else {
+            return;
+        };
+        for (uri, sub) in self.subscriptions.lock().unwrap().iter_mut() {
+            let modified = modified_time(&sub.path);
+            if modified == sub.modified {
+                continue;
+            }
+            sub.modified = modified;
+            let _ = outgoing.send(json!({
+                "jsonrpc": rpc::VERSION,
+                field::METHOD: rpc::NOTIFICATION_RESOURCE_UPDATED,
+                field::PARAMS: { field::URI: uri }
+            }));
+        }
+    }
+
+    /// Session a tool call's file changes are attributed to
+    fn session_id(&self, params: &Value) -> String {
+        if let Some(id) = params.get(field::META).and_then(|m| m.get(field::SESSION_ID)).and_then(Value::as_str) {
+            return id.to_string();
+        }
+        self.connection_id
+            .get_or_init(|| {
+                static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+                let started = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+                let n = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+                format!("connection-{}-{}-{}", started.as_millis(), std::process::id(), n)
+            })
+            .clone()
+    }
+
+    fn set_session(&self, session: ClientSession) {
+        *self.session.lock().unwrap() = Some(session);
+    }
+
+    /// Negotiated protocol version. Clients that skip `initialize` get the
+    /// latest one.
+    fn protocol_version(&self) -> &'static str {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(rpc::PROTOCOL_VERSION, |s| s.protocol_version)
+    }
+
+    fn supports_structured_content(&self) -> bool {
+        // Versions are ISO dates, so they order as strings
+        self.protocol_version() >= rpc::STRUCTURED_CONTENT_VERSION
+    }
+
+    /// Reporter that sends `notifications/progress` for `token`.
+    ///
+    /// MCP requires `progress` to increase with every notification, so it
+    /// counts files scanned plus matches found and unchanged snapshots are
+    /// skipped.
+    fn progress_reporter(&self, token: Value) -> ProgressReporter {
+        let Some(outgoing) = self.outgoing.clone() else {
+            return ProgressReporter::default();
+        };
+        let last = Mutex::new(0);
+        ProgressReporter::new(move |p: Progress| {
+            let work = p.files_scanned + p.matches;
+            let mut last = last.lock().unwrap();
+            if work <= *last {
+                return;
+            }
+            *last = work;
+            let _ = outgoing.send(json!({
+                "jsonrpc": rpc::VERSION,
+                field::METHOD: rpc::NOTIFICATION_PROGRESS,
+                field::PARAMS: {
+                    field::PROGRESS_TOKEN: token,
+                    "progress": work,
+                    "message": format!(
+                        "{} files scanned, {} matches, {}ms",
+                        p.files_scanned, p.matches, p.elapsed_ms
+                    ),
+                    "filesScanned": p.files_scanned,
+                    "matches": p.matches,
+                    "elapsedMs": p.elapsed_ms
+                }
+            }));
+        })
+    }
+
+    /// Track a request so that it can be cancelled later. Returns `None`
+    /// if a request with the same id is still in flight.
+    fn register(&self, id: &Value) -> Option<CancelToken> {
+        match self.pending.lock().unwrap().entry(request_key(id)) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => Some(entry.insert(CancelToken::new()).clone()),
+        }
+    }
+
+    fn complete(&self, id: &Value) {
+        self.pending.lock().unwrap().remove(&request_key(id));
+    }
+
+    /// Cancel every in-flight request
+    fn cancel_all(&self) {
+        for token in self.pending.lock().unwrap().values() {
+            token.cancel();
+        }
+    }
+
+    fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Cancel an in-flight request. Returns false if it already finished.
+    fn cancel(&self, id: &Value) -> bool {
+        match self.pending.lock().unwrap().get(&request_key(id)) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+/// Poll subscriptions until the connection's state is dropped
+async fn watch_subscriptions(state: Weak<ServerState>) {
+    let mut interval = tokio::time::interval(SUBSCRIPTION_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        match state.upgrade() {
+            Some(state) => state.poll_subscriptions(),
+            None => return,
+        }
+    }
+}
+
+/// Ids may be numbers or strings; their JSON text is unique across both
+fn request_key(id: &Value) -> String {
+    id.to_string()
+}
+
+fn parse_limit(value: &str) -> Result<usize> {
+    let limit: usize = value
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid in-flight limit: {}", value))?;
+    if limit == 0 {
+        bail!("In-flight limit must be at least 1");
+    }
+    Ok(limit)
+}
+
+/// Pick the version to answer `initialize` with.
+///
+/// A supported version is echoed back. A newer, unknown one is answered
+/// with our latest and the client decides whether to continue; one older
+/// than anything we support is rejected.
+fn negotiate_version(requested: Option<&Value>) -> std::result::Result<&'static str, RpcError> {
+    let Some(requested) = requested else {
+        return Ok(rpc::PROTOCOL_VERSION);
+    };
+    let requested = requested
+        .as_str()
+        .ok_or_else(|| RpcError::invalid_params("'protocolVersion' must be a string"))?;
+    if let Some(version) = rpc::SUPPORTED_PROTOCOL_VERSIONS.iter().find(|v| **v == requested) {
+        return Ok(version);
+    }
+    if requested > rpc::PROTOCOL_VERSION {
+        return Ok(rpc::PROTOCOL_VERSION);
+    }
+    Err(
+        RpcError::invalid_params(format!("Unsupported protocol version: {}", requested))
+            .with_data(json!({
+                "supported": rpc::SUPPORTED_PROTOCOL_VERSIONS,
+                "requested": requested
+            })),
+    )
+}
+
+/// Serve: Run tool server on stdio or the `--listen` transport
+pub async fn serve(mut options: ServeOptions) -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
+
+    match load_config(&env::current_dir()?) {
+        Ok(config) => options.disabled_tools.extend(config.disabled_tools),
+        Err(e) => warn!("Ignoring config: {}", e),
+    }
+    for name in &options.disabled_tools {
+        if !tools::registry().contains(name) {
+            warn!("Cannot disable unknown tool: {}", name);
+        }
+    }
+    let unavailable = tools::registry().unavailable();
+    if !unavailable.is_empty() {
+        info!("Not available on this host: {}", unavailable.join(", "));
+        options.disabled_tools.extend(unavailable.into_iter().map(String::from));
+    }
+
+    info!(
+        "OpenCode Orchestrator starting on {} (max in-flight: {})",
+        options.listen, options.max_in_flight
+    );
+
+    transport::listen(options).await
+}
+
+/// Run the request loop over a line-oriented reader and writer.
+///
+/// Returns once the reader hits EOF, an `exit` notification arrives or
+/// `shutdown` resolves, and in-flight requests have drained.
+pub async fn run<R, W, S>(reader: R, writer: W, options: ServeOptions, shutdown: S) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+    S: Future<Output = ()>,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    let writer_task = tokio::spawn(write_responses(writer, rx));
+    let limit = Arc::new(Semaphore::new(options.max_in_flight));
+    let state = Arc::new(
+        ServerState::new(tx.clone()).with_disabled_tools(options.disabled_tools.iter().cloned()),
+    );
+    let watcher = tokio::spawn(watch_subscriptions(Arc::downgrade(&state)));
+    let mut in_flight = JoinSet::new();
+    let mut lines = reader.lines();
+    tokio::pin!(shutdown);
+
+    loop {
+        let next = tokio::select! {
+            _ = &mut shutdown => {
+                info!("Shutdown signal received");
+                break;
+            }
+            next = lines.next_line() => next,
+        };
+        let line = match next {
+            Ok(Some(l)) => l,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                error!("Read error: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // Reap finished requests so the set does not grow unbounded
+        while let Some(joined) = in_flight.try_join_next() {
+            log_join_error(joined);
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        debug!("Received: {}", line);
+
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Parse error: {}", e);
+                // The id cannot be recovered from malformed JSON, so the
+                // spec requires answering with a null id.
+                let _ = tx.send(jsonrpc::failure(Value::Null, RpcError::parse_error(e.to_string())));
+                continue;
+            }
+        };
+
+        if is_ordered(&request) {
+            match request.get(field::METHOD).and_then(Value::as_str) {
+                Some(rpc::EXIT) => break,
+                Some(rpc::SHUTDOWN) => {
+                    state.begin_shutdown();
+                    drain(&mut in_flight, &state, options.shutdown_timeout).await;
+                }
+                _ => {}
+            }
+            if let Some(resp) = handle_request(&state, &request, &CancelToken::new()).await {
+                let _ = tx.send(resp);
+            }
+            continue;
+        }
+
+        // Register before spawning so a cancellation read on the next line
+        // always finds the request.
+        let id = request[field::ID].clone();
+        let Some(cancel) = state.register(&id) else {
+            // Cancelling or completing by id must reach exactly one request
+            let error = RpcError::invalid_request(format!("Request id {} is already in flight", id));
+            let _ = tx.send(jsonrpc::failure(id, error));
+            continue;
+        };
+        let tx = tx.clone();
+        let limit = Arc::clone(&limit);
+        let state = Arc::clone(&state);
+        in_flight.spawn(async move {
+            let resp = async {
+                // Acquire inside the task so the reader keeps draining stdin
+                // (and sees notifications) while the limit is saturated.
+                let _permit = limit.acquire_owned().await.ok()?;
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                handle_request(&state, &request, &cancel).await
+            }
+            .await;
+            state.complete(&id);
+
+            if cancel.is_cancelled() {
+                debug!("Request {} cancelled, dropping response", id);
+                return None;
+            }
+            tx.send(resp?).ok()
+        });
+    }
+
+    drain(&mut in_flight, &state, options.shutdown_timeout).await;
+    watcher.abort();
+    let _ = watcher.await;
+
+    // The writer finishes once every sender, including the one held by
+    // the state for notifications, is gone
+    drop(state);
+    drop(tx);
+    writer_task.await?
+}
+
+/// Whether a message must be handled before the next one is read.
+fn is_ordered(request: &Value) -> bool {
+    request.get(field::ID).is_none()
+        || matches!(
+            request.get(field::METHOD).and_then(Value::as_str),
+            Some(rpc::INITIALIZE | rpc::PING | rpc::SHUTDOWN)
+        )
+}
+
+/// Wait for in-flight requests, cancelling whatever is left at the deadline
+async fn drain(in_flight: &mut JoinSet<Option<()>>, state: &ServerState, timeout: Duration) {
+    if in_flight.is_empty() {
+        return;
+    }
+    info!("Waiting up to {:?} for {} request(s)", timeout, in_flight.len());
+
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            joined = in_flight.join_next() => match joined {
+                Some(joined) => log_join_error(joined),
+                None => return,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+
+    // Cancelled tools stop at their next check and kill their children
+    warn!("Cancelling {} request(s) still running after {:?}", in_flight.len(), timeout);
+    state.cancel_all();
+    while let Some(joined) = in_flight.join_next().await {
+        log_join_error(joined);
+    }
+}
+
+fn log_join_error<T>(joined: std::result::Result<T, tokio::task::JoinError>) {
+    if let Err(e) = joined {
+        error!("Request task failed: {}", e);
+    }
+}
+
+/// Serialize responses one per line, in completion order
+async fn write_responses<W>(mut writer: W, mut rx: mpsc::UnboundedReceiver<Value>) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    while let Some(resp) = rx.recv().await {
+        let mut resp_str = serde_json::to_string(&resp)?;
+        debug!("Sending: {}", resp_str);
+        resp_str.push('\n');
+        writer.write_all(resp_str.as_bytes()).await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Handle a JSON-RPC message, returning the response to send (if any)
+pub async fn handle_request(
+    state: &ServerState,
+    request: &Value,
+    cancel: &CancelToken,
+) -> Option<Value> {
+    let id = request.get(field::ID).cloned();
+
+    // Responses to server-initiated requests are not dispatched
+    if request.get(field::METHOD).is_none()
+        && (request.get(field::RESULT).is_some() || request.get(field::ERROR).is_some())
+    {
+        debug!("Ignoring response message");
+        return None;
+    }
+
+    let outcome = match validate(request) {
+        Ok(name) => dispatch(state, name, request, cancel).await,
+        // Invalid requests are answered even without an id (spec: null id)
+        Err(e) => return Some(jsonrpc::failure(id.unwrap_or(Value::Null), e)),
+    };
+
+    match (id, outcome) {
+        (Some(id), Ok(result)) => Some(jsonrpc::success(id, result)),
+        (Some(id), Err(e)) => Some(jsonrpc::failure(id, e)),
+        (None, Err(e)) => {
+            debug!("Notification failed: {}", e.message);
+            None
+        }
+        (None, Ok(_)) => None,
+    }
+}
+
+/// Check the envelope and return the method name
+fn validate(request: &Value) -> std::result::Result<&str, RpcError> {
+    if !request.is_object() {
+        return Err(RpcError::invalid_request("Request must be a JSON object"));
+    }
+    if let Some(id) = request.get(field::ID)
+        && !(id.is_string() || id.is_number() || id.is_null())
+    {
+        return Err(RpcError::invalid_request("'id' must be a string, number or null"));
+    }
+    request
+        .get(field::METHOD)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_request("Missing or non-string 'method'"))
+}
+
+async fn dispatch(
+    state: &ServerState,
+    name: &str,
+    request: &Value,
+    cancel: &CancelToken,
+) -> std::result::Result<Value, RpcError> {
+    if state.is_shutting_down()
+        && !matches!(name, rpc::PING | rpc::SHUTDOWN | rpc::EXIT | rpc::NOTIFICATION_CANCELLED)
+    {
+        return Err(RpcError::invalid_request("Server is shutting down"));
+    }
+
+    let result = match name {
+        rpc::INITIALIZE => {
+            let params = request.get(field::PARAMS);
+            let param = |key: &str| params.and_then(|p| p.get(key));
+            let version = negotiate_version(param("protocolVersion"))?;
+            let session = ClientSession {
+                protocol_version: version,
+                capabilities: param("capabilities").cloned().unwrap_or(json!({})),
+                client_info: param("clientInfo").cloned().unwrap_or(Value::Null),
+            };
+            let client = session.client_info.get("name").and_then(Value::as_str).unwrap_or("unknown");
+            info!("Client {} initialized with protocol {}", client, version);
+            debug!("Client capabilities: {}", session.capabilities);
+            state.set_session(session);
+
+            json!({
+                "protocolVersion": version,
+                "serverInfo": {
+                    "name": "orchestrator",
+                    "version": env!("CARGO_PKG_VERSION")
+                },
+                "capabilities": {
+                    "tools": { "listChanged": false },
+                    "resources": { "subscribe": true, "listChanged": false },
+                    "experimental": {
+                        "orchestrator": {
+                            "progress": true,
+                            "cancellation": true,
+                            "shutdown": true
+                        }
+                    }
+                }
+            })
+        }
+        rpc::PING => json!({}),
+        rpc::SHUTDOWN => {
+            state.begin_shutdown();
+            Value::Null
+        }
+        rpc::EXIT => Value::Null,
+        rpc::TOOLS_LIST => {
+            let structured = state.supports_structured_content();
+            let tools = tools::registry()
+                .definitions()
+                .into_iter()
+                .filter(|def| !state.is_disabled(def.name))
+                .map(|def| {
+                    let mut def = serde_json::to_value(def)?;
+                    if !structured && let Some(def) = def.as_object_mut() {
+                        def.remove(field::OUTPUT_SCHEMA);
+                    }
+                    Ok(def)
+                })
+                .collect::<serde_json::Result<Vec<_>>>()
+                .map_err(anyhow::Error::from)?;
+            json!({ "tools": tools })
+        }
+        rpc::TOOLS_CALL => {
+            let params = request
+                .get(field::PARAMS)
+                .ok_or_else(|| RpcError::invalid_params("Missing 'params'"))?;
+            let tool_name = params
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::invalid_params("Missing or non-string 'params.name'"))?;
+            let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+            if state.is_disabled(tool_name) {
+                let err = orchestrator_core::Error::ToolUnavailable(tool_name.to_string());
+                return Err(anyhow::Error::from(err).into());
+            }
+
+            let request_id = request.get(field::ID).map(|id| id.as_str().map_or_else(|| id.to_string(), String::from));
+            let mut ctx = ToolContext::new(cancel.clone()).with_origin(Some(state.session_id(params)), request_id);
+            if let Some(token) = params.get(field::META).and_then(|m| m.get(field::PROGRESS_TOKEN)) {
+                ctx = ctx.with_progress(state.progress_reporter(token.clone()));
+            }
+
+            let result = tools::execute_tool(tool_name, arguments, ctx).await?;
+            // Older clients only read the text item, so it carries the same JSON
+            let text = serde_json::to_string_pretty(&result).map_err(anyhow::Error::from)?;
+            let mut response = json!({
+                field::CONTENT: [{
+                    field::TYPE: field::TEXT,
+                    field::TEXT: text
+                }]
+            });
+            if state.supports_structured_content() {
+                response[field::STRUCTURED_CONTENT] = result;
+            }
+            response
+        }
+        rpc::RESOURCES_LIST => {
+            let cursor = request
+                .get(field::PARAMS)
+                .and_then(|p| p.get(field::CURSOR))
+                .and_then(Value::as_str)
+                .map(String::from);
+            resources::list(state.resource_scope()?, cursor, cancel.clone()).await?
+        }
+        rpc::RESOURCES_TEMPLATES_LIST => resources::templates(&state.resource_scope()?),
+        rpc::RESOURCES_READ => {
+            let uri = resource_uri(request)?;
+            resources::read(state.resource_scope()?, uri.to_string(), cancel.clone()).await?
+        }
+        rpc::RESOURCES_SUBSCRIBE => {
+            let uri = resource_uri(request)?;
+            let path = resources::subscription_path(&state.resource_scope()?, uri)
+                .map_err(anyhow::Error::from)?;
+            state.subscribe(uri, path);
+            json!({})
+        }
+        rpc::RESOURCES_UNSUBSCRIBE => {
+            state.unsubscribe(resource_uri(request)?);
+            json!({})
+        }
+        rpc::NOTIFICATION_CANCELLED => {
+            if let Some(target) = request.get(field::PARAMS).and_then(|p| p.get("requestId")) {
+                if state.cancel(target) {
+                    info!("Cancelling request {}", target);
+                } else {
+                    debug!("Cancel for unknown or finished request {}", target);
+                }
+            }
+            Value::Null
+        }
+        _ => {
+            debug!("Unknown method: {}", name);
+            return Err(RpcError::method_not_found(name));
+        }
+    };
+
+    Ok(result)
+}
+
+fn resource_uri(request: &Value) -> std::result::Result<&str, RpcError> {
+    request
+        .get(field::PARAMS)
+        .and_then(|p| p.get(field::URI))
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params("Missing or non-string 'params.uri'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orchestrator_core::constants::error_code;
+    use serde_json::json;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn test_handle_initialize() {
+        let req = json!({
+            "jsonrpc": rpc::VERSION,
+            field::ID: 1,
+            field::METHOD: rpc::INITIALIZE
+        });
+        let resp = handle_request(&ServerState::default(), &req, &CancelToken::new()).await.unwrap();
+        assert_eq!(resp[field::RESULT]["serverInfo"]["name"], "orchestrator");
+        assert_eq!(resp[field::RESULT]["protocolVersion"], rpc::PROTOCOL_VERSION);
+        assert_eq!(resp[field::RESULT]["capabilities"]["experimental"]["orchestrator"]["progress"], true);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_negotiates_version() {
+        let initialize = |version: Value| {
+            json!({
+                "jsonrpc": rpc::VERSION,
+                field::ID: 1,
+                field::METHOD: rpc::INITIALIZE,
+                field::PARAMS: {
+                    "protocolVersion": version,
+                    "capabilities": {},
+                    "clientInfo": {"name": "test", "version": "1"}
+                }
+            })
+        };
+        let state = ServerState::default();
+        let call = |req: Value| {
+            let state = &state;
+            async move { handle_request(state, &req, &CancelToken::new()).await.unwrap() }
+        };
+
+        let resp = call(initialize(json!("2099-01-01"))).await;
+        assert_eq!(resp[field::RESULT]["protocolVersion"], rpc::PROTOCOL_VERSION);
+
+        let resp = call(initialize(json!("2000-01-01"))).await;
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+        assert_eq!(resp[field::ERROR][field::DATA]["supported"][0], rpc::PROTOCOL_VERSION);
+
+        let resp = call(initialize(json!(20241105))).await;
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+
+        // An older client keeps its version and gets text-only results
+        let resp = call(initialize(json!("2024-11-05"))).await;
+        assert_eq!(resp[field::RESULT]["protocolVersion"], "2024-11-05");
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 2, field::METHOD: rpc::TOOLS_LIST})).await;
+        let tools = resp[field::RESULT]["tools"].as_array().unwrap();
+        assert!(tools.iter().all(|t| t.get(field::OUTPUT_SCHEMA).is_none()));
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 3, field::METHOD: rpc::TOOLS_CALL,
+                               field::PARAMS: {"name": tool::LIST_HOOKS}}))
+            .await;
+        assert!(resp[field::RESULT].get(field::STRUCTURED_CONTENT).is_none());
+        assert!(resp[field::RESULT][field::CONTENT][0][field::TEXT].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tools_are_hidden() {
+        let state = ServerState::default().with_disabled_tools([tool::HTTP.to_string()]);
+
+        let req = json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: rpc::TOOLS_LIST});
+        let resp = handle_request(&state, &req, &CancelToken::new()).await.unwrap();
+        let tools = resp[field::RESULT]["tools"].as_array().unwrap();
+        assert!(!tools.iter().any(|t| t["name"] == tool::HTTP));
+        assert!(tools.iter().any(|t| t["name"] == tool::GREP_SEARCH));
+
+        let req = json!({"jsonrpc": rpc::VERSION, field::ID: 2, field::METHOD: rpc::TOOLS_CALL,
+                         field::PARAMS: {"name": tool::HTTP, "arguments": {"url": "http://localhost"}}});
+        let resp = handle_request(&state, &req, &CancelToken::new()).await.unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+        assert_eq!(resp[field::ERROR][field::DATA]["kind"], "tool_unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_handle_tools_list() {
+        let req = json!({
+            "jsonrpc": rpc::VERSION,
+            field::ID: 1,
+            field::METHOD: rpc::TOOLS_LIST
+        });
+        let resp = handle_request(&ServerState::default(), &req, &CancelToken::new()).await.unwrap();
+        let tools = resp[field::RESULT]["tools"].as_array().unwrap();
+        let names = [
+            tool::GREP_SEARCH, tool::GLOB_SEARCH, tool::MGREP, tool::SED_REPLACE, tool::APPLY_PATCH, tool::UNDO_CHANGESET,
+            tool::UNDO_LAST, tool::LIST_CHANGESETS, tool::DIFF, tool::MERGE3, tool::JQ, tool::HTTP, tool::FILE_STATS, tool::INDEX, tool::GIT_DIFF, tool::GIT_STATUS,
+            tool::LSP_DIAGNOSTICS, tool::AST_SEARCH, tool::AST_REPLACE, tool::LIST_AGENTS,
+            tool::LIST_HOOKS,
+        ];
+        assert_eq!(tools.len(), names.len());
+        for name in names {
+            assert!(tools.iter().any(|t| t["name"] == name), "{} not listed", name);
+        }
+        assert!(tools.iter().all(|t| t["inputSchema"]["type"] == "object"));
+        assert!(tools.iter().all(|t| t[field::OUTPUT_SCHEMA]["type"] == "object"));
+
+        let diff = tools.iter().find(|t| t["name"] == tool::DIFF).unwrap();
+        assert!(diff["inputSchema"]["properties"]["ignore_whitespace"].is_object());
+
+        for name in [tool::GREP_SEARCH, tool::MGREP, tool::GLOB_SEARCH, tool::SED_REPLACE] {
+            let properties = &tools.iter().find(|t| t["name"] == name).unwrap()["inputSchema"]["properties"];
+            for filter in ["include", "exclude", "types", "exclude_types"] {
+                assert!(properties[filter].is_object(), "{} lacks {}", name, filter);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_tools_call_structured() {
+        let req = json!({
+            "jsonrpc": rpc::VERSION,
+            field::ID: 1,
+            field::METHOD: rpc::TOOLS_CALL,
+            field::PARAMS: {
+                "name": tool::DIFF,
+                "arguments": {"content1": "a\n", "content2": "a\n"}
+            }
+        });
+        let resp = handle_request(&ServerState::default(), &req, &CancelToken::new()).await.unwrap();
+        let result = &resp[field::RESULT];
+        assert_eq!(result[field::STRUCTURED_CONTENT]["has_differences"], false);
+
+        let text = result[field::CONTENT][0][field::TEXT].as_str().unwrap();
+        let parsed: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(parsed, result[field::STRUCTURED_CONTENT]);
+    }
+
+    #[tokio::test]
+    async fn test_undo_outside_the_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let file = dir.path().join("src/a.txt");
+        std::fs::write(&file, "old\n").unwrap();
+        let state = ServerState::default();
+        let call = |name: &str, arguments: Value| {
+            let req = json!({
+                "jsonrpc": rpc::VERSION,
+                field::ID: 1,
+                field::METHOD: rpc::TOOLS_CALL,
+                field::PARAMS: {"name": name, "arguments": arguments}
+            });
+            let state = &state;
+            async move { handle_request(state, &req, &CancelToken::new()).await.unwrap()[field::RESULT][field::STRUCTURED_CONTENT].clone() }
+        };
+
+        let edited = call(tool::SED_REPLACE, json!({"pattern": "old", "replacement": "new", "file": file})).await;
+        let id = edited["changeset_id"].as_str().unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "new\n");
+
+        // The journal belongs to the edited project, found from any path in it
+        let listed = call(tool::LIST_CHANGESETS, json!({"directory": dir.path().join("src"), "all_sessions": true})).await;
+        assert_eq!(listed["changesets"][0]["id"], id);
+        let undone = call(tool::UNDO_CHANGESET, json!({"changeset_id": id, "directory": dir.path()})).await;
+        assert_eq!(undone["changeset_id"], id);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "old\n");
+    }
+
+    #[tokio::test]
+    async fn test_handle_tools_call_unknown() {
+        let req = json!({
+            "jsonrpc": rpc::VERSION,
+            field::ID: 1,
+            field::METHOD: rpc::TOOLS_CALL,
+            field::PARAMS: {
+                "name": "non_existent_tool",
+                "arguments": {}
+            }
+        });
+        let resp = handle_request(&ServerState::default(), &req, &CancelToken::new()).await.unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+        assert!(resp[field::ERROR][field::MESSAGE].as_str().unwrap().contains("Unknown tool"));
+        assert_eq!(resp[field::ERROR][field::DATA]["kind"], "unknown_tool");
+    }
+
+    #[tokio::test]
+    async fn test_handle_protocol_errors() {
+        let state = ServerState::default();
+        let call = |req: Value| {
+            let state = &state;
+            async move { handle_request(state, &req, &CancelToken::new()).await }
+        };
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: "bogus"})).await.unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::METHOD_NOT_FOUND);
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 2})).await.unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_REQUEST);
+        assert_eq!(resp[field::ID], 2);
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 3, field::METHOD: rpc::TOOLS_CALL, field::PARAMS: {}}))
+            .await
+            .unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 4, field::METHOD: rpc::TOOLS_CALL,
+                               field::PARAMS: {"name": tool::GREP_SEARCH, "arguments": {"pattern": 5}}}))
+            .await
+            .unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+
+        let resp = call(json!({"jsonrpc": rpc::VERSION, field::ID: 5, field::METHOD: rpc::TOOLS_CALL,
+                               field::PARAMS: {"name": tool::GREP_SEARCH, "arguments": {"pattern": "("}}}))
+            .await
+            .unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::REGEX);
+
+        // Unknown notifications are dropped without a response
+        assert!(call(json!({"jsonrpc": rpc::VERSION, field::METHOD: "notifications/bogus"})).await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_answers_every_request() {
+        let input = [
+            json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: rpc::INITIALIZE}),
+            json!({"jsonrpc": rpc::VERSION, field::METHOD: "notifications/initialized"}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 2, field::METHOD: rpc::TOOLS_LIST}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 3, field::METHOD: rpc::TOOLS_CALL,
+                   field::PARAMS: {"name": tool::LIST_AGENTS}}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 4, field::METHOD: rpc::TOOLS_CALL,
+                   field::PARAMS: {"name": tool::LIST_HOOKS}}),
+        ]
+        .iter()
+        .map(|v| format!("{}\n", v))
+        .chain(std::iter::once("{not json\n".to_string()))
+        .collect::<String>();
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let options = ServeOptions { max_in_flight: 2, ..ServeOptions::default() };
+        run(BufReader::new(input.as_bytes()), server, options, std::future::pending()).await.unwrap();
+
+        let mut output = String::new();
+        let mut reader = BufReader::new(client);
+        while reader.read_line(&mut output).await.unwrap() > 0 {}
+
+        let responses: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let mut ids: Vec<u64> = responses.iter().filter_map(|r| r[field::ID].as_u64()).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let parse_error = responses.iter().find(|r| r[field::ID].is_null()).unwrap();
+        assert_eq!(parse_error[field::ERROR][field::CODE], error_code::PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_and_exit() {
+        let input = [
+            json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: rpc::PING}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 2, field::METHOD: rpc::SHUTDOWN}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 3, field::METHOD: rpc::TOOLS_LIST}),
+            json!({"jsonrpc": rpc::VERSION, field::METHOD: rpc::EXIT}),
+            json!({"jsonrpc": rpc::VERSION, field::ID: 4, field::METHOD: rpc::TOOLS_LIST}),
+        ]
+        .iter()
+        .map(|v| format!("{}\n", v))
+        .collect::<String>();
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        run(BufReader::new(input.as_bytes()), server, ServeOptions::default(), std::future::pending())
+            .await
+            .unwrap();
+
+        let mut output = String::new();
+        let mut reader = BufReader::new(client);
+        while reader.read_line(&mut output).await.unwrap() > 0 {}
+        let responses: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+
+        // Lifecycle messages are handled in order, and nothing is read after `exit`
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0][field::RESULT], json!({}));
+        assert_eq!(responses[1][field::ID], 2);
+        assert!(responses[1][field::RESULT].is_null());
+        assert_eq!(responses[2][field::ERROR][field::CODE], error_code::INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_drain_cancels_after_deadline() {
+        let state = ServerState::default();
+        let token = state.register(&json!(1)).unwrap();
+        let mut in_flight = JoinSet::new();
+        let running = token.clone();
+        in_flight.spawn(async move {
+            while !running.is_cancelled() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            None
+        });
+
+        drain(&mut in_flight, &state, Duration::from_millis(20)).await;
+        assert!(token.is_cancelled());
+        assert!(in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_notification() {
+        let state = ServerState::default();
+        let token = state.register(&json!("abc")).unwrap();
+
+        let req = json!({
+            "jsonrpc": rpc::VERSION,
+            field::METHOD: rpc::NOTIFICATION_CANCELLED,
+            field::PARAMS: {"requestId": "abc", "reason": "abandoned"}
+        });
+        assert!(handle_request(&state, &req, &CancelToken::new()).await.is_none());
+        assert!(token.is_cancelled());
+
+        state.complete(&json!("abc"));
+        assert!(!state.cancel(&json!("abc")));
+    }
+
+    #[test]
+    fn test_duplicate_request_ids() {
+        let state = ServerState::default();
+        let first = state.register(&json!(7)).unwrap();
+
+        // A second request with the same id is refused, not swapped in
+        assert!(state.register(&json!(7)).is_none());
+        assert!(state.register(&json!("7")).is_some());
+        assert!(state.cancel(&json!(7)));
+        assert!(first.is_cancelled());
+
+        state.complete(&json!(7));
+        assert!(state.register(&json!(7)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_tool_call() {
+        let dir = std::env::temp_dir();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        let err = tools::execute_tool(
+            tool::GREP_SEARCH,
+            json!({"pattern": "x", "directory": dir}),
+            ToolContext::new(cancel),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<orchestrator_core::Error>(),
+            Some(orchestrator_core::Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_progress_notifications() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let state = ServerState::new(tx);
+        let progress = state.progress_reporter(json!("tok"));
+
+        progress.file_scanned();
+        progress.matches_found(2);
+        std::thread::sleep(orchestrator_core::tools::progress::DEFAULT_PROGRESS_INTERVAL);
+        progress.tick();
+        // Nothing new since the last report, so nothing is sent
+        std::thread::sleep(orchestrator_core::tools::progress::DEFAULT_PROGRESS_INTERVAL);
+        progress.tick();
+
+        let note = rx.try_recv().unwrap();
+        assert_eq!(note[field::METHOD], rpc::NOTIFICATION_PROGRESS);
+        assert_eq!(note[field::PARAMS][field::PROGRESS_TOKEN], "tok");
+        assert_eq!(note[field::PARAMS]["progress"], 3);
+        assert_eq!(note[field::PARAMS]["filesScanned"], 1);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resource_subscriptions() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir(&root).unwrap();
+        let file = root.join("a.txt");
+        std::fs::write(&file, "one").unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let state = ServerState::new(tx).with_root(root.clone());
+        let uri = format!("file://{}", root.canonicalize().unwrap().join("a.txt").display());
+        let subscribe = |uri: &str| {
+            json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: rpc::RESOURCES_SUBSCRIBE,
+                   field::PARAMS: {field::URI: uri}})
+        };
+
+        let resp = handle_request(&state, &subscribe(&uri), &CancelToken::new()).await.unwrap();
+        assert_eq!(resp[field::RESULT], json!({}));
+        let resp = handle_request(&state, &subscribe("orchestrator://git/diff"), &CancelToken::new()).await.unwrap();
+        assert_eq!(resp[field::ERROR][field::CODE], error_code::INVALID_PARAMS);
+
+        state.poll_subscriptions();
+        assert!(rx.try_recv().is_err());
+
+        std::fs::remove_file(&file).unwrap();
+        state.poll_subscriptions();
+        let note = rx.try_recv().unwrap();
+        assert_eq!(note[field::METHOD], rpc::NOTIFICATION_RESOURCE_UPDATED);
+        assert_eq!(note[field::PARAMS][field::URI], uri);
+
+        state.unsubscribe(&uri);
+        std::fs::write(&file, "two").unwrap();
+        state.poll_subscriptions();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_ids() {
+        let state = ServerState::default();
+        let named = json!({ field::META: { field::SESSION_ID: "worker-3" } });
+        assert_eq!(state.session_id(&named), "worker-3");
+
+        // Calls without one share an id per connection
+        let connection = state.session_id(&json!({}));
+        assert_eq!(state.session_id(&json!({})), connection);
+        assert_ne!(ServerState::default().session_id(&json!({})), connection);
+    }
+
+    #[test]
+    fn test_serve_options_from_args() {
+        let args = vec!["--max-in-flight".to_string(), "4".to_string()];
+        assert_eq!(ServeOptions::from_args(&args).unwrap().max_in_flight, 4);
+
+        let zero = vec!["--max-in-flight".to_string(), "0".to_string()];
+        assert!(ServeOptions::from_args(&zero).is_err());
+
+        let listen = vec!["--listen".to_string(), "tcp:127.0.0.1:0".to_string()];
+        assert_eq!(
+            ServeOptions::from_args(&listen).unwrap().listen,
+            Listen::Tcp("127.0.0.1:0".to_string())
+        );
+
+        let timeout = vec!["--shutdown-timeout".to_string(), "2.5".to_string()];
+        assert_eq!(
+            ServeOptions::from_args(&timeout).unwrap().shutdown_timeout,
+            Duration::from_millis(2500)
+        );
+
+        let unknown = vec!["--bogus".to_string()];
+        assert!(ServeOptions::from_args(&unknown).is_err());
+    }
+}
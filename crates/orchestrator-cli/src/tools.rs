@@ -1,632 +1,1950 @@
 //! Tool implementations for the orchestrator CLI
+//!
+//! Each tool is a [`Tool`] with typed arguments and a typed output struct;
+//! [`registry`] collects them and drives both `tools/list` and `tools/call`.
 
-use anyhow::Result;
 use orchestrator_core::hooks::Hook;
 use orchestrator_core::tools::{
-    GlobTool, GrepTool, MgrepTool, SedTool, DiffTool, JqTool, HttpTool, FileStatsTool, GitTool,
-    DiagnosticsTool, AstTool,
-    glob::GlobConfig, grep::GrepConfig, mgrep::MgrepConfig, sed::SedConfig,
-    diff::DiffConfig, jq::JqConfig, http::HttpConfig,
-    lsp::DiagnosticsConfig, ast::AstConfig,
+    GlobTool, GrepTool, MgrepTool, SedTool, PatchTool, DiffTool, MergeTool, JqTool, HttpTool, FileStatsTool, GitTool,
+    DiagnosticsTool, AstTool, Tool, ToolContext, ToolRegistry, WalkConfig, command_exists,
+    IndexUsage, TrigramIndex, ChangeSet, changeset::JOURNAL_DIR,
+    glob::GlobConfig, grep::{self, GrepConfig, GrepMatch}, mgrep::{MgrepConfig, MgrepMatch, MgrepQuery}, sed::{self, SedConfig},
+    patch::{HunkResult, PatchConfig},
+    diff::{Algorithm, DiffConfig, DiffFormat, Hunk, InlineDiff}, merge::{MergeConfig, MergeConflict}, jq::JqConfig, http::HttpConfig,
+    lsp::{DiagnosticSeverity, DiagnosticsConfig}, ast::AstConfig, index::INDEX_FILE,
 };
 
 use orchestrator_core::constants::{tool, status};
-use serde::Deserialize;
-use serde_json::{Value, json};
-use std::path::PathBuf;
+use orchestrator_core::{Error, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::Duration;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// All tools served over MCP, in `tools/list` order
+pub fn registry() -> &'static ToolRegistry {
+    static REGISTRY: OnceLock<ToolRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = ToolRegistry::new();
+        registry
+            .register(GrepSearch)
+            .register(GlobSearch)
+            .register(Mgrep)
+            .register(SedReplace)
+            .register(ApplyPatch)
+            .register(UndoChangeset)
+            .register(UndoLast)
+            .register(ListChangesets)
+            .register(Diff)
+            .register(Merge3)
+            .register(Jq)
+            .register(Http)
+            .register(FileStats)
+            .register(Index)
+            .register(GitDiff)
+            .register(GitStatus)
+            .register(LspDiagnostics)
+            .register(AstSearch)
+            .register(AstReplace)
+            .register(ListAgents)
+            .register(ListHooks);
+        registry
+    })
+}
 
 /// Execute a tool by name
-pub async fn execute_tool(name: &str, arguments: Value) -> Result<String> {
-    match name {
-        tool::GREP_SEARCH => grep_search(arguments).await,
-        tool::GLOB_SEARCH => glob_search(arguments).await,
-        tool::MGREP => mgrep(arguments).await,
-        tool::SED_REPLACE => sed_replace(arguments).await,
-        tool::DIFF => diff_files(arguments).await,
-        tool::JQ => jq_query(arguments).await,
-        tool::HTTP => http_request(arguments).await,
-        tool::FILE_STATS => file_stats(arguments).await,
-        tool::GIT_DIFF => git_diff(arguments).await,
-        tool::GIT_STATUS => git_status(arguments).await,
-        tool::LSP_DIAGNOSTICS => lsp_diagnostics(arguments).await,
-        tool::AST_SEARCH => ast_search(arguments).await,
-        tool::AST_REPLACE => ast_replace(arguments).await,
-        tool::LIST_AGENTS => list_agents().await,
-        tool::LIST_HOOKS => list_hooks().await,
-        _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
-    }
-}
-
-
-#[derive(Deserialize)]
+///
+/// Tools do blocking filesystem and process work, so they run on tokio's
+/// blocking pool to keep concurrent requests from starving each other.
+/// Long-running tools poll `ctx.cancel` and stop early once it is triggered.
+pub async fn execute_tool(name: &str, arguments: Value, ctx: ToolContext) -> anyhow::Result<Value> {
+    let name = name.to_string();
+    Ok(tokio::task::spawn_blocking(move || registry().call(&name, arguments, &ctx)).await??)
+}
+
+/// Arguments of tools that take none
+#[derive(Deserialize, JsonSchema)]
+struct NoArgs {}
+
+/// A matching line in mgrep results
+#[derive(Serialize, JsonSchema)]
+struct LineMatch {
+    file: String,
+    line: usize,
+    content: String,
+    /// Every match in `content`
+    spans: Vec<MatchSpan>,
+}
+
+impl From<&MgrepMatch> for LineMatch {
+    fn from(m: &MgrepMatch) -> Self {
+        let (content, spans) = trim_spans(&m.content, &m.spans);
+        Self {
+            file: m.file.clone(),
+            line: m.line,
+            content,
+            spans,
+        }
+    }
+}
+
+/// Byte range of a match within `content`
+#[derive(Serialize, JsonSchema)]
+struct MatchSpan {
+    match_start: usize,
+    match_end: usize,
+}
+
+/// Trim `content` and move `spans` so they still point into it
+fn trim_spans(content: &str, spans: &[(usize, usize)]) -> (String, Vec<MatchSpan>) {
+    let trimmed = content.trim();
+    let offset = content.len() - content.trim_start().len();
+    let shift = |pos: usize| pos.saturating_sub(offset).min(trimmed.len());
+    let spans = spans
+        .iter()
+        .map(|&(start, end)| MatchSpan {
+            match_start: shift(start),
+            match_end: shift(end),
+        })
+        .collect();
+    (trimmed.to_string(), spans)
+}
+
+/// File selection shared by the search and replace tools
+#[derive(Deserialize, JsonSchema)]
+struct FileFilterArgs {
+    /// Only visit files matching these globs, e.g. "src/**" or "*.rs"
+    include: Option<Vec<String>>,
+    /// Also skip files and directories matching these globs
+    exclude: Option<Vec<String>>,
+    /// Only visit these file types, as in ripgrep --type (e.g. "rust", "ts", "py")
+    types: Option<Vec<String>>,
+    /// Skip these file types
+    exclude_types: Option<Vec<String>>,
+}
+
+impl FileFilterArgs {
+    /// Add the filters to a tool config; excludes extend its defaults
+    fn apply(
+        self,
+        include: &mut Vec<String>,
+        exclude: &mut Vec<String>,
+        types: &mut Vec<String>,
+        exclude_types: &mut Vec<String>,
+    ) {
+        include.extend(self.include.unwrap_or_default());
+        exclude.extend(self.exclude.unwrap_or_default());
+        types.extend(self.types.unwrap_or_default());
+        exclude_types.extend(self.exclude_types.unwrap_or_default());
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
 struct GrepArgs {
+    /// Regex pattern
     pattern: String,
+    /// Search directory
     directory: Option<String>,
+    /// Timeout in milliseconds (default: 30000)
     timeout_ms: Option<u64>,
+    /// Max results (default: 100)
     max_results: Option<usize>,
+    /// Also visit files ignored by .gitignore, .ignore and .orchestratorignore (default: false)
+    no_ignore: Option<bool>,
+    /// Include hidden files and directories (default: false)
+    hidden: Option<bool>,
+    #[serde(flatten)]
+    filter: FileFilterArgs,
+    /// Lines of context before and after each match, like grep -C (default: 0)
+    context: Option<usize>,
+    /// Lines of context before each match, like grep -B; overrides `context`
+    before_context: Option<usize>,
+    /// Lines of context after each match, like grep -A; overrides `context`
+    after_context: Option<usize>,
+    /// Ignore case (default: false)
+    case_insensitive: Option<bool>,
+    /// Ignore case unless the pattern contains an uppercase letter (default: false)
+    smart_case: Option<bool>,
+    /// Only match whole words (default: false)
+    whole_word: Option<bool>,
+    /// Treat the pattern as a literal string, not a regex (default: false)
+    fixed_strings: Option<bool>,
+    /// Let matches span lines so `\n` matches a line break (default: false)
+    multiline: Option<bool>,
+    /// Scan every file even if a trigram index exists (default: false)
+    no_index: Option<bool>,
 }
 
-async fn grep_search(arguments: Value) -> Result<String> {
-    let args: GrepArgs = serde_json::from_value(arguments)?;
+/// A line shown around a match
+#[derive(Serialize, JsonSchema)]
+struct ContextLine {
+    line: usize,
+    content: String,
+}
 
-    let mut config = GrepConfig::default();
-    if let Some(ms) = args.timeout_ms {
-        config.timeout = Duration::from_millis(ms);
-    }
-    if let Some(max) = args.max_results {
-        config.max_results = max;
+/// A matching line; with `multiline` it may hold several lines
+#[derive(Serialize, JsonSchema)]
+struct GrepLine {
+    file: String,
+    line: usize,
+    content: String,
+    /// Every match in `content`
+    spans: Vec<MatchSpan>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    before: Vec<ContextLine>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    after: Vec<ContextLine>,
+}
+
+impl From<GrepMatch> for GrepLine {
+    fn from(m: GrepMatch) -> Self {
+        let (content, spans) = trim_spans(&m.line_content, &m.spans);
+        let context = |lines: Vec<grep::ContextLine>| {
+            lines
+                .into_iter()
+                .map(|c| ContextLine {
+                    line: c.line_number,
+                    content: c.content,
+                })
+                .collect()
+        };
+        Self {
+            file: m.file,
+            line: m.line_number,
+            content,
+            spans,
+            before: context(m.context_before),
+            after: context(m.context_after),
+        }
     }
+}
 
-    let search_dir = args
-        .directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+#[derive(Serialize, JsonSchema)]
+struct GrepOutput {
+    matches: Vec<GrepLine>,
+    total: usize,
+    /// Files scanned, including skipped binary files
+    files_searched: usize,
+    /// Files skipped because they look binary
+    binary_files_skipped: usize,
+    /// The search stopped at the timeout; `matches` covers only the files searched
+    timed_out: bool,
+    /// More matches exist beyond `max_results`
+    truncated: bool,
+    /// How the trigram index was used
+    index: IndexUsage,
+}
 
-    let tool = GrepTool::new(config);
-    let results = tool.search(&args.pattern, &search_dir)?;
+struct GrepSearch;
 
-    let matches: Vec<Value> = results
-        .iter()
-        .take(100)
-        .map(|m| {
-            json!({
-                "file": m.file.clone(),
-                "line": m.line_number,
-                "content": m.line_content.trim()
-            })
-        })
-        .collect();
+impl Tool for GrepSearch {
+    type Args = GrepArgs;
+    type Output = GrepOutput;
 
-    Ok(serde_json::to_string_pretty(&json!({
-        "matches": matches,
-        "total": results.len()
-    }))?)
+    fn name(&self) -> &'static str {
+        tool::GREP_SEARCH
+    }
+
+    fn description(&self) -> &'static str {
+        "Fast regex search with context lines and timeout protection"
+    }
+
+    fn execute(&self, args: GrepArgs, ctx: &ToolContext) -> Result<GrepOutput> {
+        let mut config = GrepConfig::default();
+        if let Some(ms) = args.timeout_ms {
+            config.timeout = Duration::from_millis(ms);
+        }
+        config.max_results = args.max_results.unwrap_or(100);
+        config.respect_ignore = !args.no_ignore.unwrap_or(false);
+        config.include_hidden = args.hidden.unwrap_or(false);
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+        let context = args.context.unwrap_or(0);
+        config.before_context = args.before_context.unwrap_or(context);
+        config.after_context = args.after_context.unwrap_or(context);
+        config.case_insensitive = args.case_insensitive.unwrap_or(false);
+        config.smart_case = args.smart_case.unwrap_or(false);
+        config.whole_word = args.whole_word.unwrap_or(false);
+        config.fixed_strings = args.fixed_strings.unwrap_or(false);
+        config.multiline = args.multiline.unwrap_or(false);
+        config.use_index = !args.no_index.unwrap_or(false);
+
+        let search_dir = args
+            .directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let tool = GrepTool::new(config)
+            .with_cancel(ctx.cancel.clone())
+            .with_progress(ctx.progress.clone());
+        let mut matches = Vec::new();
+        let summary = tool.search_with(&args.pattern, &search_dir, |m| matches.push(m.into()))?;
+
+        Ok(GrepOutput {
+            matches,
+            total: summary.matches,
+            files_searched: summary.files_searched,
+            binary_files_skipped: summary.binary_files,
+            timed_out: summary.timed_out,
+            truncated: summary.truncated,
+            index: summary.index,
+        })
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct MgrepArgs {
+    /// Array of regex patterns to search, each reported separately
+    #[serde(default)]
     patterns: Vec<String>,
+    /// Query mode: files must match every one of these patterns
+    #[serde(default)]
+    all: Vec<String>,
+    /// Query mode: files must match at least one of these patterns
+    #[serde(default)]
+    any: Vec<String>,
+    /// Query mode: files must match none of these patterns
+    #[serde(default)]
+    none: Vec<String>,
+    /// Query mode: evaluate the query within this many consecutive lines instead of the whole file
+    window: Option<usize>,
+    /// Query mode: max files returned (default: 200)
+    max_files: Option<usize>,
+    /// Search directory (optional)
     directory: Option<String>,
+    /// Timeout in milliseconds
     timeout_ms: Option<u64>,
+    /// Max results per pattern (default: 50)
     max_results_per_pattern: Option<usize>,
+    /// Scan every file even if a trigram index exists (default: false)
+    no_index: Option<bool>,
+    /// Also visit files ignored by .gitignore, .ignore and .orchestratorignore (default: false)
+    no_ignore: Option<bool>,
+    /// Include hidden files and directories (default: false)
+    hidden: Option<bool>,
+    #[serde(flatten)]
+    filter: FileFilterArgs,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct MgrepPatternResult {
+    pattern: String,
+    matches: Vec<LineMatch>,
+    total: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct InvalidPatternOutput {
+    pattern: String,
+    error: String,
+}
+
+/// A file satisfying an all/any/none query
+#[derive(Serialize, JsonSchema)]
+struct MgrepFileOutput {
+    file: String,
+    /// Lines matching each `all` and `any` pattern
+    evidence: Vec<MgrepPatternResult>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct MgrepOutput {
+    /// Matches per pattern; empty in query mode
+    results: Vec<MgrepPatternResult>,
+    /// Files satisfying the query, in query mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<MgrepFileOutput>>,
+    /// More files matched the query than `max_files`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<bool>,
+    patterns_searched: usize,
+    /// Patterns that failed to compile and were not searched
+    invalid_patterns: Vec<InvalidPatternOutput>,
+    /// Patterns given more than once; each is searched and reported once
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    duplicate_patterns: Vec<String>,
+    files_searched: usize,
+    /// The search stopped at the timeout; results cover only the files searched
+    timed_out: bool,
+    /// How the trigram index was used
+    index: IndexUsage,
 }
 
 /// Multi-pattern grep - search multiple patterns in parallel
-async fn mgrep(arguments: Value) -> Result<String> {
-    let args: MgrepArgs = serde_json::from_value(arguments)?;
+struct Mgrep;
 
-    if args.patterns.is_empty() {
-        return Ok(json!({"error": "No patterns provided"}).to_string());
-    }
+impl Tool for Mgrep {
+    type Args = MgrepArgs;
+    type Output = MgrepOutput;
 
-    let mut config = MgrepConfig::default();
-    if let Some(ms) = args.timeout_ms {
-        config.timeout = Duration::from_millis(ms);
+    fn name(&self) -> &'static str {
+        tool::MGREP
     }
-    if let Some(max) = args.max_results_per_pattern {
-        config.max_results_per_pattern = max;
+
+    fn description(&self) -> &'static str {
+        "Search multiple patterns in one pass over the files. Much faster than running grep multiple times. \
+         With all/any/none instead of patterns, find files (or windows of lines) matching a boolean combination."
     }
 
-    let search_dir = args
-        .directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    fn execute(&self, args: MgrepArgs, ctx: &ToolContext) -> Result<MgrepOutput> {
+        let is_query = !(args.all.is_empty() && args.any.is_empty() && args.none.is_empty());
+        if is_query && !args.patterns.is_empty() {
+            return Err(Error::invalid_arguments("Use either patterns or all/any/none, not both"));
+        }
+        if !is_query && args.patterns.is_empty() {
+            return Err(Error::invalid_arguments("No patterns provided"));
+        }
+
+        let mut config = MgrepConfig::default();
+        if let Some(ms) = args.timeout_ms {
+            config.timeout = Duration::from_millis(ms);
+        }
+        if let Some(max) = args.max_results_per_pattern {
+            config.max_results_per_pattern = max;
+        }
+        if let Some(max) = args.max_files {
+            config.max_files = max;
+        }
+        config.use_index = !args.no_index.unwrap_or(false);
+        config.respect_ignore = !args.no_ignore.unwrap_or(false);
+        config.include_hidden = args.hidden.unwrap_or(false);
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+
+        let search_dir = args
+            .directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let tool = MgrepTool::new(config)
+            .with_cancel(ctx.cancel.clone())
+            .with_progress(ctx.progress.clone());
+
+        if is_query {
+            let query = MgrepQuery {
+                all: args.all,
+                any: args.any,
+                none: args.none,
+                window: args.window,
+            };
+            let result = tool.query(&query, &search_dir)?;
+            let files = result
+                .files
+                .into_iter()
+                .map(|file| MgrepFileOutput {
+                    file: file.file,
+                    // Group evidence in the order the patterns were given
+                    evidence: query
+                        .all
+                        .iter()
+                        .chain(&query.any)
+                        .filter_map(|pattern| {
+                            let matches: Vec<LineMatch> = file
+                                .evidence
+                                .iter()
+                                .filter(|m| &m.pattern == pattern)
+                                .map(LineMatch::from)
+                                .collect();
+                            (!matches.is_empty()).then(|| MgrepPatternResult {
+                                pattern: pattern.clone(),
+                                total: matches.len(),
+                                matches,
+                            })
+                        })
+                        .collect(),
+                })
+                .collect();
+            return Ok(MgrepOutput {
+                results: vec![],
+                files: Some(files),
+                truncated: Some(result.truncated),
+                patterns_searched: query.all.len() + query.any.len() + query.none.len(),
+                invalid_patterns: vec![],
+                duplicate_patterns: vec![],
+                files_searched: result.files_searched,
+                timed_out: result.timed_out,
+                index: result.index,
+            });
+        }
 
-    let tool = MgrepTool::new(config);
-    let result = tool.search(&args.patterns, &search_dir)?;
+        let mut result = tool.search(&args.patterns, &search_dir)?;
 
-    // Format results
-    let mut all_results = Vec::new();
-    for (pattern, matches) in &result.results {
-        let formatted: Vec<Value> = matches
+        // Format results in the order the patterns were given; a repeated
+        // pattern was searched once and is listed at its first position
+        let results = args
+            .patterns
             .iter()
-            .map(|m| {
-                json!({
-                    "file": m.file.clone(),
-                    "line": m.line,
-                    "content": m.content.trim()
-                })
+            .filter_map(|pattern| result.results.remove_entry(pattern))
+            .map(|(pattern, matches)| MgrepPatternResult {
+                pattern,
+                matches: matches.iter().map(LineMatch::from).collect(),
+                total: matches.len(),
             })
             .collect();
 
-        all_results.push(json!({
-            "pattern": pattern,
-            "matches": formatted,
-            "total": matches.len()
-        }));
+        Ok(MgrepOutput {
+            results,
+            files: None,
+            truncated: None,
+            patterns_searched: args.patterns.iter().collect::<HashSet<_>>().len(),
+            invalid_patterns: result
+                .invalid_patterns
+                .into_iter()
+                .map(|p| InvalidPatternOutput {
+                    pattern: p.pattern,
+                    error: p.error,
+                })
+                .collect(),
+            duplicate_patterns: result.duplicate_patterns,
+            files_searched: result.files_searched,
+            timed_out: result.timed_out,
+            index: result.index,
+        })
     }
-
-    Ok(serde_json::to_string_pretty(&json!({
-        "results": all_results,
-        "patterns_searched": args.patterns.len()
-    }))?)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct GlobArgs {
+    /// Glob pattern (e.g., **/*.rs)
     pattern: String,
+    /// Search directory
     directory: Option<String>,
+    /// Max results
     max_results: Option<usize>,
+    /// Also visit files ignored by .gitignore, .ignore and .orchestratorignore (default: false)
+    no_ignore: Option<bool>,
+    /// Include hidden files and directories (default: false)
+    hidden: Option<bool>,
+    #[serde(flatten)]
+    filter: FileFilterArgs,
 }
 
-async fn glob_search(arguments: Value) -> Result<String> {
-    let args: GlobArgs = serde_json::from_value(arguments)?;
+#[derive(Serialize, JsonSchema)]
+struct GlobOutput {
+    files: Vec<String>,
+    total: usize,
+}
+
+struct GlobSearch;
+
+impl Tool for GlobSearch {
+    type Args = GlobArgs;
+    type Output = GlobOutput;
 
-    let mut config = GlobConfig::default();
-    if let Some(max) = args.max_results {
-        config.max_results = max;
+    fn name(&self) -> &'static str {
+        tool::GLOB_SEARCH
     }
 
-    let search_dir = args
-        .directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    fn description(&self) -> &'static str {
+        "Find files by glob pattern"
+    }
 
-    let tool = GlobTool::new(config);
-    let results = tool.find(&args.pattern, &search_dir)?;
+    fn execute(&self, args: GlobArgs, ctx: &ToolContext) -> Result<GlobOutput> {
+        let mut config = GlobConfig::default();
+        if let Some(max) = args.max_results {
+            config.max_results = max;
+        }
+        config.respect_ignore = !args.no_ignore.unwrap_or(false);
+        config.include_hidden = args.hidden.unwrap_or(false);
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+
+        let search_dir = args
+            .directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let tool = GlobTool::new(config).with_cancel(ctx.cancel.clone());
+        let results = tool.find(&args.pattern, &search_dir)?;
+
+        let files = results
+            .iter()
+            .take(100)
+            .map(|p| p.display().to_string())
+            .collect();
 
-    let files: Vec<String> = results
-        .iter()
-        .take(100)
-        .map(|p| p.display().to_string())
-        .collect();
+        Ok(GlobOutput {
+            files,
+            total: results.len(),
+        })
+    }
+}
 
-    Ok(serde_json::to_string_pretty(&json!({
-        "files": files,
-        "total": results.len()
-    }))?)
+#[derive(Serialize, JsonSchema)]
+struct AgentInfo {
+    id: &'static str,
+    description: &'static str,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ListAgentsOutput {
+    agents: Vec<AgentInfo>,
 }
 
 /// List all available agents (4-agent architecture)
-async fn list_agents() -> Result<String> {
-    let agents = vec![
-        json!({
-            "id": "Commander",
-            "description": "Autonomous orchestrator - executes until mission complete"
-        }),
-        json!({
-            "id": "Planner",
-            "description": "Strategic planning and research specialist"
-        }),
-        json!({
-            "id": "Worker",
-            "description": "Implementation and documentation specialist"
-        }),
-        json!({
-            "id": "Reviewer",
-            "description": "Verification and context management specialist"
-        }),
-    ];
-
-    Ok(serde_json::to_string_pretty(&json!({"agents": agents}))?)
-}
-
-async fn list_hooks() -> Result<String> {
-    let hooks: Vec<Value> = Hook::all()
-        .iter()
-        .map(|h| {
-            json!({
-                "name": h.to_string(),
-                "description": h.description()
+struct ListAgents;
+
+impl Tool for ListAgents {
+    type Args = NoArgs;
+    type Output = ListAgentsOutput;
+
+    fn name(&self) -> &'static str {
+        tool::LIST_AGENTS
+    }
+
+    fn description(&self) -> &'static str {
+        "List available agents"
+    }
+
+    fn execute(&self, _args: NoArgs, _ctx: &ToolContext) -> Result<ListAgentsOutput> {
+        let agents = vec![
+            AgentInfo {
+                id: "Commander",
+                description: "Autonomous orchestrator - executes until mission complete",
+            },
+            AgentInfo {
+                id: "Planner",
+                description: "Strategic planning and research specialist",
+            },
+            AgentInfo {
+                id: "Worker",
+                description: "Implementation and documentation specialist",
+            },
+            AgentInfo {
+                id: "Reviewer",
+                description: "Verification and context management specialist",
+            },
+        ];
+
+        Ok(ListAgentsOutput { agents })
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct HookInfo {
+    name: String,
+    description: &'static str,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ListHooksOutput {
+    hooks: Vec<HookInfo>,
+}
+
+struct ListHooks;
+
+impl Tool for ListHooks {
+    type Args = NoArgs;
+    type Output = ListHooksOutput;
+
+    fn name(&self) -> &'static str {
+        tool::LIST_HOOKS
+    }
+
+    fn description(&self) -> &'static str {
+        "List available hooks"
+    }
+
+    fn execute(&self, _args: NoArgs, _ctx: &ToolContext) -> Result<ListHooksOutput> {
+        let hooks = Hook::all()
+            .iter()
+            .map(|h| HookInfo {
+                name: h.to_string(),
+                description: h.description(),
             })
-        })
-        .collect();
+            .collect();
 
-    Ok(serde_json::to_string_pretty(&json!({"hooks": hooks}))?)
+        Ok(ListHooksOutput { hooks })
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct SedArgs {
+    /// Regex pattern to find
     pattern: String,
+    /// Replacement string; $1 or ${name} insert capture groups unless literal
     replacement: String,
+    /// Match against the whole file so patterns can span lines; use (?s) to let . match newlines (default: false)
+    multiline: Option<bool>,
+    /// Treat pattern and replacement as plain text (default: false)
+    literal: Option<bool>,
+    /// Only replace within these lines, e.g. "10-40", "10-" or "7"
+    lines: Option<String>,
+    /// Replace at most this many matches per file
+    max_replacements: Option<usize>,
+    /// Single file to modify
     file: Option<String>,
+    /// Directory to modify (recursive)
     directory: Option<String>,
+    /// Timeout in milliseconds
     timeout_ms: Option<u64>,
+    /// Preview changes without modifying (default: false)
     dry_run: Option<bool>,
+    /// Create .bak backup (default: false)
     backup: Option<bool>,
+    /// Compute every replacement first, then write all files or none; otherwise files are written one by one and a timeout keeps those already written (default: false)
+    transactional: Option<bool>,
+    /// Return a unified diff for each file (default: true for dry runs)
+    diff: Option<bool>,
+    /// Context lines around each change in diffs (default: 3)
+    diff_context: Option<usize>,
+    /// Also visit files ignored by .gitignore, .ignore and .orchestratorignore (default: false)
+    no_ignore: Option<bool>,
+    /// Include hidden files and directories (default: false)
+    hidden: Option<bool>,
+    #[serde(flatten)]
+    filter: FileFilterArgs,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SedFileOutput {
+    file: String,
+    replacements: usize,
+    /// Unified diff of the change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SedOutput {
+    success: bool,
+    files_modified: usize,
+    total_replacements: usize,
+    files: Vec<SedFileOutput>,
+    dry_run: bool,
+    /// Pass to undo_changeset to revert the replacement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changeset_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
 }
 
 /// Sed-like find and replace tool
-async fn sed_replace(arguments: Value) -> Result<String> {
-    let args: SedArgs = serde_json::from_value(arguments)?;
+struct SedReplace;
+
+impl Tool for SedReplace {
+    type Args = SedArgs;
+    type Output = SedOutput;
 
-    let mut config = SedConfig::default();
-    if let Some(ms) = args.timeout_ms {
-        config.timeout = Duration::from_millis(ms);
+    fn name(&self) -> &'static str {
+        tool::SED_REPLACE
     }
-    if let Some(dry) = args.dry_run {
-        config.dry_run = dry;
+
+    fn description(&self) -> &'static str {
+        "Find and replace patterns in files (sed-like). Dry runs return a unified diff per file for review."
     }
-    if let Some(backup) = args.backup {
-        config.backup = backup;
+
+    fn execute(&self, args: SedArgs, ctx: &ToolContext) -> Result<SedOutput> {
+        let mut config = SedConfig::default();
+        if let Some(ms) = args.timeout_ms {
+            config.timeout = Duration::from_millis(ms);
+        }
+        if let Some(dry) = args.dry_run {
+            config.dry_run = dry;
+        }
+        if let Some(backup) = args.backup {
+            config.backup = backup;
+        }
+        config.multiline = args.multiline.unwrap_or(false);
+        config.literal = args.literal.unwrap_or(false);
+        config.line_range = args.lines.as_deref().map(sed::parse_line_range).transpose()?;
+        config.max_replacements = args.max_replacements;
+        config.respect_ignore = !args.no_ignore.unwrap_or(false);
+        config.include_hidden = args.hidden.unwrap_or(false);
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+
+        let target = args.file.as_deref().or(args.directory.as_deref()).map(Path::new);
+        config.journal = ctx.journal(journal_root(target));
+        if args.diff.unwrap_or(config.dry_run) {
+            config.diff_context = Some(args.diff_context.unwrap_or(3));
+        }
+
+        let tool = SedTool::new(config)
+            .with_cancel(ctx.cancel.clone())
+            .with_progress(ctx.progress.clone());
+
+        let mut changeset_id = None;
+        let dry_run = args.dry_run.unwrap_or(false);
+        let results = if !dry_run {
+            // Every write is journaled so it can be undone
+            let target = args
+                .file
+                .or(args.directory)
+                .ok_or_else(|| Error::invalid_arguments("Either 'file' or 'directory' must be specified"))?;
+            let target = PathBuf::from(target);
+            let change = if args.transactional.unwrap_or(false) {
+                tool.replace_atomic(&args.pattern, &args.replacement, &target)?
+            } else {
+                tool.replace_journaled(&args.pattern, &args.replacement, &target)?
+            };
+            changeset_id = change.changeset.map(|c| c.id);
+            change.results
+        } else if let Some(file_path) = args.file {
+            // Single file mode
+            let path = PathBuf::from(&file_path);
+            tool.replace_in_file(&args.pattern, &args.replacement, &path)?
+                .into_iter()
+                .collect()
+        } else if let Some(dir_path) = args.directory {
+            // Directory mode
+            let path = PathBuf::from(&dir_path);
+            tool.replace_in_directory(&args.pattern, &args.replacement, &path)?
+        } else {
+            return Err(Error::invalid_arguments("Either 'file' or 'directory' must be specified"));
+        };
+
+        let files: Vec<SedFileOutput> = results
+            .into_iter()
+            .map(|r| SedFileOutput {
+                file: r.file,
+                replacements: r.replacements,
+                diff: r.diff,
+            })
+            .collect();
+
+        Ok(SedOutput {
+            success: true,
+            files_modified: files.len(),
+            total_replacements: files.iter().map(|f| f.replacements).sum(),
+            message: files.is_empty().then(|| "No matches found".to_string()),
+            files,
+            dry_run,
+            changeset_id,
+        })
     }
+}
 
-    let tool = SedTool::new(config);
+// ========== APPLY PATCH TOOL ==========
 
-    // Single file mode
-    if let Some(file_path) = args.file {
-        let path = PathBuf::from(&file_path);
-        match tool.replace_in_file(&args.pattern, &args.replacement, &path) {
-            Ok(Some(result)) => {
-                Ok(serde_json::to_string_pretty(&json!({
-                    "success": true,
-                    "file": result.file,
-                    "replacements": result.replacements,
-                    "dry_run": args.dry_run.unwrap_or(false)
-                }))?)
-            }
-            Ok(None) => {
-                Ok(serde_json::to_string_pretty(&json!({
-                    "success": true,
-                    "file": file_path,
-                    "replacements": 0,
-                    "message": "No matches found"
-                }))?)
-            }
-            Err(e) => {
-                Ok(serde_json::to_string_pretty(&json!({
-                    "success": false,
-                    "error": e.to_string()
-                }))?)
-            }
+#[derive(Deserialize, JsonSchema)]
+struct ApplyPatchArgs {
+    /// Unified diff to apply, as produced by `diff -u` or `git diff`
+    patch: Option<String>,
+    /// File holding the diff (instead of patch)
+    patch_file: Option<String>,
+    /// Directory the paths in the patch are relative to (default: current directory)
+    directory: Option<String>,
+    /// Leading path components to strip, as in `patch -p` (default: strip git's a/ and b/)
+    strip: Option<usize>,
+    /// Context lines that may be ignored at each end of a hunk (default: 2)
+    fuzz: Option<usize>,
+    /// Lines a hunk may move from its stated position (default: whole file)
+    max_offset: Option<usize>,
+    /// Compare lines ignoring whitespace differences (default: false)
+    ignore_whitespace: Option<bool>,
+    /// Only check whether the patch applies (default: false)
+    dry_run: Option<bool>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct PatchHunkOutput {
+    /// Line the hunk names in the old file
+    old_start: usize,
+    /// "applied" or "conflict"
+    status: &'static str,
+    /// Line the hunk applied at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    applied_at: Option<usize>,
+    /// Lines between where it applied and where it said it would
+    offset: isize,
+    /// Context lines ignored to apply it
+    fuzz: usize,
+    /// Old lines the hunk looked for, on conflict
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    expected: Vec<String>,
+    /// The file's lines around where they should be, on conflict
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    actual: Vec<String>,
+    /// Line number of the first actual line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual_start: Option<usize>,
+}
+
+impl From<HunkResult> for PatchHunkOutput {
+    fn from(hunk: HunkResult) -> Self {
+        let applied = hunk.is_applied();
+        Self {
+            old_start: hunk.old_start,
+            status: if applied { "applied" } else { "conflict" },
+            applied_at: hunk.applied_at,
+            offset: hunk.offset,
+            fuzz: hunk.fuzz,
+            expected: hunk.expected,
+            actual: hunk.actual,
+            actual_start: (!applied).then_some(hunk.actual_start),
         }
     }
-    // Directory mode
-    else if let Some(dir_path) = args.directory {
-        let path = PathBuf::from(&dir_path);
-        match tool.replace_in_directory(&args.pattern, &args.replacement, &path) {
-            Ok(results) => {
-                let total_replacements: usize = results.iter().map(|r| r.replacements).sum();
-                let files: Vec<Value> = results
-                    .iter()
-                    .map(|r| {
-                        json!({
-                            "file": r.file,
-                            "replacements": r.replacements
-                        })
-                    })
-                    .collect();
-
-                Ok(serde_json::to_string_pretty(&json!({
-                    "success": true,
-                    "files_modified": results.len(),
-                    "total_replacements": total_replacements,
-                    "files": files,
-                    "dry_run": args.dry_run.unwrap_or(false)
-                }))?)
-            }
-            Err(e) => {
-                Ok(serde_json::to_string_pretty(&json!({
-                    "success": false,
-                    "error": e.to_string()
-                }))?)
-            }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct PatchFileOutput {
+    path: String,
+    /// Path renamed from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_path: Option<String>,
+    /// "modify", "create", "delete" or "rename"
+    operation: &'static str,
+    applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    hunks: Vec<PatchHunkOutput>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ApplyPatchOutput {
+    /// Whether every hunk applied; nothing is written otherwise
+    applied: bool,
+    dry_run: bool,
+    files: Vec<PatchFileOutput>,
+    /// Pass to undo_changeset to revert the patch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changeset_id: Option<String>,
+}
+
+/// Apply unified diffs
+struct ApplyPatch;
+
+impl Tool for ApplyPatch {
+    type Args = ApplyPatchArgs;
+    type Output = ApplyPatchOutput;
+
+    fn name(&self) -> &'static str {
+        tool::APPLY_PATCH
+    }
+
+    fn description(&self) -> &'static str {
+        "Apply a unified diff (diff -u or git diff) to one or more files, including creates, deletes and renames. Hunks may apply at an offset or with fuzz; conflicts are reported with the actual surrounding lines and nothing is written unless every hunk applies."
+    }
+
+    fn execute(&self, args: ApplyPatchArgs, ctx: &ToolContext) -> Result<ApplyPatchOutput> {
+        let patch = match (args.patch, args.patch_file) {
+            (Some(patch), _) => patch,
+            (None, Some(file)) => std::fs::read_to_string(file)?,
+            (None, None) => return Err(Error::invalid_arguments("Provide patch or patch_file")),
+        };
+
+        let mut config = PatchConfig::default();
+        if let Some(directory) = &args.directory {
+            config.root = PathBuf::from(directory);
         }
+        if let Some(fuzz) = args.fuzz {
+            config.fuzz = fuzz;
+        }
+        config.strip = args.strip;
+        config.max_offset = args.max_offset;
+        config.ignore_whitespace = args.ignore_whitespace.unwrap_or(false);
+        config.dry_run = args.dry_run.unwrap_or(false);
+        config.journal = ctx.journal(journal_root(args.directory.as_deref().map(Path::new)));
+        let dry_run = config.dry_run;
+
+        let result = PatchTool::new(config).with_cancel(ctx.cancel.clone()).apply(&patch)?;
+        Ok(ApplyPatchOutput {
+            applied: result.is_applied(),
+            dry_run,
+            changeset_id: result.changeset.map(|c| c.id),
+            files: result
+                .files
+                .into_iter()
+                .map(|file| PatchFileOutput {
+                    applied: file.is_applied(),
+                    path: file.path.display().to_string(),
+                    old_path: file.old_path.map(|p| p.display().to_string()),
+                    operation: file.operation.as_str(),
+                    error: file.error,
+                    hunks: file.hunks.into_iter().map(PatchHunkOutput::from).collect(),
+                })
+                .collect(),
+        })
+    }
+}
+
+// ========== JOURNAL TOOLS ==========
+
+/// Project root whose journal records changes to `target`, a file or
+/// directory (default: current directory): the nearest ancestor that
+/// already has a journal or is a git work tree, else the target directory.
+/// Writers and the undo tools resolve it the same way, so passing any path
+/// in the project finds its change sets.
+fn journal_root(target: Option<&Path>) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let target = target.map_or_else(|| cwd.clone(), |target| cwd.join(target));
+    let directory = if target.is_dir() {
+        target.as_path()
     } else {
-        Ok(serde_json::to_string_pretty(&json!({
-            "success": false,
-            "error": "Either 'file' or 'directory' must be specified"
-        }))?)
+        target.parent().unwrap_or(&target)
+    };
+    directory
+        .ancestors()
+        .find(|dir| dir.join(JOURNAL_DIR).is_dir() || dir.join(".git").exists())
+        .unwrap_or(directory)
+        .to_path_buf()
+}
+
+#[derive(Serialize, JsonSchema)]
+struct UndoOutput {
+    changeset_id: String,
+    /// Tool that made the change
+    tool: String,
+    /// Files restored to their earlier contents
+    files: Vec<String>,
+}
+
+impl From<ChangeSet> for UndoOutput {
+    fn from(changeset: ChangeSet) -> Self {
+        Self {
+            files: changeset.files.iter().map(|f| f.path.display().to_string()).collect(),
+            changeset_id: changeset.id,
+            tool: changeset.tool,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct UndoChangesetArgs {
+    /// Change set returned by sed_replace, ast_replace or apply_patch
+    changeset_id: String,
+    /// Project, or any path in it, whose journal holds the change set (default: current directory)
+    directory: Option<String>,
+    /// Restore even if the files were edited after the change (default: false)
+    force: Option<bool>,
+}
+
+/// Revert a change set recorded in the journal
+struct UndoChangeset;
+
+impl Tool for UndoChangeset {
+    type Args = UndoChangesetArgs;
+    type Output = UndoOutput;
+
+    fn name(&self) -> &'static str {
+        tool::UNDO_CHANGESET
+    }
+
+    fn description(&self) -> &'static str {
+        "Revert every file of a change set to its contents before the change"
+    }
+
+    fn execute(&self, args: UndoChangesetArgs, ctx: &ToolContext) -> Result<UndoOutput> {
+        let journal = ctx.journal(journal_root(args.directory.as_deref().map(Path::new)));
+        Ok(journal.undo(&args.changeset_id, args.force.unwrap_or(false))?.into())
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct UndoLastArgs {
+    /// Project, or any path in it, whose journal holds the change set (default: current directory)
+    directory: Option<String>,
+    /// Restore even if the files were edited after the change (default: false)
+    force: Option<bool>,
+}
+
+/// Revert the session's most recent change set
+struct UndoLast;
+
+impl Tool for UndoLast {
+    type Args = UndoLastArgs;
+    type Output = UndoOutput;
+
+    fn name(&self) -> &'static str {
+        tool::UNDO_LAST
+    }
+
+    fn description(&self) -> &'static str {
+        "Revert the most recent file change made in this session by sed_replace, ast_replace or apply_patch"
+    }
+
+    fn execute(&self, args: UndoLastArgs, ctx: &ToolContext) -> Result<UndoOutput> {
+        let journal = ctx.journal(journal_root(args.directory.as_deref().map(Path::new)));
+        Ok(journal.undo_last(args.force.unwrap_or(false))?.into())
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ListChangesetsArgs {
+    /// Project, or any path in it, whose journal to list (default: current directory)
+    directory: Option<String>,
+    /// Include change sets of other sessions (default: false)
+    all_sessions: Option<bool>,
+    /// Max change sets returned, newest first (default: 50)
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ChangesetOutput {
+    id: String,
+    tool: String,
+    /// Seconds since the Unix epoch
+    created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    /// JSON RPC id of the tool call that made the change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    files: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ListChangesetsOutput {
+    changesets: Vec<ChangesetOutput>,
+    total: usize,
+}
+
+/// List the change sets that can still be undone
+struct ListChangesets;
+
+impl Tool for ListChangesets {
+    type Args = ListChangesetsArgs;
+    type Output = ListChangesetsOutput;
+
+    fn name(&self) -> &'static str {
+        tool::LIST_CHANGESETS
+    }
+
+    fn description(&self) -> &'static str {
+        "List recorded file changes that can be undone, newest first"
+    }
+
+    fn execute(&self, args: ListChangesetsArgs, ctx: &ToolContext) -> Result<ListChangesetsOutput> {
+        let journal = ctx.journal(journal_root(args.directory.as_deref().map(Path::new)));
+        let changesets = journal.list(args.all_sessions.unwrap_or(false))?;
+        let total = changesets.len();
+
+        Ok(ListChangesetsOutput {
+            changesets: changesets
+                .into_iter()
+                .take(args.limit.unwrap_or(50))
+                .map(|c| ChangesetOutput {
+                    files: c.files.iter().map(|f| f.path.display().to_string()).collect(),
+                    id: c.id,
+                    tool: c.tool,
+                    created: c.created,
+                    session_id: c.session_id,
+                    request_id: c.request_id,
+                })
+                .collect(),
+            total,
+        })
     }
 }
 
 // ========== DIFF TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct DiffArgs {
+    /// First file to compare
     file1: Option<String>,
+    /// Second file to compare
     file2: Option<String>,
+    /// First string to compare (instead of file1)
     content1: Option<String>,
+    /// Second string to compare (instead of file2)
     content2: Option<String>,
+    /// Ignore whitespace changes (default: false)
     ignore_whitespace: Option<bool>,
+    /// Ignore case differences (default: false)
+    ignore_case: Option<bool>,
+    /// Lines of context around each change (default: 3)
+    context_lines: Option<usize>,
+    /// Line matching algorithm (default: myers)
+    algorithm: Option<Algorithm>,
+    /// Output format (default: unified)
+    format: Option<DiffFormat>,
+    /// Highlight changes within lines by word or character (default: none)
+    inline: Option<InlineDiff>,
+    /// Total width of side_by_side output (default: 130)
+    width: Option<usize>,
 }
 
-async fn diff_files(arguments: Value) -> Result<String> {
-    let args: DiffArgs = serde_json::from_value(arguments)?;
-    
-    let mut config = DiffConfig::default();
-    if let Some(ignore_ws) = args.ignore_whitespace {
-        config.ignore_whitespace = ignore_ws;
-    }
-    
-    let tool = DiffTool::new(config);
-    
-    let result = if let (Some(f1), Some(f2)) = (&args.file1, &args.file2) {
-        tool.diff_files(&PathBuf::from(f1), &PathBuf::from(f2))?
-    } else if let (Some(c1), Some(c2)) = (&args.content1, &args.content2) {
-        tool.diff_strings(c1, c2)?
-    } else {
-        return Ok(json!({"error": "Provide file1+file2 or content1+content2"}).to_string());
-    };
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "has_differences": result.has_differences,
-        "additions": result.additions,
-        "deletions": result.deletions,
-        "diff": result.diff_output
-    }))?)
+#[derive(Serialize, JsonSchema)]
+struct DiffOutput {
+    has_differences: bool,
+    additions: usize,
+    deletions: usize,
+    diff: String,
+    /// Structured hunks, for json output or inline highlighting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hunks: Option<Vec<Hunk>>,
+}
+
+struct Diff;
+
+impl Tool for Diff {
+    type Args = DiffArgs;
+    type Output = DiffOutput;
+
+    fn name(&self) -> &'static str {
+        tool::DIFF
+    }
+
+    fn description(&self) -> &'static str {
+        "Compare two files or strings in process (Myers, patience or histogram), as unified, context, side-by-side or JSON hunks, with optional word or character highlights"
+    }
+
+    fn execute(&self, args: DiffArgs, ctx: &ToolContext) -> Result<DiffOutput> {
+        let mut config = DiffConfig::default();
+        if let Some(ignore_ws) = args.ignore_whitespace {
+            config.ignore_whitespace = ignore_ws;
+        }
+        if let Some(ignore_case) = args.ignore_case {
+            config.ignore_case = ignore_case;
+        }
+        if let Some(context_lines) = args.context_lines {
+            config.context_lines = context_lines;
+        }
+        if let Some(algorithm) = args.algorithm {
+            config.algorithm = algorithm;
+        }
+        if let Some(format) = args.format {
+            config.format = format;
+        }
+        if let Some(inline) = args.inline {
+            config.inline = inline;
+        }
+        if let Some(width) = args.width {
+            config.width = width;
+        }
+        let structured = config.format == DiffFormat::Json || config.inline != InlineDiff::None;
+
+        let tool = DiffTool::new(config).with_cancel(ctx.cancel.clone());
+
+        let result = if let (Some(f1), Some(f2)) = (&args.file1, &args.file2) {
+            tool.diff_files(&PathBuf::from(f1), &PathBuf::from(f2))?
+        } else if let (Some(c1), Some(c2)) = (&args.content1, &args.content2) {
+            tool.diff_strings(c1, c2)?
+        } else {
+            return Err(Error::invalid_arguments("Provide file1+file2 or content1+content2"));
+        };
+
+        Ok(DiffOutput {
+            has_differences: result.has_differences,
+            additions: result.additions,
+            deletions: result.deletions,
+            diff: result.diff_output,
+            hunks: structured.then_some(result.hunks),
+        })
+    }
+}
+
+// ========== MERGE3 TOOL ==========
+
+#[derive(Deserialize, JsonSchema)]
+struct Merge3Args {
+    /// Common ancestor file
+    base_file: Option<String>,
+    /// Our version of the file
+    ours_file: Option<String>,
+    /// Their version of the file
+    theirs_file: Option<String>,
+    /// Common ancestor text (instead of base_file)
+    base: Option<String>,
+    /// Our version as text (instead of ours_file)
+    ours: Option<String>,
+    /// Their version as text (instead of theirs_file)
+    theirs: Option<String>,
+    /// Line matching algorithm (default: myers)
+    algorithm: Option<Algorithm>,
+    /// Name after the <<<<<<< marker (default: ours_file or "ours")
+    ours_label: Option<String>,
+    /// Name after the ||||||| marker (default: base_file or "base")
+    base_label: Option<String>,
+    /// Name after the >>>>>>> marker (default: theirs_file or "theirs")
+    theirs_label: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Merge3Output {
+    /// Whether the versions merged without conflicts
+    clean: bool,
+    /// Merged text, with diff3-style markers around each conflict
+    merged: String,
+    /// Changes merged without conflict
+    resolved: usize,
+    conflicts: Vec<MergeConflict>,
+}
+
+/// Three-way merge of two versions of a file
+struct Merge3;
+
+impl Tool for Merge3 {
+    type Args = Merge3Args;
+    type Output = Merge3Output;
+
+    fn name(&self) -> &'static str {
+        tool::MERGE3
+    }
+
+    fn description(&self) -> &'static str {
+        "Three-way merge of two versions (ours, theirs) of a common base, as files or strings. Returns the merged text with diff3-style conflict markers and each conflict as a structured hunk."
+    }
+
+    fn execute(&self, args: Merge3Args, ctx: &ToolContext) -> Result<Merge3Output> {
+        let mut config = MergeConfig::default();
+        if let Some(algorithm) = args.algorithm {
+            config.algorithm = algorithm;
+        }
+        config.ours_label = args.ours_label;
+        config.base_label = args.base_label;
+        config.theirs_label = args.theirs_label;
+
+        let tool = MergeTool::new(config).with_cancel(ctx.cancel.clone());
+        let result = match (args.base_file, args.ours_file, args.theirs_file, args.base, args.ours, args.theirs) {
+            (Some(base), Some(ours), Some(theirs), ..) => {
+                tool.merge_files(&PathBuf::from(base), &PathBuf::from(ours), &PathBuf::from(theirs))?
+            }
+            (_, _, _, Some(base), Some(ours), Some(theirs)) => tool.merge_strings(&base, &ours, &theirs)?,
+            _ => {
+                return Err(Error::invalid_arguments(
+                    "Provide base_file+ours_file+theirs_file or base+ours+theirs",
+                ));
+            }
+        };
+
+        Ok(Merge3Output {
+            clean: result.is_clean(),
+            merged: result.merged,
+            resolved: result.resolved,
+            conflicts: result.conflicts,
+        })
+    }
 }
 
 // ========== JQ TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct JqArgs {
+    /// JSON text to query
     json_input: Option<String>,
+    /// JSON file to query (instead of json_input)
     file: Option<String>,
+    /// JSON files to query in order, as one input (instead of json_input)
+    files: Option<Vec<String>>,
+    /// jq expression
     expression: String,
+    /// Print strings without quotes (default: false)
     raw_output: Option<bool>,
+    /// Print each output on a single line instead of pretty printing (default: false)
+    compact: Option<bool>,
+    /// Sort object keys (default: false)
+    sort_keys: Option<bool>,
+    /// Run the expression once on an array of all input values (default: false)
+    slurp: Option<bool>,
 }
 
-async fn jq_query(arguments: Value) -> Result<String> {
-    let args: JqArgs = serde_json::from_value(arguments)?;
-    
-    let mut config = JqConfig::default();
-    if let Some(raw) = args.raw_output {
-        config.raw_output = raw;
-    }
-    
-    let tool = JqTool::new(config);
-    
-    let result = if let Some(input) = args.json_input {
-        tool.query(&input, &args.expression)?
-    } else if let Some(file_path) = args.file {
-        tool.query_file(&PathBuf::from(file_path), &args.expression)?
-    } else {
-        return Ok(json!({"error": "Provide json_input or file"}).to_string());
-    };
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "result": result
-    }))?)
+#[derive(Serialize, JsonSchema)]
+struct JqOutput {
+    result: String,
+}
+
+struct Jq;
+
+impl Tool for Jq {
+    type Args = JqArgs;
+    type Output = JqOutput;
+
+    fn name(&self) -> &'static str {
+        tool::JQ
+    }
+
+    fn description(&self) -> &'static str {
+        "Query JSON or JSON Lines with a jq expression"
+    }
+
+    fn execute(&self, args: JqArgs, ctx: &ToolContext) -> Result<JqOutput> {
+        let config = JqConfig {
+            raw_output: args.raw_output.unwrap_or(false),
+            compact: args.compact.unwrap_or(false),
+            sort_keys: args.sort_keys.unwrap_or(false),
+            slurp: args.slurp.unwrap_or(false),
+        };
+
+        let tool = JqTool::new(config).with_cancel(ctx.cancel.clone());
+
+        let result = if let Some(input) = args.json_input {
+            tool.query(&input, &args.expression)?
+        } else if let Some(file_path) = args.file {
+            tool.query_file(&PathBuf::from(file_path), &args.expression)?
+        } else if let Some(files) = args.files {
+            let paths: Vec<&Path> = files.iter().map(Path::new).collect();
+            tool.query_files(&paths, &args.expression)?
+        } else {
+            return Err(Error::invalid_arguments("Provide json_input, file or files"));
+        };
+
+        Ok(JqOutput { result })
+    }
 }
 
 // ========== HTTP TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct HttpArgs {
+    /// Request URL
     url: String,
+    /// GET, POST, PUT, DELETE, PATCH or HEAD (default: GET)
     method: Option<String>,
+    /// Request headers
     headers: Option<HashMap<String, String>>,
+    /// Request body
     body: Option<String>,
+    /// Timeout in milliseconds
     timeout_ms: Option<u64>,
 }
 
-async fn http_request(arguments: Value) -> Result<String> {
-    let args: HttpArgs = serde_json::from_value(arguments)?;
-    
-    let mut config = HttpConfig::default();
-    if let Some(ms) = args.timeout_ms {
-        config.timeout = Duration::from_millis(ms);
-    }
-    
-    let tool = HttpTool::new(config);
-    
-    use orchestrator_core::tools::http::HttpMethod;
-    let method = match args.method.as_deref().unwrap_or("GET").to_uppercase().as_str() {
-        "POST" => HttpMethod::POST,
-        "PUT" => HttpMethod::PUT,
-        "DELETE" => HttpMethod::DELETE,
-        "PATCH" => HttpMethod::PATCH,
-        "HEAD" => HttpMethod::HEAD,
-        _ => HttpMethod::GET,
-    };
-    
-    let result = tool.request(method, &args.url, args.headers.as_ref(), args.body.as_deref())?;
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "status_code": result.status_code,
-        "headers": result.headers,
-        "body": result.body
-    }))?)
+#[derive(Serialize, JsonSchema)]
+struct HttpOutput {
+    status_code: u16,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+struct Http;
+
+impl Tool for Http {
+    type Args = HttpArgs;
+    type Output = HttpOutput;
+
+    fn name(&self) -> &'static str {
+        tool::HTTP
+    }
+
+    fn description(&self) -> &'static str {
+        "Make an HTTP request"
+    }
+
+    fn is_available(&self) -> bool {
+        command_exists("curl")
+    }
+
+    fn execute(&self, args: HttpArgs, ctx: &ToolContext) -> Result<HttpOutput> {
+        let mut config = HttpConfig::default();
+        if let Some(ms) = args.timeout_ms {
+            config.timeout = Duration::from_millis(ms);
+        }
+
+        let tool = HttpTool::new(config).with_cancel(ctx.cancel.clone());
+
+        use orchestrator_core::tools::http::HttpMethod;
+        let method = match args.method.as_deref().unwrap_or("GET").to_uppercase().as_str() {
+            "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "DELETE" => HttpMethod::DELETE,
+            "PATCH" => HttpMethod::PATCH,
+            "HEAD" => HttpMethod::HEAD,
+            _ => HttpMethod::GET,
+        };
+
+        let result = tool.request(method, &args.url, args.headers.as_ref(), args.body.as_deref())?;
+
+        Ok(HttpOutput {
+            status_code: result.status_code,
+            headers: result.headers,
+            body: result.body,
+        })
+    }
 }
 
 // ========== FILE STATS TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct FileStatsArgs {
+    /// Directory to analyze
     directory: String,
+    /// Maximum directory depth
     max_depth: Option<usize>,
+    /// Also visit files ignored by .gitignore, .ignore and .orchestratorignore (default: false)
+    no_ignore: Option<bool>,
+    /// Include hidden files and directories (default: false)
+    hidden: Option<bool>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FileTypeOutput {
+    extension: String,
+    count: usize,
+    total_lines: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FileStatsOutput {
+    total_files: usize,
+    total_dirs: usize,
+    total_size_bytes: u64,
+    total_lines: usize,
+    file_types: Vec<FileTypeOutput>,
+    /// `[path, size_bytes]` pairs, largest first
+    largest_files: Vec<(String, u64)>,
+}
+
+struct FileStats;
+
+impl Tool for FileStats {
+    type Args = FileStatsArgs;
+    type Output = FileStatsOutput;
+
+    fn name(&self) -> &'static str {
+        tool::FILE_STATS
+    }
+
+    fn description(&self) -> &'static str {
+        "Summarize a directory: file counts, sizes, lines and file types"
+    }
+
+    fn execute(&self, args: FileStatsArgs, ctx: &ToolContext) -> Result<FileStatsOutput> {
+        let tool = FileStatsTool::new()
+            .with_cancel(ctx.cancel.clone())
+            .with_progress(ctx.progress.clone())
+            .with_walk_config(WalkConfig {
+                respect_ignore: !args.no_ignore.unwrap_or(false),
+                include_hidden: args.hidden.unwrap_or(false),
+                ..WalkConfig::default()
+            });
+        let stats = tool.analyze(&PathBuf::from(&args.directory), args.max_depth)?;
+
+        let file_types = stats.file_types.iter().take(10).map(|ft| FileTypeOutput {
+            extension: ft.extension.clone(),
+            count: ft.count,
+            total_lines: ft.total_lines,
+        }).collect();
+
+        Ok(FileStatsOutput {
+            total_files: stats.total_files,
+            total_dirs: stats.total_dirs,
+            total_size_bytes: stats.total_size,
+            total_lines: stats.total_lines,
+            file_types,
+            largest_files: stats.largest_files,
+        })
+    }
+}
+
+// ========== INDEX TOOL ==========
+
+#[derive(Deserialize, JsonSchema)]
+struct IndexArgs {
+    /// Project root to index (default: current directory)
+    directory: Option<String>,
 }
 
-async fn file_stats(arguments: Value) -> Result<String> {
-    let args: FileStatsArgs = serde_json::from_value(arguments)?;
-    
-    let tool = FileStatsTool::new();
-    let stats = tool.analyze(&PathBuf::from(&args.directory), args.max_depth)?;
-    
-    let file_types: Vec<Value> = stats.file_types.iter().take(10).map(|ft| {
-        json!({
-            "extension": ft.extension,
-            "count": ft.count,
-            "total_lines": ft.total_lines
+#[derive(Serialize, JsonSchema)]
+struct IndexOutput {
+    /// Index file
+    path: String,
+    /// Files in the index
+    files: usize,
+    /// Files read because they were new or changed
+    reindexed: usize,
+    /// Files dropped because they no longer exist
+    removed: usize,
+    trigrams: usize,
+}
+
+/// Build or refresh the trigram index used by grep_search and mgrep
+struct Index;
+
+impl Tool for Index {
+    type Args = IndexArgs;
+    type Output = IndexOutput;
+
+    fn name(&self) -> &'static str {
+        tool::INDEX
+    }
+
+    fn description(&self) -> &'static str {
+        "Build or refresh the project's trigram index under .opencode/ so repeated grep_search and mgrep calls skip files that cannot match"
+    }
+
+    fn execute(&self, args: IndexArgs, ctx: &ToolContext) -> Result<IndexOutput> {
+        let root = args
+            .directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+        let (index, stats) = TrigramIndex::update(&root, &ctx.cancel)?;
+
+        Ok(IndexOutput {
+            path: index.root().join(INDEX_FILE).display().to_string(),
+            files: stats.files,
+            reindexed: stats.reindexed,
+            removed: stats.removed,
+            trigrams: stats.trigrams,
         })
-    }).collect();
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "total_files": stats.total_files,
-        "total_dirs": stats.total_dirs,
-        "total_size_bytes": stats.total_size,
-        "total_lines": stats.total_lines,
-        "file_types": file_types,
-        "largest_files": stats.largest_files
-    }))?)
+    }
 }
 
 // ========== GIT TOOLS ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct GitDiffArgs {
+    /// Repository directory
     directory: Option<String>,
+    /// Only show staged changes (default: false)
     staged_only: Option<bool>,
 }
 
-async fn git_diff(arguments: Value) -> Result<String> {
-    let args: GitDiffArgs = serde_json::from_value(arguments)?;
-    
-    let tool = GitTool::new();
-    let repo_path = args.directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    
-    let stats = tool.diff(&repo_path, args.staged_only.unwrap_or(false))?;
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "files_changed": stats.files_changed,
-        "insertions": stats.insertions,
-        "deletions": stats.deletions,
-        "diff": stats.diff_output
-    }))?)
-}
-
-#[derive(Deserialize)]
+#[derive(Serialize, JsonSchema)]
+struct GitDiffOutput {
+    files_changed: usize,
+    insertions: usize,
+    deletions: usize,
+    diff: String,
+}
+
+struct GitDiff;
+
+impl Tool for GitDiff {
+    type Args = GitDiffArgs;
+    type Output = GitDiffOutput;
+
+    fn name(&self) -> &'static str {
+        tool::GIT_DIFF
+    }
+
+    fn description(&self) -> &'static str {
+        "Show git diff and change statistics for a repository"
+    }
+
+    fn is_available(&self) -> bool {
+        command_exists("git")
+    }
+
+    fn execute(&self, args: GitDiffArgs, ctx: &ToolContext) -> Result<GitDiffOutput> {
+        let tool = GitTool::new().with_cancel(ctx.cancel.clone());
+        let repo_path = args.directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let stats = tool.diff(&repo_path, args.staged_only.unwrap_or(false))?;
+
+        Ok(GitDiffOutput {
+            files_changed: stats.files_changed,
+            insertions: stats.insertions,
+            deletions: stats.deletions,
+            diff: stats.diff_output,
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
 struct GitStatusArgs {
+    /// Repository directory
     directory: Option<String>,
 }
 
-async fn git_status(arguments: Value) -> Result<String> {
-    let args: GitStatusArgs = serde_json::from_value(arguments)?;
-    
-    let tool = GitTool::new();
-    let repo_path = args.directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    
-    let files = tool.status(&repo_path)?;
-    let branch = tool.current_branch(&repo_path).unwrap_or_else(|_| "unknown".to_string());
-    
-    let file_list: Vec<Value> = files.iter().map(|f| {
-        json!({
-            "file": f.file,
-            "status": f.status
+#[derive(Serialize, JsonSchema)]
+struct GitFileOutput {
+    file: String,
+    status: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitStatusOutput {
+    branch: String,
+    files: Vec<GitFileOutput>,
+    total_changed: usize,
+}
+
+struct GitStatus;
+
+impl Tool for GitStatus {
+    type Args = GitStatusArgs;
+    type Output = GitStatusOutput;
+
+    fn name(&self) -> &'static str {
+        tool::GIT_STATUS
+    }
+
+    fn description(&self) -> &'static str {
+        "Show git branch and changed files for a repository"
+    }
+
+    fn is_available(&self) -> bool {
+        command_exists("git")
+    }
+
+    fn execute(&self, args: GitStatusArgs, ctx: &ToolContext) -> Result<GitStatusOutput> {
+        let tool = GitTool::new().with_cancel(ctx.cancel.clone());
+        let repo_path = args.directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let files = tool.status(&repo_path)?;
+        let branch = tool.current_branch(&repo_path).unwrap_or_else(|_| "unknown".to_string());
+
+        let file_list = files.iter().map(|f| GitFileOutput {
+            file: f.file.clone(),
+            status: f.status.clone(),
+        }).collect();
+
+        Ok(GitStatusOutput {
+            branch,
+            files: file_list,
+            total_changed: files.len(),
         })
-    }).collect();
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "branch": branch,
-        "files": file_list,
-        "total_changed": files.len()
-    }))?)
+    }
 }
 
 // ========== LSP DIAGNOSTICS TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct LspDiagnosticsArgs {
+    /// Directory to check
     directory: Option<String>,
+    /// Specific file or glob filter
     file: Option<String>,
+    /// Include warnings (default: true)
     include_warnings: Option<bool>,
 }
 
-async fn lsp_diagnostics(arguments: Value) -> Result<String> {
-    let args: LspDiagnosticsArgs = serde_json::from_value(arguments)?;
-    
-    let mut config = DiagnosticsConfig::default();
-    if let Some(include_warnings) = args.include_warnings {
-        config.include_warnings = include_warnings;
-    }
-    
-    let directory = args.directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    
-    let tool = DiagnosticsTool::new(config);
-    let diagnostics = tool.get_diagnostics(&directory, args.file.as_deref())?;
-    
-    if diagnostics.is_empty() {
-        return Ok(json!({"status": status::CLEAN, "message": "No diagnostics found. All clean!"}).to_string());
-    }
-    
-    let errors: Vec<&_> = diagnostics.iter().filter(|d| matches!(d.severity, orchestrator_core::tools::lsp::DiagnosticSeverity::Error)).collect();
-    let warnings: Vec<&_> = diagnostics.iter().filter(|d| matches!(d.severity, orchestrator_core::tools::lsp::DiagnosticSeverity::Warning)).collect();
-
-    
-    let diag_list: Vec<Value> = diagnostics.iter().take(50).map(|d| {
-        json!({
-            "file": d.file,
-            "line": d.line,
-            "column": d.column,
-            "severity": format!("{:?}", d.severity).to_lowercase(),
-            "message": d.message,
-            "source": d.source,
-            "code": d.code
+#[derive(Serialize, JsonSchema)]
+struct DiagnosticOutput {
+    file: String,
+    line: u32,
+    column: u32,
+    /// error, warning, info or hint
+    severity: String,
+    message: String,
+    source: Option<String>,
+    code: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct LspDiagnosticsOutput {
+    /// clean, warning or error
+    status: &'static str,
+    summary: String,
+    diagnostics: Vec<DiagnosticOutput>,
+    total: usize,
+}
+
+struct LspDiagnostics;
+
+impl Tool for LspDiagnostics {
+    type Args = LspDiagnosticsArgs;
+    type Output = LspDiagnosticsOutput;
+
+    fn name(&self) -> &'static str {
+        tool::LSP_DIAGNOSTICS
+    }
+
+    fn description(&self) -> &'static str {
+        "Get LSP diagnostics (errors/warnings) for files"
+    }
+
+    fn is_available(&self) -> bool {
+        command_exists("npx")
+    }
+
+    fn execute(&self, args: LspDiagnosticsArgs, ctx: &ToolContext) -> Result<LspDiagnosticsOutput> {
+        let mut config = DiagnosticsConfig::default();
+        if let Some(include_warnings) = args.include_warnings {
+            config.include_warnings = include_warnings;
+        }
+
+        let directory = args.directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let tool = DiagnosticsTool::new(config).with_cancel(ctx.cancel.clone());
+        let diagnostics = tool.get_diagnostics(&directory, args.file.as_deref())?;
+
+        if diagnostics.is_empty() {
+            return Ok(LspDiagnosticsOutput {
+                status: status::CLEAN,
+                summary: "No diagnostics found. All clean!".to_string(),
+                diagnostics: vec![],
+                total: 0,
+            });
+        }
+
+        let errors = diagnostics.iter().filter(|d| matches!(d.severity, DiagnosticSeverity::Error)).count();
+        let warnings = diagnostics.iter().filter(|d| matches!(d.severity, DiagnosticSeverity::Warning)).count();
+
+        let diag_list = diagnostics.iter().take(50).map(|d| DiagnosticOutput {
+            file: d.file.clone(),
+            line: d.line,
+            column: d.column,
+            severity: format!("{:?}", d.severity).to_lowercase(),
+            message: d.message.clone(),
+            source: d.source.clone(),
+            code: d.code.clone(),
+        }).collect();
+
+        Ok(LspDiagnosticsOutput {
+            status: if errors > 0 { status::ERROR } else if warnings > 0 { status::WARNING } else { status::CLEAN },
+            summary: format!("{} error(s), {} warning(s)", errors, warnings),
+            diagnostics: diag_list,
+            total: diagnostics.len(),
         })
-    }).collect();
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "status": if !errors.is_empty() { status::ERROR } else if !warnings.is_empty() { status::WARNING } else { status::CLEAN },
-        "summary": format!("{} error(s), {} warning(s)", errors.len(), warnings.len()),
-        "diagnostics": diag_list,
-        "total": diagnostics.len()
-    }))?)
+    }
 }
 
 // ========== AST SEARCH TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct AstSearchArgs {
+    /// ast-grep pattern (e.g. 'const $X = $Y')
     pattern: String,
+    /// Directory to search
     directory: Option<String>,
+    /// Language (typescript, javascript, rust, etc)
     lang: Option<String>,
-    include: Option<String>,
-}
-
-async fn ast_search(arguments: Value) -> Result<String> {
-    let args: AstSearchArgs = serde_json::from_value(arguments)?;
-    
-    let directory = args.directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    
-    let tool = AstTool::new(AstConfig::default());
-    let matches = tool.search(&args.pattern, &directory, args.lang.as_deref(), args.include.as_deref())?;
-    
-    if matches.is_empty() {
-        return Ok(json!({"matches": [], "total": 0, "message": "No structural matches found."}).to_string());
-    }
-    
-    let match_list: Vec<Value> = matches.iter().take(50).map(|m| {
-        json!({
-            "file": m.file,
-            "line": m.line,
-            "column": m.column,
-            "content": m.content,
-            "matched_text": m.matched_text
+    #[serde(flatten)]
+    filter: FileFilterArgs,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AstMatchOutput {
+    file: String,
+    line: u32,
+    column: u32,
+    content: String,
+    matched_text: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AstSearchOutput {
+    matches: Vec<AstMatchOutput>,
+    total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+struct AstSearch;
+
+impl Tool for AstSearch {
+    type Args = AstSearchArgs;
+    type Output = AstSearchOutput;
+
+    fn name(&self) -> &'static str {
+        tool::AST_SEARCH
+    }
+
+    fn description(&self) -> &'static str {
+        "Structural code search using ast-grep"
+    }
+
+    fn is_available(&self) -> bool {
+        AstTool::is_available()
+    }
+
+    fn execute(&self, args: AstSearchArgs, ctx: &ToolContext) -> Result<AstSearchOutput> {
+        let directory = args.directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let mut config = AstConfig::default();
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+        let tool = AstTool::new(config).with_cancel(ctx.cancel.clone());
+        let matches = tool.search(&args.pattern, &directory, args.lang.as_deref())?;
+
+        let match_list = matches.iter().take(50).map(|m| AstMatchOutput {
+            file: m.file.clone(),
+            line: m.line,
+            column: m.column,
+            content: m.content.clone(),
+            matched_text: m.matched_text.clone(),
+        }).collect();
+
+        Ok(AstSearchOutput {
+            matches: match_list,
+            total: matches.len(),
+            message: matches.is_empty().then(|| "No structural matches found.".to_string()),
         })
-    }).collect();
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "matches": match_list,
-        "total": matches.len()
-    }))?)
+    }
 }
 
 // ========== AST REPLACE TOOL ==========
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct AstReplaceArgs {
+    /// ast-grep search pattern
     pattern: String,
+    /// ast-grep rewrite pattern
     rewrite: String,
+    /// Directory to modify
     directory: Option<String>,
+    /// Language
     lang: Option<String>,
-    include: Option<String>,
-}
-
-async fn ast_replace(arguments: Value) -> Result<String> {
-    let args: AstReplaceArgs = serde_json::from_value(arguments)?;
-    
-    let directory = args.directory
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    
-    let tool = AstTool::new(AstConfig::default());
-    let result = tool.replace(&args.pattern, &args.rewrite, &directory, args.lang.as_deref(), args.include.as_deref())?;
-    
-    Ok(serde_json::to_string_pretty(&json!({
-        "success": result.success,
-        "message": result.message,
-        "pattern": args.pattern,
-        "rewrite": args.rewrite
-    }))?)
+    #[serde(flatten)]
+    filter: FileFilterArgs,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AstReplaceOutput {
+    success: bool,
+    message: String,
+    pattern: String,
+    rewrite: String,
+    /// Pass to undo_changeset to revert the rewrite
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changeset_id: Option<String>,
+}
+
+struct AstReplace;
+
+impl Tool for AstReplace {
+    type Args = AstReplaceArgs;
+    type Output = AstReplaceOutput;
+
+    fn name(&self) -> &'static str {
+        tool::AST_REPLACE
+    }
+
+    fn description(&self) -> &'static str {
+        "Structural code replace using ast-grep"
+    }
+
+    fn is_available(&self) -> bool {
+        AstTool::is_available()
+    }
+
+    fn execute(&self, args: AstReplaceArgs, ctx: &ToolContext) -> Result<AstReplaceOutput> {
+        let directory = args.directory
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+        let mut config = AstConfig::default();
+        args.filter.apply(
+            &mut config.include_patterns,
+            &mut config.exclude_patterns,
+            &mut config.file_types,
+            &mut config.exclude_file_types,
+        );
+        let tool = AstTool::new(config)
+            .with_cancel(ctx.cancel.clone())
+            .with_journal(ctx.journal(journal_root(Some(&directory))));
+        let result = tool.replace(&args.pattern, &args.rewrite, &directory, args.lang.as_deref())?;
+
+        Ok(AstReplaceOutput {
+            success: result.success,
+            message: result.message,
+            pattern: args.pattern,
+            rewrite: args.rewrite,
+            changeset_id: result.changeset.map(|c| c.id),
+        })
+    }
 }
@@ -0,0 +1,315 @@
+//! Transports for `orchestrator serve`
+//!
+//! The default transport is stdio: one client, one request loop. With
+//! `--listen unix:/path.sock` or `--listen tcp:127.0.0.1:PORT` the server
+//! accepts any number of clients and runs a separate [`server::run`] loop per
+//! connection, so request ids, cancellation and the in-flight limit are all
+//! scoped to the connection that sent the request.
+//!
+//! On Ctrl-C (or SIGTERM on unix) the listener stops accepting, every
+//! connection drains its in-flight requests as for `exit`, and the unix
+//! socket file is removed.
+
+use crate::server::{self, ServeOptions};
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
+
+/// Where `serve` reads requests from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Listen {
+    /// Newline-delimited JSON on stdin/stdout
+    #[default]
+    Stdio,
+    /// TCP address, e.g. `127.0.0.1:7777`
+    Tcp(String),
+    /// Unix domain socket path
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "stdio" {
+            return Ok(Self::Stdio);
+        }
+        match s.split_once(':') {
+            Some(("tcp", addr)) if !addr.is_empty() => Ok(Self::Tcp(addr.to_string())),
+            Some(("unix", path)) if !path.is_empty() => Ok(Self::Unix(PathBuf::from(path))),
+            _ => bail!(
+                "Invalid listen address: {} (expected stdio, unix:/path.sock or tcp:HOST:PORT)",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdio => write!(f, "stdio"),
+            Self::Tcp(addr) => write!(f, "tcp:{}", addr),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Serve requests on the transport selected by `options.listen`
+pub async fn listen(options: ServeOptions) -> Result<()> {
+    match options.listen.clone() {
+        Listen::Stdio => {
+            server::run(
+                BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+                options,
+                shutdown_signal(),
+            )
+            .await
+        }
+        Listen::Tcp(addr) => {
+            let listener = TcpListener::bind(&addr)
+                .await
+                .with_context(|| format!("Failed to listen on tcp:{}", addr))?;
+            let local = listener.local_addr()?;
+            if !local.ip().is_loopback() {
+                warn!("Listening on non-loopback address {}: tools can read and modify files", local);
+            }
+            info!("Listening on tcp:{}", local);
+            accept_loop(listener, options, shutdown_signal()).await
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            remove_stale_socket(&path).await?;
+            let listener = tokio::net::UnixListener::bind(&path)
+                .with_context(|| format!("Failed to listen on unix:{}", path.display()))?;
+            info!("Listening on unix:{}", path.display());
+            let result = accept_loop(listener, options, shutdown_signal()).await;
+            let _ = std::fs::remove_file(&path);
+            result
+        }
+        #[cfg(not(unix))]
+        Listen::Unix(_) => bail!("Unix sockets are not supported on this platform"),
+    }
+}
+
+/// A listener whose connections can be split into a reader and a writer
+pub trait Listener {
+    type Reader: AsyncRead + Unpin + Send + 'static;
+    type Writer: AsyncWrite + Unpin + Send + 'static;
+
+    /// Accept a connection, returning its halves and a peer description for logs
+    fn accept_split(
+        &self,
+    ) -> impl Future<Output = io::Result<(Self::Reader, Self::Writer, String)>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Reader = tokio::net::tcp::OwnedReadHalf;
+    type Writer = tokio::net::tcp::OwnedWriteHalf;
+
+    async fn accept_split(&self) -> io::Result<(Self::Reader, Self::Writer, String)> {
+        let (stream, peer) = self.accept().await?;
+        let (reader, writer) = stream.into_split();
+        Ok((reader, writer, peer.to_string()))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for tokio::net::UnixListener {
+    type Reader = tokio::net::unix::OwnedReadHalf;
+    type Writer = tokio::net::unix::OwnedWriteHalf;
+
+    async fn accept_split(&self) -> io::Result<(Self::Reader, Self::Writer, String)> {
+        let (stream, _) = self.accept().await?;
+        let (reader, writer) = stream.into_split();
+        Ok((reader, writer, "unix socket client".to_string()))
+    }
+}
+
+/// Accept connections until `shutdown` resolves, one request loop each
+pub async fn accept_loop<L, S>(listener: L, options: ServeOptions, shutdown: S) -> Result<()>
+where
+    L: Listener,
+    S: Future<Output = ()>,
+{
+    let mut connections = JoinSet::new();
+    let (stop, stopped) = watch::channel(false);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept_split() => match accepted {
+                Ok((reader, writer, peer)) => {
+                    info!("Connection from {}", peer);
+                    let options = options.clone();
+                    let mut stopped = stopped.clone();
+                    connections.spawn(async move {
+                        let stop = async move {
+                            let _ = stopped.wait_for(|stop| *stop).await;
+                        };
+                        let reader = BufReader::new(reader);
+                        if let Err(e) = server::run(reader, writer, options, stop).await {
+                            error!("Connection from {} failed: {}", peer, e);
+                        }
+                        debug!("Connection from {} closed", peer);
+                    });
+                }
+                Err(e) => error!("Accept failed: {}", e),
+            },
+            Some(joined) = connections.join_next(), if !connections.is_empty() => {
+                if let Err(e) = joined {
+                    error!("Connection task failed: {}", e);
+                }
+            }
+        }
+    }
+
+    info!("Shutting down {} connection(s)", connections.len());
+    let _ = stop.send(true);
+    while let Some(joined) = connections.join_next().await {
+        if let Err(e) = joined {
+            error!("Connection task failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on unix
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Remove a socket file left behind by a server that did not exit cleanly.
+/// Refuses to touch a socket that still has a live server behind it.
+#[cfg(unix)]
+async fn remove_stale_socket(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        bail!("{} exists and is not a socket", path.display());
+    }
+    if tokio::net::UnixStream::connect(path).await.is_ok() {
+        bail!("Another server is already listening on {}", path.display());
+    }
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove stale socket {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orchestrator_core::constants::{field, rpc};
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn test_parse_listen() {
+        assert_eq!("stdio".parse::<Listen>().unwrap(), Listen::Stdio);
+        assert_eq!(
+            "tcp:127.0.0.1:7777".parse::<Listen>().unwrap(),
+            Listen::Tcp("127.0.0.1:7777".to_string())
+        );
+        assert_eq!(
+            "unix:/tmp/o.sock".parse::<Listen>().unwrap(),
+            Listen::Unix(PathBuf::from("/tmp/o.sock"))
+        );
+        assert!("tcp:".parse::<Listen>().is_err());
+        assert!("http://x".parse::<Listen>().is_err());
+        assert_eq!(Listen::Tcp("h:1".to_string()).to_string(), "tcp:h:1");
+    }
+
+    async fn round_trip<R, W>(reader: R, mut writer: W, request: Value) -> Value
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut line = serde_json::to_string(&request).unwrap();
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await.unwrap();
+        let mut lines = BufReader::new(reader).lines();
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connections_are_independent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(accept_loop(listener, ServeOptions::default(), async {
+            let _ = stopped.await;
+        }));
+
+        let request = json!({"jsonrpc": rpc::VERSION, field::ID: 1, field::METHOD: rpc::TOOLS_LIST});
+        let first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let second = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (r1, w1) = first.into_split();
+        let (r2, w2) = second.into_split();
+
+        // Both clients use id 1; each gets its own answer
+        let (a, b) = tokio::join!(round_trip(r1, w1, request.clone()), round_trip(r2, w2, request));
+        assert_eq!(a[field::ID], 1);
+        assert_eq!(b[field::ID], 1);
+        assert!(a[field::RESULT]["tools"].is_array());
+        assert_eq!(a, b);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_round_trip() {
+        let path = std::env::temp_dir().join(format!("orchestrator-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(accept_loop(listener, ServeOptions::default(), async {
+            let _ = stopped.await;
+        }));
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (reader, writer) = stream.into_split();
+        let request = json!({"jsonrpc": rpc::VERSION, field::ID: "a", field::METHOD: rpc::INITIALIZE});
+        let resp = round_trip(reader, writer, request).await;
+        assert_eq!(resp[field::RESULT]["serverInfo"]["name"], "orchestrator");
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        // A socket file without a server behind it is cleaned up on the next start
+        assert!(remove_stale_socket(&path).await.is_ok());
+        assert!(!path.exists());
+    }
+}
@@ -109,35 +109,34 @@ impl ConfigLoader {
                 continue;
             }
 
-            if !in_string && c == '/' {
-                if let Some(&next) = chars.peek() {
-                    if next == '/' {
-                        // Line comment - skip until newline
-                        chars.next();
-                        while let Some(&ch) = chars.peek() {
-                            if ch == '\n' {
-                                break;
-                            }
-                            chars.next();
+            if !in_string
+                && c == '/'
+                && let Some(&next) = chars.peek()
+            {
+                if next == '/' {
+                    // Line comment - skip until newline
+                    chars.next();
+                    while let Some(&ch) = chars.peek() {
+                        if ch == '\n' {
+                            break;
                         }
-                        continue;
-                    } else if next == '*' {
-                        // Block comment - skip until */
                         chars.next();
-                        loop {
-                            match chars.next() {
-                                Some('*') => {
-                                    if chars.peek() == Some(&'/') {
-                                        chars.next();
-                                        break;
-                                    }
-                                }
-                                None => break,
-                                _ => {}
+                    }
+                    continue;
+                } else if next == '*' {
+                    // Block comment - skip until */
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            Some('*') if chars.peek() == Some(&'/') => {
+                                chars.next();
+                                break;
                             }
+                            None => break,
+                            _ => {}
                         }
-                        continue;
                     }
+                    continue;
                 }
             }
 
@@ -170,6 +169,7 @@ impl ConfigLoader {
         // Append lists
         merged.disabled_hooks.extend(b.disabled_hooks);
         merged.disabled_skills.extend(b.disabled_skills);
+        merged.disabled_tools.extend(b.disabled_tools);
 
         // Override complex configs
         if b.schema.is_some() {
@@ -27,6 +27,10 @@ pub struct OrchestratorConfig {
     #[serde(default)]
     pub disabled_skills: Vec<String>,
 
+    /// Tools hidden from `tools/list` and refused by `tools/call`
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+
     /// Skill configurations
     #[serde(default)]
     pub skills: HashMap<String, SkillConfig>,
@@ -76,6 +80,7 @@ impl Default for OrchestratorConfig {
             agents: HashMap::new(),
             disabled_hooks: Vec::new(),
             disabled_skills: Vec::new(),
+            disabled_tools: Vec::new(),
             skills: HashMap::new(),
             categories: HashMap::new(),
             claude_code: ClaudeCodeConfig::default(),
@@ -33,10 +33,16 @@ pub mod tool {
     pub const GLOB_SEARCH: &str = "glob_search";
     pub const MGREP: &str = "mgrep";
     pub const SED_REPLACE: &str = "sed_replace";
+    pub const UNDO_CHANGESET: &str = "undo_changeset";
+    pub const UNDO_LAST: &str = "undo_last";
+    pub const LIST_CHANGESETS: &str = "list_changesets";
+    pub const APPLY_PATCH: &str = "apply_patch";
     pub const DIFF: &str = "diff";
+    pub const MERGE3: &str = "merge3";
     pub const JQ: &str = "jq";
     pub const HTTP: &str = "http";
     pub const FILE_STATS: &str = "file_stats";
+    pub const INDEX: &str = "index";
     pub const GIT_DIFF: &str = "git_diff";
     pub const GIT_STATUS: &str = "git_status";
     pub const LSP_DIAGNOSTICS: &str = "lsp_diagnostics";
@@ -52,9 +58,25 @@ pub mod rpc {
     pub const INITIALIZE: &str = "initialize";
     pub const TOOLS_LIST: &str = "tools/list";
     pub const TOOLS_CALL: &str = "tools/call";
+    pub const PING: &str = "ping";
+    pub const SHUTDOWN: &str = "shutdown";
+    pub const EXIT: &str = "exit";
+    pub const NOTIFICATION_CANCELLED: &str = "notifications/cancelled";
+    pub const NOTIFICATION_PROGRESS: &str = "notifications/progress";
+    pub const RESOURCES_LIST: &str = "resources/list";
+    pub const RESOURCES_READ: &str = "resources/read";
+    pub const RESOURCES_TEMPLATES_LIST: &str = "resources/templates/list";
+    pub const RESOURCES_SUBSCRIBE: &str = "resources/subscribe";
+    pub const RESOURCES_UNSUBSCRIBE: &str = "resources/unsubscribe";
+    pub const NOTIFICATION_RESOURCE_UPDATED: &str = "notifications/resources/updated";
     
     // Protocol Metadata
-    pub const PROTOCOL_VERSION: &str = "2024-11-05";
+    /// Newest protocol version, offered when the client asks for one we do not know
+    pub const PROTOCOL_VERSION: &str = "2025-06-18";
+    /// Every protocol version the server can speak, newest first
+    pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+    /// First version whose clients understand `structuredContent` and `outputSchema`
+    pub const STRUCTURED_CONTENT_VERSION: &str = "2025-06-18";
 }
 
 /// Field names used in JSON RPC requests and responses.
@@ -65,8 +87,49 @@ pub mod field {
     pub const PARAMS: &str = "params";
     pub const RESULT: &str = "result";
     pub const ERROR: &str = "error";
+    pub const CODE: &str = "code";
+    pub const MESSAGE: &str = "message";
+    pub const DATA: &str = "data";
     pub const CONTENT: &str = "content";
     pub const TYPE: &str = "type";
     pub const TEXT: &str = "text";
     pub const IS_ERROR: &str = "isError";
+    pub const STRUCTURED_CONTENT: &str = "structuredContent";
+    pub const OUTPUT_SCHEMA: &str = "outputSchema";
+    pub const META: &str = "_meta";
+    pub const PROGRESS_TOKEN: &str = "progressToken";
+    pub const SESSION_ID: &str = "sessionId";
+    pub const URI: &str = "uri";
+    pub const MIME_TYPE: &str = "mimeType";
+    pub const CURSOR: &str = "cursor";
+    pub const NEXT_CURSOR: &str = "nextCursor";
+}
+
+/// JSON RPC error codes.
+///
+/// The first block is defined by the JSON-RPC 2.0 spec. Application codes use
+/// the implementation-defined -32000..-32099 range, one per
+/// `orchestrator_core::Error` variant. They are part of the protocol: never
+/// renumber an existing code, only append new ones.
+pub mod error_code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    pub const CONFIG: i64 = -32001;
+    pub const AGENT: i64 = -32002;
+    pub const TOOL: i64 = -32003;
+    pub const HOOK: i64 = -32004;
+    pub const IO: i64 = -32005;
+    pub const JSON: i64 = -32006;
+    pub const REGEX: i64 = -32007;
+    pub const SESSION_NOT_FOUND: i64 = -32008;
+    pub const LSP: i64 = -32009;
+    pub const BACKGROUND_TASK: i64 = -32010;
+    pub const SKILL: i64 = -32011;
+    pub const MCP: i64 = -32012;
+    pub const CANCELLED: i64 = -32013;
+    pub const RESOURCE_NOT_FOUND: i64 = -32014;
 }
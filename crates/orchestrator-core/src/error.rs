@@ -1,5 +1,6 @@
 //! Error types for OpenCode Orchestrator
 
+use crate::constants::error_code;
 use thiserror::Error;
 
 /// Result type alias for orchestrator operations
@@ -43,6 +44,21 @@ pub enum Error {
 
     #[error("MCP error: {0}")]
     Mcp(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(String),
+
+    #[error("Tool not available: {0}")]
+    ToolUnavailable(String),
+
+    #[error("Resource not found: {0}")]
+    ResourceNotFound(String),
 }
 
 impl Error {
@@ -61,4 +77,73 @@ impl Error {
     pub fn hook(msg: impl Into<String>) -> Self {
         Self::Hook(msg.into())
     }
+
+    pub fn invalid_arguments(msg: impl Into<String>) -> Self {
+        Self::InvalidArguments(msg.into())
+    }
+
+    /// Stable JSON RPC error code for this error (see [`error_code`])
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Config(_) => error_code::CONFIG,
+            Self::Agent(_) => error_code::AGENT,
+            Self::Tool(_) => error_code::TOOL,
+            Self::Hook(_) => error_code::HOOK,
+            Self::Io(_) => error_code::IO,
+            Self::Json(_) => error_code::JSON,
+            Self::Regex(_) => error_code::REGEX,
+            Self::SessionNotFound(_) => error_code::SESSION_NOT_FOUND,
+            Self::Lsp(_) => error_code::LSP,
+            Self::BackgroundTask(_) => error_code::BACKGROUND_TASK,
+            Self::Skill(_) => error_code::SKILL,
+            Self::Mcp(_) => error_code::MCP,
+            Self::Cancelled => error_code::CANCELLED,
+            Self::ResourceNotFound(_) => error_code::RESOURCE_NOT_FOUND,
+            Self::UnknownTool(_) | Self::InvalidArguments(_) | Self::ToolUnavailable(_) => {
+                error_code::INVALID_PARAMS
+            }
+        }
+    }
+
+    /// Short machine-readable name of the variant, sent as `error.data.kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Config(_) => "config",
+            Self::Agent(_) => "agent",
+            Self::Tool(_) => "tool",
+            Self::Hook(_) => "hook",
+            Self::Io(_) => "io",
+            Self::Json(_) => "json",
+            Self::Regex(_) => "regex",
+            Self::SessionNotFound(_) => "session_not_found",
+            Self::Lsp(_) => "lsp",
+            Self::BackgroundTask(_) => "background_task",
+            Self::Skill(_) => "skill",
+            Self::Mcp(_) => "mcp",
+            Self::Cancelled => "cancelled",
+            Self::UnknownTool(_) => "unknown_tool",
+            Self::InvalidArguments(_) => "invalid_arguments",
+            Self::ToolUnavailable(_) => "tool_unavailable",
+            Self::ResourceNotFound(_) => "resource_not_found",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_application_range() {
+        let errors = [
+            Error::config("x"),
+            Error::tool("x"),
+            Error::Io(std::io::Error::other("x")),
+            Error::Cancelled,
+        ];
+        for err in &errors {
+            assert!((-32099..=-32000).contains(&err.code()), "{}", err.kind());
+        }
+        assert_eq!(Error::invalid_arguments("x").code(), error_code::INVALID_PARAMS);
+    }
 }
@@ -1,9 +1,15 @@
 //! AST tools - structural search and replace using ast-grep
 
-use crate::Result;
+use super::cancel::CancelToken;
+use super::changeset::{ChangeSet, Journal, Snapshot};
+use super::process::{find_command, run_command};
+use crate::constants::tool;
+use crate::{Error, Result};
+use ignore::types::TypesBuilder;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
 use std::time::Duration;
 
 /// A single AST match result
@@ -21,6 +27,15 @@ pub struct AstMatch {
 pub struct AstConfig {
     pub timeout: Duration,
     pub max_results: usize,
+    /// Only visit files matching these globs
+    pub include_patterns: Vec<String>,
+    /// Skip files matching these globs
+    pub exclude_patterns: Vec<String>,
+    /// Named file types to include, as in ripgrep's `--type`. ast-grep has
+    /// a single glob list, so their globs add to `include_patterns`.
+    pub file_types: Vec<String>,
+    /// Named file types to skip
+    pub exclude_file_types: Vec<String>,
 }
 
 impl Default for AstConfig {
@@ -28,6 +43,10 @@ impl Default for AstConfig {
         Self {
             timeout: Duration::from_secs(30),
             max_results: 100,
+            include_patterns: vec![],
+            exclude_patterns: vec![],
+            file_types: vec![],
+            exclude_file_types: vec![],
         }
     }
 }
@@ -35,11 +54,35 @@ impl Default for AstConfig {
 /// AST tool for structural search and replace
 pub struct AstTool {
     config: AstConfig,
+    cancel: CancelToken,
+    journal: Option<Journal>,
 }
 
 impl AstTool {
     pub fn new(config: AstConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+            journal: None,
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Whether ast-grep is installed, so the tools can run without
+    /// downloading it
+    pub fn is_available() -> bool {
+        ast_grep().is_some()
+    }
+
+    /// Record the files [`replace`](Self::replace) changes in `journal`
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
     }
 
     /// Search for structural patterns using ast-grep
@@ -48,13 +91,21 @@ impl AstTool {
         pattern: &str,
         directory: &Path,
         lang: Option<&str>,
-        include: Option<&str>,
     ) -> Result<Vec<AstMatch>> {
+        let stdout = self.search_json(pattern, directory, lang)?;
+        Ok(self.parse_ast_grep_output(&stdout))
+    }
+
+    /// Raw `--json` output of ast-grep for `pattern`
+    fn search_json(
+        &self,
+        pattern: &str,
+        directory: &Path,
+        lang: Option<&str>,
+    ) -> Result<String> {
         let lang = lang.unwrap_or("typescript");
         
         let mut args = vec![
-            "-y".to_string(),
-            "ast-grep".to_string(),
             "run".to_string(),
             "--pattern".to_string(),
             pattern.to_string(),
@@ -63,19 +114,16 @@ impl AstTool {
             "--json".to_string(),
         ];
 
-        if let Some(inc) = include {
-            args.push("--include".to_string());
-            args.push(inc.to_string());
-        }
+        args.extend(self.glob_args()?);
 
-        let output = Command::new("npx")
-            .args(&args)
-            .current_dir(directory)
-            .output()?;
+        let output = run_command(
+            Command::new(program()?).args(&args).current_dir(directory),
+            None,
+            &self.cancel,
+            Some(self.config.timeout),
+        )?;
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        
-        Ok(self.parse_ast_grep_output(&stdout))
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
     }
 
     /// Replace structural patterns using ast-grep
@@ -85,13 +133,23 @@ impl AstTool {
         rewrite: &str,
         directory: &Path,
         lang: Option<&str>,
-        include: Option<&str>,
     ) -> Result<AstReplaceResult> {
+        // ast-grep rewrites the files itself, so remember the ones it will
+        // touch beforehand
+        let snapshot = match &self.journal {
+            Some(_) => {
+                let stdout = self.search_json(pattern, directory, lang)?;
+                let mut snapshot = Snapshot::new(tool::AST_REPLACE);
+                for file in matched_files(&stdout, directory) {
+                    snapshot.capture(&file)?;
+                }
+                Some(snapshot)
+            }
+            None => None,
+        };
         let lang = lang.unwrap_or("typescript");
         
         let mut args = vec![
-            "-y".to_string(),
-            "ast-grep".to_string(),
             "run".to_string(),
             "--pattern".to_string(),
             pattern.to_string(),
@@ -102,22 +160,26 @@ impl AstTool {
             "--update-all".to_string(),
         ];
 
-        if let Some(inc) = include {
-            args.push("--include".to_string());
-            args.push(inc.to_string());
-        }
+        args.extend(self.glob_args()?);
 
-        let output = Command::new("npx")
-            .args(&args)
-            .current_dir(directory)
-            .output()?;
+        let output = run_command(
+            Command::new(program()?).args(&args).current_dir(directory),
+            None,
+            &self.cancel,
+            Some(self.config.timeout),
+        )?;
 
         let success = output.status.success();
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let changeset = match (snapshot, &self.journal) {
+            (Some(snapshot), Some(journal)) => snapshot.commit(journal)?,
+            _ => None,
+        };
 
         Ok(AstReplaceResult {
             success,
+            changeset,
             message: if success {
                 format!("AST replace completed. Pattern: `{}` -> `{}`", pattern, rewrite)
             } else {
@@ -128,6 +190,16 @@ impl AstTool {
         })
     }
 
+    /// `--globs` arguments for the file filters; excludes are negated
+    fn glob_args(&self) -> Result<Vec<String>> {
+        let includes = self.config.include_patterns.iter().cloned().chain(type_globs(&self.config.file_types)?);
+        let excludes = self.config.exclude_patterns.iter().cloned().chain(type_globs(&self.config.exclude_file_types)?);
+        Ok(includes
+            .chain(excludes.map(|glob| format!("!{}", glob)))
+            .flat_map(|glob| ["--globs".to_string(), glob])
+            .collect())
+    }
+
     /// Parse ast-grep JSON output
     fn parse_ast_grep_output(&self, output: &str) -> Vec<AstMatch> {
         let mut matches = Vec::new();
@@ -159,11 +231,74 @@ impl Default for AstTool {
 #[derive(Debug, Clone, Serialize)]
 pub struct AstReplaceResult {
     pub success: bool,
+    /// Files rewritten, if the tool has a journal and anything changed
+    pub changeset: Option<ChangeSet>,
     pub message: String,
     pub stdout: String,
     pub stderr: String,
 }
 
+/// Globs of ripgrep's named file types
+fn type_globs(names: &[String]) -> Result<Vec<String>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    let definitions = builder.definitions();
+    let mut globs = Vec::new();
+    for name in names {
+        let definition = definitions
+            .iter()
+            .find(|d| d.name() == name)
+            .ok_or_else(|| Error::invalid_arguments(format!("Unknown file type '{}'", name)))?;
+        globs.extend(definition.globs().iter().cloned());
+    }
+    Ok(globs)
+}
+
+/// The ast-grep executable: `ast-grep` or `sg` on `PATH`, else the one
+/// in the working directory's `node_modules`. `sg` only counts if it is
+/// ast-grep and not the shadow-utils command of the same name.
+fn ast_grep() -> Option<&'static Path> {
+    static AST_GREP: OnceLock<Option<PathBuf>> = OnceLock::new();
+    AST_GREP
+        .get_or_init(|| {
+            find_command("ast-grep")
+                .or_else(|| find_command("sg").filter(|sg| is_ast_grep(sg)))
+                .or_else(|| {
+                    let local = std::env::current_dir().ok()?.join("node_modules/.bin/ast-grep");
+                    local.is_file().then_some(local)
+                })
+        })
+        .as_deref()
+}
+
+fn is_ast_grep(program: &Path) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("ast-grep"))
+}
+
+fn program() -> Result<&'static Path> {
+    ast_grep().ok_or_else(|| Error::tool("ast-grep is not installed"))
+}
+
+/// Distinct files in ast-grep `--json` output, which names them relative
+/// to `directory`
+fn matched_files(output: &str, directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = serde_json::from_str::<Vec<AstGrepMatch>>(output)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| directory.join(m.file))
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
 #[derive(Deserialize)]
 struct AstGrepMatch {
     file: String,
@@ -201,4 +336,35 @@ mod tests {
         assert_eq!(m.matched_text, "code");
         assert_eq!(m.file, "test.js");
     }
+
+    #[test]
+    fn test_matched_files() {
+        let match_at = |file: &str| {
+            format!(
+                r#"{{"file": "{}", "text": "x", "range": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 1}}}}}}"#,
+                file
+            )
+        };
+        let output = format!("[{}, {}, {}]", match_at("b.ts"), match_at("a.ts"), match_at("b.ts"));
+        let files = matched_files(&output, Path::new("/src"));
+        assert_eq!(files, [PathBuf::from("/src/a.ts"), PathBuf::from("/src/b.ts")]);
+        assert!(matched_files("not json", Path::new("/src")).is_empty());
+    }
+
+    #[test]
+    fn test_glob_args() {
+        let tool = AstTool::new(AstConfig {
+            include_patterns: vec!["src/**".to_string()],
+            exclude_patterns: vec!["**/*.test.ts".to_string()],
+            exclude_file_types: vec!["json".to_string()],
+            ..Default::default()
+        });
+        let args = tool.glob_args().unwrap();
+        assert_eq!(args[..4], ["--globs", "src/**", "--globs", "!**/*.test.ts"]);
+        assert!(args[4..].chunks(2).all(|pair| pair[0] == "--globs" && pair[1].starts_with('!')));
+        assert!(args.contains(&"!*.json".to_string()));
+
+        let unknown = AstTool::new(AstConfig { file_types: vec!["nope".to_string()], ..Default::default() });
+        assert!(matches!(unknown.glob_args(), Err(Error::InvalidArguments(_))));
+    }
 }
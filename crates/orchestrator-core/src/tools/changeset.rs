@@ -0,0 +1,516 @@
+//! Change sets: groups of file writes that succeed or fail together
+//!
+//! A [`Transaction`] is given the new contents of every file up front. On
+//! commit it records each file's original bytes under
+//! `.opencode/orchestrator/journal/<id>/`, then replaces the files one by one
+//! through a temporary file and a rename, so no file is ever half written.
+//! If any write fails, the files already written are put back. Tools whose
+//! writes happen in another process take a [`Snapshot`] of the files first
+//! instead.
+//!
+//! The [`Journal`] keeps the pre-images, tagged with the session and request
+//! that made the change, so it can be reverted later, even after a restart.
+
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Journal directory, relative to the project root
+pub const JOURNAL_DIR: &str = ".opencode/orchestrator/journal";
+
+const MANIFEST: &str = "changeset.json";
+
+/// Files changed together by one tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub id: String,
+    /// Tool that made the change
+    pub tool: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// JSON RPC id of the tool call
+    #[serde(default)]
+    pub request_id: Option<String>,
+    pub files: Vec<FileChange>,
+}
+
+/// Where change sets are recorded, and on whose behalf
+#[derive(Debug, Clone)]
+pub struct Journal {
+    root: PathBuf,
+    session_id: Option<String>,
+    request_id: Option<String>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+/// One file of a change set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Whether the file existed before; undoing the change deletes it otherwise
+    pub existed: bool,
+    /// Hash of the contents written, to notice later edits
+    written: Option<u64>,
+}
+
+/// New contents for a set of files, written all at once by [`commit`](Self::commit)
+#[derive(Debug, Default)]
+pub struct Transaction {
+    tool: String,
+    /// `None` deletes the file
+    writes: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl Transaction {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Replace the contents of `path`, creating it if needed
+    pub fn write(&mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        self.writes.push((path.into(), Some(contents.into())));
+    }
+
+    /// Delete `path`
+    pub fn remove(&mut self, path: impl Into<PathBuf>) {
+        self.writes.push((path.into(), None));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Record the files' current contents in `journal`, then apply every
+    /// write. Either all files change or none do.
+    pub fn commit(self, journal: &Journal) -> Result<ChangeSet> {
+        let mut entry = journal.create(&self.tool)?;
+        let recorded = self.writes.iter().try_for_each(|(path, contents)| {
+            let original = read_existing(&std::path::absolute(path)?)?;
+            entry.add(path, original, contents.as_deref())
+        });
+        let changeset = match recorded.and_then(|()| entry.save()) {
+            Ok(changeset) => changeset,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&entry.dir);
+                return Err(e);
+            }
+        };
+        let writes = self.writes.into_iter().zip(&changeset.files).map(|((_, contents), file)| (file.path.clone(), contents));
+        if let Err(e) = apply(writes.collect()) {
+            let _ = fs::remove_dir_all(&entry.dir);
+            return Err(e);
+        }
+        Ok(changeset)
+    }
+}
+
+/// Contents of files about to be changed by something other than a
+/// [`Transaction`], such as an external program
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    tool: String,
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl Snapshot {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Remember the current contents of `path`, or that it does not exist
+    pub fn capture(&mut self, path: &Path) -> Result<()> {
+        let path = std::path::absolute(path)?;
+        if !self.files.iter().any(|(p, _)| *p == path) {
+            let original = read_existing(&path)?;
+            self.files.push((path, original));
+        }
+        Ok(())
+    }
+
+    /// Remember `original` as the contents of `path` before the change
+    pub fn add(&mut self, path: &Path, original: Vec<u8>) -> Result<()> {
+        let path = std::path::absolute(path)?;
+        if !self.files.iter().any(|(p, _)| *p == path) {
+            self.files.push((path, Some(original)));
+        }
+        Ok(())
+    }
+
+    /// Record the files that changed since they were captured in `journal`.
+    /// `None` if none did.
+    pub fn commit(self, journal: &Journal) -> Result<Option<ChangeSet>> {
+        let changed: Vec<_> = self
+            .files
+            .into_iter()
+            .filter_map(|(path, original)| {
+                let current = read_existing(&path).ok()?;
+                (current != original).then_some((path, original, current))
+            })
+            .collect();
+        if changed.is_empty() {
+            return Ok(None);
+        }
+
+        let mut entry = journal.create(&self.tool)?;
+        let recorded = changed
+            .into_iter()
+            .try_for_each(|(path, original, current)| entry.add(&path, original, current.as_deref()));
+        match recorded.and_then(|()| entry.save()) {
+            Ok(changeset) => Ok(Some(changeset)),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&entry.dir);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A change set being written to the journal
+struct Entry {
+    dir: PathBuf,
+    changeset: ChangeSet,
+}
+
+impl Entry {
+    /// Save the pre-image of `path` and remember what was written to it
+    fn add(&mut self, path: &Path, original: Option<Vec<u8>>, written: Option<&[u8]>) -> Result<()> {
+        let index = self.changeset.files.len();
+        if let Some(original) = &original {
+            fs::write(self.dir.join(format!("{}.orig", index)), original)?;
+        }
+        self.changeset.files.push(FileChange {
+            path: std::path::absolute(path)?,
+            existed: original.is_some(),
+            written: written.map(hash),
+        });
+        Ok(())
+    }
+
+    fn save(&self) -> Result<ChangeSet> {
+        write_atomic(&self.dir.join(MANIFEST), &serde_json::to_vec_pretty(&self.changeset)?)?;
+        Ok(self.changeset.clone())
+    }
+}
+
+impl Journal {
+    /// Journal of the project at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            session_id: None,
+            request_id: None,
+        }
+    }
+
+    /// Tag new change sets with `session_id` and scope [`undo_last`](Self::undo_last) to it
+    pub fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Tag new change sets with the JSON RPC id of the tool call
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Load change set `id`
+    pub fn load(&self, id: &str) -> Result<ChangeSet> {
+        let manifest = self.dir(id)?.join(MANIFEST);
+        match fs::read(&manifest) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(Error::ResourceNotFound(format!("Change set '{}'", id)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Change sets still recorded, newest first; only this session's unless
+    /// `all_sessions` is set
+    pub fn list(&self, all_sessions: bool) -> Result<Vec<ChangeSet>> {
+        let entries = match fs::read_dir(self.root.join(JOURNAL_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut changesets = Vec::new();
+        for entry in entries {
+            let id = entry?.file_name().to_string_lossy().into_owned();
+            // Entries being written or removed have no manifest
+            if let Ok(changeset) = self.load(&id)
+                && (all_sessions || changeset.session_id == self.session_id)
+            {
+                changesets.push(changeset);
+            }
+        }
+        changesets.sort_by_key(|c| std::cmp::Reverse(order(&c.id)));
+        Ok(changesets)
+    }
+
+    /// Restore every file of change set `id` to its contents before the
+    /// change and drop it from the journal. Refuses if any file was edited
+    /// since, unless `force` is set.
+    pub fn undo(&self, id: &str, force: bool) -> Result<ChangeSet> {
+        let changeset = self.load(id)?;
+        let dir = self.dir(id)?;
+
+        if !force {
+            let edited: Vec<String> = changeset
+                .files
+                .iter()
+                .filter(|file| fs::read(&file.path).ok().as_deref().map(hash) != file.written)
+                .map(|file| file.path.display().to_string())
+                .collect();
+            if !edited.is_empty() {
+                return Err(Error::tool(format!(
+                    "Files changed since change set '{}': {}",
+                    id,
+                    edited.join(", ")
+                )));
+            }
+        }
+
+        let mut writes = Vec::with_capacity(changeset.files.len());
+        for (i, file) in changeset.files.iter().enumerate() {
+            let original = if file.existed {
+                Some(fs::read(dir.join(format!("{}.orig", i)))?)
+            } else {
+                None
+            };
+            writes.push((file.path.clone(), original));
+        }
+        apply(writes)?;
+        fs::remove_dir_all(&dir)?;
+        Ok(changeset)
+    }
+
+    /// Undo the newest change set of this session
+    pub fn undo_last(&self, force: bool) -> Result<ChangeSet> {
+        let last = self
+            .list(false)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::ResourceNotFound("No change sets to undo in this session".to_string()))?;
+        self.undo(&last.id, force)
+    }
+
+    /// Start a change set for `tool`
+    fn create(&self, tool: &str) -> Result<Entry> {
+        let id = new_id();
+        let dir = self.root.join(JOURNAL_DIR).join(&id);
+        fs::create_dir_all(&dir)?;
+        Ok(Entry {
+            dir,
+            changeset: ChangeSet {
+                id,
+                tool: tool.to_string(),
+                created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+                session_id: self.session_id.clone(),
+                request_id: self.request_id.clone(),
+                files: Vec::new(),
+            },
+        })
+    }
+
+    fn dir(&self, id: &str) -> Result<PathBuf> {
+        // IDs come from callers, so keep them from naming other paths
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(Error::invalid_arguments(format!("Invalid change set id '{}'", id)));
+        }
+        Ok(self.root.join(JOURNAL_DIR).join(id))
+    }
+}
+
+fn read_existing(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replace `path` through a temporary file in the same directory, keeping
+/// its permissions
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        tmp.as_file().set_permissions(metadata.permissions())?;
+    }
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Apply `writes` in order, putting back the files already changed if one fails
+fn apply(writes: Vec<(PathBuf, Option<Vec<u8>>)>) -> Result<()> {
+    let mut done: Vec<(&Path, Option<Vec<u8>>)> = Vec::with_capacity(writes.len());
+    for (path, contents) in &writes {
+        let result = read_existing(path).and_then(|original| {
+            put(path, contents.as_deref())?;
+            Ok(original)
+        });
+        match result {
+            Ok(original) => done.push((path, original)),
+            Err(e) => {
+                for (path, original) in done.into_iter().rev() {
+                    let _ = put(path, original.as_deref());
+                }
+                return Err(Error::tool(format!(
+                    "Failed to write {}: {}; no files were changed",
+                    path.display(),
+                    e
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write or delete `path`
+fn put(path: &Path, contents: Option<&[u8]>) -> io::Result<()> {
+    match contents {
+        Some(contents) => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            write_atomic(path, contents)
+        }
+        None => match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Unique, time-ordered change set ID
+fn new_id() -> String {
+    static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    format!("{:013}-{}-{}", millis, std::process::id(), sequence)
+}
+
+/// Creation order of an ID made by [`new_id`]
+fn order(id: &str) -> (u128, u32) {
+    let mut parts = id.split('-');
+    let millis = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    let sequence = parts.nth(1).and_then(|p| p.parse().ok()).unwrap_or(0);
+    (millis, sequence)
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_commit_and_undo() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("sub/b.txt");
+        fs::write(&a, "old").unwrap();
+
+        let journal = Journal::new(dir.path());
+        let mut tx = Transaction::new("test");
+        tx.write(&a, "new");
+        tx.write(&b, "created");
+        let changeset = tx.commit(&journal).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "created");
+        assert_eq!(journal.load(&changeset.id).unwrap().files.len(), 2);
+
+        // Later edits block the undo unless forced
+        fs::write(&b, "edited").unwrap();
+        assert!(journal.undo(&changeset.id, false).is_err());
+        fs::write(&b, "created").unwrap();
+
+        journal.undo(&changeset.id, false).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old");
+        assert!(!b.exists());
+        assert!(matches!(journal.load(&changeset.id), Err(Error::ResourceNotFound(_))));
+        assert!(journal.load("../x").is_err());
+    }
+
+    #[test]
+    fn test_sessions_and_snapshots() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "1").unwrap();
+        let mine = Journal::new(dir.path()).with_session_id(Some("mine".to_string()));
+        let theirs = Journal::new(dir.path()).with_session_id(Some("theirs".to_string()));
+
+        let mut tx = Transaction::new("test");
+        tx.write(&file, "2");
+        let first = tx.commit(&mine).unwrap();
+
+        // An external writer, recorded through a snapshot
+        let mut snapshot = Snapshot::new("external");
+        snapshot.capture(&file).unwrap();
+        snapshot.capture(&dir.path().join("untouched.txt")).unwrap();
+        fs::write(&file, "3").unwrap();
+        let second = snapshot.commit(&theirs).unwrap().unwrap();
+        assert_eq!(second.files.len(), 1);
+        assert!(Snapshot::new("external").commit(&theirs).unwrap().is_none());
+
+        let ids: Vec<String> = mine.list(true).unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, [second.id.clone(), first.id.clone()]);
+        assert_eq!(mine.list(false).unwrap().len(), 1);
+
+        // Undoing the older change first would lose the newer one
+        assert!(mine.undo_last(false).is_err());
+        theirs.undo_last(false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "2");
+        mine.undo_last(false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1");
+        assert!(mine.undo_last(false).is_err());
+    }
+
+    #[test]
+    fn test_failed_write_restores_files() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "old").unwrap();
+        let blocker = dir.path().join("file");
+        fs::write(&blocker, "").unwrap();
+
+        // A path below a regular file cannot be written
+        let writes = vec![(a.clone(), Some(b"new".to_vec())), (blocker.join("b.txt"), Some(b"x".to_vec()))];
+        assert!(apply(writes).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old");
+
+        let mut tx = Transaction::new("test");
+        tx.write(&a, "new");
+        tx.write(blocker.join("b.txt"), "x");
+        assert!(tx.commit(&Journal::new(dir.path())).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.path().join(JOURNAL_DIR)).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,445 @@
+//! Line diff algorithms
+//!
+//! All three produce a minimal-looking edit script as [`DiffOp`]s:
+//! - Myers finds a shortest edit script (linear space, divide and conquer
+//!   on the middle snake).
+//! - Patience anchors on lines that occur exactly once on both sides and
+//!   diffs the gaps between them, which keeps moved blocks and braces from
+//!   being matched out of place.
+//! - Histogram, as in git, anchors on the rarest line the sides share and
+//!   grows the match around it.
+//!
+//! Patience and histogram fall back to Myers where they find no anchor.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+/// Lines occurring more often than this are never histogram anchors
+const MAX_CHAIN: usize = 64;
+
+/// Algorithm used to match lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    #[default]
+    Myers,
+    Patience,
+    Histogram,
+}
+
+/// What a [`DiffOp`] does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A run of lines in an edit script. Ranges index the old and new lines;
+/// the range on the side an operation does not touch is empty and marks
+/// where it applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffOp {
+    pub kind: OpKind,
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Shortest edit script turning `old` into `new`
+pub fn diff_lines<T: Hash + Eq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    diff_with(Algorithm::Myers, old, new)
+}
+
+/// Edit script turning `old` into `new`, matched by `algorithm`. Within
+/// each change the deletions come before the insertions.
+pub fn diff_with<T: Hash + Eq>(algorithm: Algorithm, old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut differ = Differ {
+        old,
+        new,
+        forward: vec![0; 2 * max_d + 1],
+        backward: vec![0; 2 * max_d + 1],
+        offset: max_d as isize,
+        ops: Vec::new(),
+    };
+    let (old, new) = (0..old.len(), 0..new.len());
+    match algorithm {
+        Algorithm::Myers => differ.myers(old, new),
+        Algorithm::Patience => differ.patience(old, new),
+        Algorithm::Histogram => differ.histogram(old, new),
+    }
+    normalize(differ.ops)
+}
+
+/// Reorder each change so every deletion precedes the insertions
+fn normalize(raw: Vec<DiffOp>) -> Vec<DiffOp> {
+    let mut ops: Vec<DiffOp> = Vec::with_capacity(raw.len());
+    for op in raw {
+        let n = ops.len();
+        match (op.kind, n.checked_sub(1).map(|i| ops[i].kind), n.checked_sub(2).map(|i| ops[i].kind)) {
+            (OpKind::Insert, Some(OpKind::Insert), _) | (OpKind::Delete, Some(OpKind::Delete), _) => {
+                ops[n - 1].old.end = op.old.end;
+                ops[n - 1].new.end = op.new.end;
+            }
+            (OpKind::Delete, Some(OpKind::Insert), Some(OpKind::Delete)) => {
+                // Delete, insert, delete: grow the first deletion and move the
+                // insertion after it
+                ops[n - 2].old.end = op.old.end;
+                ops[n - 1].old = op.old.end..op.old.end;
+            }
+            (OpKind::Delete, Some(OpKind::Insert), _) => {
+                let insert = ops.pop().expect("checked above");
+                ops.push(DiffOp {
+                    kind: OpKind::Delete,
+                    old: op.old.clone(),
+                    new: insert.new.start..insert.new.start,
+                });
+                ops.push(DiffOp {
+                    kind: OpKind::Insert,
+                    old: op.old.end..op.old.end,
+                    new: insert.new,
+                });
+            }
+            _ => ops.push(op),
+        }
+    }
+    ops
+}
+
+/// State of one diff: the sequences, the furthest-reaching Myers paths of
+/// both searches indexed by diagonal, and the edit script so far
+struct Differ<'a, T> {
+    old: &'a [T],
+    new: &'a [T],
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+    ops: Vec<DiffOp>,
+}
+
+impl<T: Hash + Eq> Differ<'_, T> {
+    /// Emit the common prefix and return the remaining ranges and the
+    /// common suffix, which the caller emits last
+    fn trim(&mut self, mut old: Range<usize>, mut new: Range<usize>) -> (Range<usize>, Range<usize>, usize) {
+        let prefix = self.common_prefix(old.clone(), new.clone());
+        self.push(OpKind::Equal, old.start..old.start + prefix, new.start..new.start + prefix);
+        old.start += prefix;
+        new.start += prefix;
+        let suffix = self.common_suffix(old.clone(), new.clone());
+        old.end -= suffix;
+        new.end -= suffix;
+        (old, new, suffix)
+    }
+
+    /// Emit the `suffix` equal lines after `old` and `new`
+    fn push_suffix(&mut self, old: &Range<usize>, new: &Range<usize>, suffix: usize) {
+        self.push(OpKind::Equal, old.end..old.end + suffix, new.end..new.end + suffix);
+    }
+
+    /// Emit a change of one side only; false if both sides have lines
+    fn push_one_sided(&mut self, old: &Range<usize>, new: &Range<usize>) -> bool {
+        if !old.is_empty() && !new.is_empty() {
+            return false;
+        }
+        self.push(OpKind::Delete, old.clone(), new.start..new.start);
+        self.push(OpKind::Insert, old.end..old.end, new.clone());
+        true
+    }
+
+    fn myers(&mut self, old: Range<usize>, new: Range<usize>) {
+        let (old, new, suffix) = self.trim(old, new);
+        if !self.push_one_sided(&old, &new) {
+            let (x, y) = self.middle_snake(old.clone(), new.clone());
+            self.myers(old.start..x, new.start..y);
+            self.myers(x..old.end, y..new.end);
+        }
+        self.push_suffix(&old, &new, suffix);
+    }
+
+    /// A point on an optimal path through the middle of the edit graph,
+    /// splitting the problem in two smaller ones
+    fn middle_snake(&mut self, old: Range<usize>, new: Range<usize>) -> (usize, usize) {
+        let (n, m) = (old.len() as isize, new.len() as isize);
+        let delta = n - m;
+        let odd = delta & 1 == 1;
+        let at = |k: isize, offset: isize| (k + offset) as usize;
+        self.forward[at(1, self.offset)] = 0;
+        self.backward[at(1, self.offset)] = 0;
+
+        let max_d = (n + m + 1) / 2 + 1;
+        for d in 0..max_d {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d
+                    || (k != d && self.forward[at(k - 1, self.offset)] < self.forward[at(k + 1, self.offset)])
+                {
+                    self.forward[at(k + 1, self.offset)]
+                } else {
+                    self.forward[at(k - 1, self.offset)] + 1
+                };
+                let y = (x as isize - k) as usize;
+                let start = (x, y);
+                if (x as isize) < n && (y as isize) < m {
+                    x += self.common_prefix(old.start + x..old.end, new.start + y..new.end);
+                }
+                self.forward[at(k, self.offset)] = x;
+                if odd
+                    && (k - delta).abs() < d
+                    && x + self.backward[at(-(k - delta), self.offset)] >= n as usize
+                {
+                    return (old.start + start.0, new.start + start.1);
+                }
+            }
+
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d
+                    || (k != d && self.backward[at(k - 1, self.offset)] < self.backward[at(k + 1, self.offset)])
+                {
+                    self.backward[at(k + 1, self.offset)]
+                } else {
+                    self.backward[at(k - 1, self.offset)] + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                if (x as isize) < n && (y as isize) < m {
+                    let common = self.common_suffix(old.start..old.end - x, new.start..new.end - y);
+                    x += common;
+                    y += common;
+                }
+                self.backward[at(k, self.offset)] = x;
+                if !odd
+                    && (k - delta).abs() <= d
+                    && x + self.forward[at(-(k - delta), self.offset)] >= n as usize
+                {
+                    return (old.end - x, new.end - y);
+                }
+            }
+        }
+        unreachable!("the searches always meet within (n + m) / 2 steps")
+    }
+
+    fn patience(&mut self, old: Range<usize>, new: Range<usize>) {
+        let (old, new, suffix) = self.trim(old, new);
+        if !self.push_one_sided(&old, &new) {
+            let anchors = self.unique_anchors(old.clone(), new.clone());
+            if anchors.is_empty() {
+                self.myers(old.clone(), new.clone());
+            } else {
+                let (mut x, mut y) = (old.start, new.start);
+                for (i, j) in anchors {
+                    self.patience(x..i, y..j);
+                    self.push(OpKind::Equal, i..i + 1, j..j + 1);
+                    (x, y) = (i + 1, j + 1);
+                }
+                self.patience(x..old.end, y..new.end);
+            }
+        }
+        self.push_suffix(&old, &new, suffix);
+    }
+
+    /// Longest increasing run of lines unique to both ranges, as
+    /// `(old, new)` index pairs in order
+    fn unique_anchors(&self, old: Range<usize>, new: Range<usize>) -> Vec<(usize, usize)> {
+        // Occurrences in old, in new, and the index of the last one in each
+        let mut counts: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+        for i in old {
+            let entry = counts.entry(&self.old[i]).or_default();
+            entry.0 += 1;
+            entry.2 = i;
+        }
+        for j in new.clone() {
+            if let Some(entry) = counts.get_mut(&self.new[j]) {
+                entry.1 += 1;
+                entry.3 = j;
+            }
+        }
+        // Unique pairs in new order; their old indexes must increase
+        let pairs: Vec<(usize, usize)> = new
+            .filter_map(|j| {
+                let &(in_old, in_new, i, last) = counts.get(&self.new[j])?;
+                (in_old == 1 && in_new == 1 && last == j).then_some((i, j))
+            })
+            .collect();
+
+        // Patience sorting: piles of old indexes, each card linked to the
+        // top of the previous pile when it was placed
+        let mut tops: Vec<usize> = Vec::new();
+        let mut links: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+        for (card, &(i, _)) in pairs.iter().enumerate() {
+            let pile = tops.partition_point(|&top| pairs[top].0 < i);
+            links.push(pile.checked_sub(1).map(|p| tops[p]));
+            if pile == tops.len() {
+                tops.push(card);
+            } else {
+                tops[pile] = card;
+            }
+        }
+        let mut anchors = Vec::with_capacity(tops.len());
+        let mut card = tops.last().copied();
+        while let Some(c) = card {
+            anchors.push(pairs[c]);
+            card = links[c];
+        }
+        anchors.reverse();
+        anchors
+    }
+
+    fn histogram(&mut self, old: Range<usize>, new: Range<usize>) {
+        let (old, new, suffix) = self.trim(old, new);
+        if !self.push_one_sided(&old, &new) {
+            match self.rarest_match(old.clone(), new.clone()) {
+                Some((i, j, len)) => {
+                    self.histogram(old.start..i, new.start..j);
+                    self.push(OpKind::Equal, i..i + len, j..j + len);
+                    self.histogram(i + len..old.end, j + len..new.end);
+                }
+                None => self.myers(old.clone(), new.clone()),
+            }
+        }
+        self.push_suffix(&old, &new, suffix);
+    }
+
+    /// The common run whose rarest old line occurs least often, longest
+    /// first on ties, as `(old start, new start, length)`
+    fn rarest_match(&self, old: Range<usize>, new: Range<usize>) -> Option<(usize, usize, usize)> {
+        let mut positions: HashMap<&T, Vec<usize>> = HashMap::new();
+        for i in old.clone() {
+            positions.entry(&self.old[i]).or_default().push(i);
+        }
+
+        // (rarity, length, old start, new start)
+        let mut best: Option<(usize, usize, usize, usize)> = None;
+        let mut j = new.start;
+        while j < new.end {
+            let mut next = j + 1;
+            let candidates = positions.get(&self.new[j]).filter(|p| p.len() <= MAX_CHAIN);
+            for &i in candidates.into_iter().flatten() {
+                let back = self.common_suffix(old.start..i, new.start..j);
+                let (start_old, start_new) = (i - back, j - back);
+                let len = back + self.common_prefix(i..old.end, j..new.end);
+                let rarity = (start_old..start_old + len)
+                    .map(|k| positions[&self.old[k]].len())
+                    .min()
+                    .unwrap_or(usize::MAX);
+                if best.is_none_or(|(r, l, _, _)| rarity < r || (rarity == r && len > l)) {
+                    best = Some((rarity, len, start_old, start_new));
+                }
+                next = next.max(start_new + len);
+            }
+            j = next;
+        }
+        best.map(|(_, len, i, j)| (i, j, len))
+    }
+
+    fn common_prefix(&self, old: Range<usize>, new: Range<usize>) -> usize {
+        self.old[old].iter().zip(&self.new[new]).take_while(|(a, b)| a == b).count()
+    }
+
+    fn common_suffix(&self, old: Range<usize>, new: Range<usize>) -> usize {
+        self.old[old].iter().rev().zip(self.new[new].iter().rev()).take_while(|(a, b)| a == b).count()
+    }
+
+    /// Append an operation, merging it into the previous one of the same kind
+    fn push(&mut self, kind: OpKind, old: Range<usize>, new: Range<usize>) {
+        if old.is_empty() && new.is_empty() {
+            return;
+        }
+        if let Some(last) = self.ops.last_mut()
+            && last.kind == kind
+            && last.old.end == old.start
+            && last.new.end == new.start
+        {
+            last.old.end = old.end;
+            last.new.end = new.end;
+            return;
+        }
+        self.ops.push(DiffOp { kind, old, new });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Length of the longest common subsequence, by dynamic programming
+    fn lcs(a: &[u8], b: &[u8]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = if a[i] == b[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    /// Check that `ops` turns `a` into `b` and return the number of lines
+    /// deleted or inserted
+    fn replay(a: &[u8], b: &[u8], ops: &[DiffOp]) -> usize {
+        let mut rebuilt = Vec::new();
+        let mut edits = 0;
+        let (mut i, mut j) = (0, 0);
+        let mut last = None;
+        for op in ops {
+            assert_eq!((op.old.start, op.new.start), (i, j), "{:?} -> {:?}: {:?}", a, b, ops);
+            assert!(!(last == Some(OpKind::Insert) && op.kind == OpKind::Delete), "{:?}", ops);
+            match op.kind {
+                OpKind::Equal => {
+                    assert_eq!(a[op.old.clone()], b[op.new.clone()]);
+                    rebuilt.extend_from_slice(&a[op.old.clone()]);
+                }
+                OpKind::Delete => edits += op.old.len(),
+                OpKind::Insert => {
+                    edits += op.new.len();
+                    rebuilt.extend_from_slice(&b[op.new.clone()]);
+                }
+            }
+            (i, j) = (op.old.end, op.new.end);
+            last = Some(op.kind);
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        assert_eq!(rebuilt, b);
+        edits
+    }
+
+    #[test]
+    fn test_algorithms_produce_valid_scripts() {
+        // Deterministic pseudo-random sequences over a small alphabet
+        let mut seed = 0x2545_f491_u32;
+        let mut next = |bound: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed % bound
+        };
+        for _ in 0..300 {
+            let a: Vec<u8> = (0..next(12)).map(|_| b'a' + next(4) as u8).collect();
+            let b: Vec<u8> = (0..next(12)).map(|_| b'a' + next(4) as u8).collect();
+            let minimal = a.len() + b.len() - 2 * lcs(&a, &b);
+            assert_eq!(replay(&a, &b, &diff_lines(&a, &b)), minimal, "{:?} -> {:?}", a, b);
+            replay(&a, &b, &diff_with(Algorithm::Patience, &a, &b));
+            replay(&a, &b, &diff_with(Algorithm::Histogram, &a, &b));
+        }
+    }
+
+    #[test]
+    fn test_patience_keeps_blocks_together() {
+        // Moving `b` above `a`: Myers matches the braces of `a` and splits
+        // `b`, patience anchors on the unique lines and keeps `b` whole
+        let old = ["fn a", "{", "}", "fn b", "{", "x", "}"];
+        let new = ["fn b", "{", "x", "}", "fn a", "{", "}"];
+        let equal = |algorithm| -> Vec<&[&str]> {
+            diff_with(algorithm, &old, &new)
+                .iter()
+                .filter(|op| op.kind == OpKind::Equal)
+                .map(|op| &old[op.old.clone()])
+                .collect()
+        };
+        assert_eq!(equal(Algorithm::Patience)[0], &old[3..6]);
+        assert_ne!(equal(Algorithm::Myers)[0], &old[3..6]);
+    }
+}
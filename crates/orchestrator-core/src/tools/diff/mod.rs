@@ -0,0 +1,660 @@
+//! Diff tool - compare files or strings
+//!
+//! Everything runs in process: lines are matched by one of the
+//! [`Algorithm`]s and the edit script is grouped into [`Hunk`]s, rendered
+//! as a unified, context, side-by-side or JSON diff. Changed lines can
+//! carry word or character ranges showing what changed within them.
+
+mod algorithm;
+
+pub use algorithm::{Algorithm, DiffOp, OpKind, diff_lines, diff_with};
+
+use super::cancel::CancelToken;
+use crate::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::path::Path;
+
+/// How a diff is rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFormat {
+    /// `diff -u`
+    #[default]
+    Unified,
+    /// `diff -c`
+    Context,
+    /// Two columns per hunk, like `diff -y`
+    SideBySide,
+    /// The hunks as JSON
+    Json,
+}
+
+/// Granularity of highlights within changed lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InlineDiff {
+    #[default]
+    None,
+    Word,
+    Char,
+}
+
+/// Configuration for diff operations
+#[derive(Debug, Clone)]
+pub struct DiffConfig {
+    /// Output format
+    pub format: DiffFormat,
+    /// Line matching algorithm
+    pub algorithm: Algorithm,
+    /// Number of context lines
+    pub context_lines: usize,
+    /// Ignore whitespace
+    pub ignore_whitespace: bool,
+    /// Ignore case
+    pub ignore_case: bool,
+    /// Highlights within changed lines
+    pub inline: InlineDiff,
+    /// Total width of side-by-side output
+    pub width: usize,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            format: DiffFormat::Unified,
+            algorithm: Algorithm::Myers,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_case: false,
+            inline: InlineDiff::None,
+            width: 130,
+        }
+    }
+}
+
+/// Result of a diff operation
+#[derive(Debug, Clone)]
+pub struct DiffResult {
+    pub has_differences: bool,
+    pub diff_output: String,
+    pub additions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<Hunk>,
+}
+
+/// A group of changes with the context around them. Starts are 1-based
+/// as in a unified diff header; an empty side starts at the line before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// Whether a hunk line is kept, deleted or inserted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Context,
+    Delete,
+    Insert,
+}
+
+/// One line of a hunk, without its line feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    /// 1-based line number in the old text, absent for insertions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    /// 1-based line number in the new text, absent for deletions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub content: String,
+    /// Last line of its text, not terminated by a line feed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_newline: bool,
+    /// Byte ranges of `content` that changed, for paired changed lines
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<(usize, usize)>,
+}
+
+/// Diff tool for comparing files
+pub struct DiffTool {
+    config: DiffConfig,
+    cancel: CancelToken,
+}
+
+impl DiffTool {
+    pub fn new(config: DiffConfig) -> Self {
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Compare two files
+    pub fn diff_files(&self, file1: &Path, file2: &Path) -> Result<DiffResult> {
+        self.cancel.check()?;
+        let old = std::fs::read(file1)?;
+        let new = std::fs::read(file2)?;
+        self.diff(
+            &String::from_utf8_lossy(&old),
+            &String::from_utf8_lossy(&new),
+            &file1.display().to_string(),
+            &file2.display().to_string(),
+        )
+    }
+
+    /// Compare two strings
+    pub fn diff_strings(&self, content1: &str, content2: &str) -> Result<DiffResult> {
+        self.diff(content1, content2, "a", "b")
+    }
+
+    fn diff(&self, old: &str, new: &str, old_label: &str, new_label: &str) -> Result<DiffResult> {
+        self.cancel.check()?;
+        let old_lines = split_lines(old);
+        let new_lines = split_lines(new);
+        let ops = diff_with(self.config.algorithm, &self.keys(&old_lines), &self.keys(&new_lines));
+        self.cancel.check()?;
+
+        let mut hunks = build_hunks(&ops, &old_lines, &new_lines, self.config.context_lines);
+        if self.config.inline != InlineDiff::None {
+            highlight(&mut hunks, self.config.inline);
+        }
+        let count = |kind| ops.iter().filter(move |op: &&DiffOp| op.kind == kind);
+        let deletions = count(OpKind::Delete).map(|op| op.old.len()).sum();
+        let additions = count(OpKind::Insert).map(|op| op.new.len()).sum();
+
+        let diff_output = match self.config.format {
+            DiffFormat::Unified => render_unified(&hunks, old_label, new_label),
+            DiffFormat::Context => render_context(&hunks, old_label, new_label),
+            DiffFormat::SideBySide => render_side_by_side(&hunks, self.config.width),
+            DiffFormat::Json => serde_json::to_string_pretty(&hunks)?,
+        };
+        Ok(DiffResult {
+            has_differences: !hunks.is_empty(),
+            diff_output,
+            additions,
+            deletions,
+            hunks,
+        })
+    }
+
+    /// What lines are compared by: the lines themselves, without
+    /// whitespace or lowercased as configured
+    fn keys<'a>(&self, lines: &[&'a str]) -> Vec<Cow<'a, str>> {
+        lines
+            .iter()
+            .map(|line| {
+                let mut key = Cow::Borrowed(*line);
+                if self.config.ignore_whitespace {
+                    key = Cow::Owned(key.chars().filter(|c| !c.is_whitespace()).collect());
+                }
+                if self.config.ignore_case {
+                    key = Cow::Owned(key.to_lowercase());
+                }
+                key
+            })
+            .collect()
+    }
+}
+
+impl Default for DiffTool {
+    fn default() -> Self {
+        Self::new(DiffConfig::default())
+    }
+}
+
+/// Unified diff of two texts with `context` lines around each change,
+/// empty if they are equal
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str, context: usize) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff_lines(&old_lines, &new_lines);
+    render_unified(&build_hunks(&ops, &old_lines, &new_lines, context), old_label, new_label)
+}
+
+/// Lines of `text`, each with its line feed
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Group `ops` into hunks of changes with up to `context` equal lines
+/// around them; changes closer than twice that share a hunk
+fn hunks(ops: &[DiffOp], context: usize) -> Vec<Vec<DiffOp>> {
+    let mut hunks = Vec::new();
+    let mut current: Vec<DiffOp> = Vec::new();
+    for (i, op) in ops.iter().enumerate() {
+        if op.kind != OpKind::Equal {
+            current.push(op.clone());
+            continue;
+        }
+        let len = op.old.len();
+        let after_change = !current.is_empty();
+        let before_change = i + 1 < ops.len();
+        if after_change && before_change && len <= 2 * context {
+            current.push(op.clone());
+            continue;
+        }
+        if after_change {
+            if context > 0 {
+                current.push(slice(op, 0..len.min(context)));
+            }
+            hunks.push(std::mem::take(&mut current));
+        }
+        if before_change && context > 0 {
+            current.push(slice(op, len.saturating_sub(context)..len));
+        }
+    }
+    if !current.is_empty() {
+        hunks.push(current);
+    }
+    hunks
+}
+
+/// Part of an equal run, `part` relative to its start
+fn slice(op: &DiffOp, part: Range<usize>) -> DiffOp {
+    DiffOp {
+        kind: op.kind,
+        old: op.old.start + part.start..op.old.start + part.end,
+        new: op.new.start + part.start..op.new.start + part.end,
+    }
+}
+
+/// The hunks of an edit script over `old` and `new`
+fn build_hunks(ops: &[DiffOp], old: &[&str], new: &[&str], context: usize) -> Vec<Hunk> {
+    hunks(ops, context)
+        .into_iter()
+        .map(|group| {
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let old_range = first.old.start..last.old.end;
+            let new_range = first.new.start..last.new.end;
+            let start = |range: &Range<usize>| range.start + usize::from(!range.is_empty());
+
+            let mut lines = Vec::new();
+            for op in &group {
+                let (kind, text, range) = match op.kind {
+                    OpKind::Equal => (LineKind::Context, old, op.old.clone()),
+                    OpKind::Delete => (LineKind::Delete, old, op.old.clone()),
+                    OpKind::Insert => (LineKind::Insert, new, op.new.clone()),
+                };
+                for (i, line) in text[range].iter().enumerate() {
+                    let content = line.strip_suffix('\n');
+                    lines.push(HunkLine {
+                        kind,
+                        old_line: (kind != LineKind::Insert).then_some(op.old.start + i + 1),
+                        new_line: (kind != LineKind::Delete).then_some(op.new.start + i + 1),
+                        content: content.unwrap_or(line).to_string(),
+                        no_newline: content.is_none(),
+                        highlights: Vec::new(),
+                    });
+                }
+            }
+            Hunk {
+                old_start: start(&old_range),
+                old_lines: old_range.len(),
+                new_start: start(&new_range),
+                new_lines: new_range.len(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Changes within a hunk: the deleted lines and the inserted lines that
+/// replace them, as index ranges into `lines`
+fn changes(lines: &[HunkLine]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut changes = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].kind == LineKind::Context {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < lines.len() && lines[i].kind == LineKind::Delete {
+            i += 1;
+        }
+        let split = i;
+        while i < lines.len() && lines[i].kind == LineKind::Insert {
+            i += 1;
+        }
+        changes.push((start..split, split..i));
+    }
+    changes
+}
+
+/// Mark what changed between each deleted line and the inserted line
+/// taking its place
+fn highlight(hunks: &mut [Hunk], inline: InlineDiff) {
+    for hunk in hunks {
+        for (deleted, inserted) in changes(&hunk.lines) {
+            for (d, i) in deleted.zip(inserted) {
+                let old = tokenize(&hunk.lines[d].content, inline);
+                let new = tokenize(&hunk.lines[i].content, inline);
+                let mut old_ranges = Vec::new();
+                let mut new_ranges = Vec::new();
+                for op in diff_lines(&old, &new) {
+                    match op.kind {
+                        OpKind::Equal => {}
+                        OpKind::Delete => push_range(&mut old_ranges, token_span(&old, op.old)),
+                        OpKind::Insert => push_range(&mut new_ranges, token_span(&new, op.new)),
+                    }
+                }
+                hunk.lines[d].highlights = old_ranges;
+                hunk.lines[i].highlights = new_ranges;
+            }
+        }
+    }
+}
+
+/// Split a line into words, whitespace runs and single other characters,
+/// or into characters
+fn tokenize(line: &str, inline: InlineDiff) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if inline == InlineDiff::Word && class(c) != 2 {
+            while let Some(&(i, next)) = chars.peek()
+                && class(next) == class(c)
+            {
+                end = i + next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(&line[start..end]);
+    }
+    tokens
+}
+
+/// Byte range covered by `range` of `tokens`
+fn token_span(tokens: &[&str], range: Range<usize>) -> (usize, usize) {
+    let start = tokens[..range.start].iter().map(|token| token.len()).sum();
+    let len: usize = tokens[range].iter().map(|token| token.len()).sum();
+    (start, start + len)
+}
+
+/// Append a byte range, merging it into the previous one if they touch
+fn push_range(ranges: &mut Vec<(usize, usize)>, (start, end): (usize, usize)) {
+    match ranges.last_mut() {
+        Some(last) if last.1 == start => last.1 = end,
+        _ => ranges.push((start, end)),
+    }
+}
+
+/// Render hunks the way `diff -u` does
+fn render_unified(hunks: &[Hunk], old_label: &str, new_label: &str) -> String {
+    let mut out = String::new();
+    if hunks.is_empty() {
+        return out;
+    }
+    let _ = writeln!(out, "--- {}\n+++ {}", old_label, new_label);
+    for hunk in hunks {
+        push_header(&mut out, hunk);
+        for line in &hunk.lines {
+            let prefix = match line.kind {
+                LineKind::Context => " ",
+                LineKind::Delete => "-",
+                LineKind::Insert => "+",
+            };
+            push_line(&mut out, prefix, line);
+        }
+    }
+    out
+}
+
+/// Render hunks the way `diff -c` does: the old lines of each hunk, then
+/// the new ones, with `!` marking lines replaced by others
+fn render_context(hunks: &[Hunk], old_label: &str, new_label: &str) -> String {
+    let mut out = String::new();
+    if hunks.is_empty() {
+        return out;
+    }
+    let _ = writeln!(out, "*** {}\n--- {}", old_label, new_label);
+    for hunk in hunks {
+        let mut markers = vec!["  "; hunk.lines.len()];
+        for (deleted, inserted) in changes(&hunk.lines) {
+            let replaced = !deleted.is_empty() && !inserted.is_empty();
+            for i in deleted {
+                markers[i] = if replaced { "! " } else { "- " };
+            }
+            for i in inserted {
+                markers[i] = if replaced { "! " } else { "+ " };
+            }
+        }
+        out.push_str("***************\n");
+        let sides = [
+            ("***", "****", hunk.old_start, hunk.old_lines, LineKind::Delete, LineKind::Insert),
+            ("---", "----", hunk.new_start, hunk.new_lines, LineKind::Insert, LineKind::Delete),
+        ];
+        for (mark, trailer, start, len, shown, hidden) in sides {
+            let _ = writeln!(out, "{} {} {}", mark, context_range(start, len), trailer);
+            if hunk.lines.iter().any(|line| line.kind == shown) {
+                for (line, marker) in hunk.lines.iter().zip(&markers) {
+                    if line.kind != hidden {
+                        push_line(&mut out, marker, line);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Render each hunk as two columns of `width` in total, old on the left
+/// and new on the right, with `|`, `<` and `>` marking changed, deleted
+/// and inserted lines
+fn render_side_by_side(hunks: &[Hunk], width: usize) -> String {
+    let column = width.saturating_sub(3) / 2;
+    let mut out = String::new();
+    for hunk in hunks {
+        push_header(&mut out, hunk);
+        let mut rows: Vec<(&str, char, &str)> = Vec::new();
+        let mut rest = 0;
+        for (deleted, inserted) in changes(&hunk.lines) {
+            for line in &hunk.lines[rest..deleted.start] {
+                rows.push((&line.content, ' ', &line.content));
+            }
+            rest = inserted.end;
+            let (deleted, inserted) = (&hunk.lines[deleted], &hunk.lines[inserted]);
+            for j in 0..deleted.len().max(inserted.len()) {
+                let (left, right) = (deleted.get(j), inserted.get(j));
+                let marker = match (left, right) {
+                    (Some(_), Some(_)) => '|',
+                    (Some(_), None) => '<',
+                    _ => '>',
+                };
+                rows.push((content(left), marker, content(right)));
+            }
+        }
+        for line in &hunk.lines[rest..] {
+            rows.push((&line.content, ' ', &line.content));
+        }
+        for (left, marker, right) in rows {
+            let row = format!("{:<column$} {} {}", fit(left, column), marker, fit(right, column));
+            out.push_str(row.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Content of a line that may be missing
+fn content(line: Option<&HunkLine>) -> &str {
+    line.map_or("", |line| line.content.as_str())
+}
+
+/// `text` with tabs expanded, cut to `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut len = 0;
+    for c in text.chars() {
+        let (c, n) = if c == '\t' { (' ', 8 - len % 8) } else { (c, 1) };
+        for _ in 0..n {
+            if len == width {
+                return out;
+            }
+            out.push(c);
+            len += 1;
+        }
+    }
+    out
+}
+
+/// Write the `@@ -old +new @@` header of a hunk
+fn push_header(out: &mut String, hunk: &Hunk) {
+    let _ = writeln!(
+        out,
+        "@@ -{} +{} @@",
+        hunk_range(hunk.old_start, hunk.old_lines),
+        hunk_range(hunk.new_start, hunk.new_lines)
+    );
+}
+
+/// Write `line` after `prefix`, noting a missing final line feed
+fn push_line(out: &mut String, prefix: &str, line: &HunkLine) {
+    out.push_str(prefix);
+    out.push_str(&line.content);
+    out.push('\n');
+    if line.no_newline {
+        out.push_str("\\ No newline at end of file\n");
+    }
+}
+
+/// `start,len` in the form of a unified hunk header
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        1 => format!("{}", start),
+        len => format!("{},{}", start, len),
+    }
+}
+
+/// `start,end` in the form of a context hunk header
+fn context_range(start: usize, len: usize) -> String {
+    match len {
+        0 | 1 => format!("{}", start),
+        len => format!("{},{}", start, start + len - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(config: DiffConfig) -> DiffTool {
+        DiffTool::new(config)
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let diff = unified_diff(old, new, "a/x", "b/x", 1);
+        assert_eq!(
+            diff,
+            "--- a/x\n+++ b/x\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -9,2 +9,2 @@\n i\n-j\n\\ No newline at end of file\n+j\n"
+        );
+        assert_eq!(unified_diff("same\n", "same\n", "a", "b", 3), "");
+        assert_eq!(unified_diff("", "new\n", "a", "b", 3), "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n");
+    }
+
+    #[test]
+    fn test_diff_strings() {
+        let result = DiffTool::default().diff_strings("line1\nline2\n", "line1\nline3\nline4\n").unwrap();
+        assert!(result.has_differences);
+        assert_eq!((result.additions, result.deletions), (2, 1));
+        assert_eq!(result.diff_output, "--- a\n+++ b\n@@ -1,2 +1,3 @@\n line1\n-line2\n+line3\n+line4\n");
+
+        let same = DiffTool::default().diff_strings("x\n", "x\n").unwrap();
+        assert!(!same.has_differences);
+        assert!(same.diff_output.is_empty());
+    }
+
+    #[test]
+    fn test_ignore_whitespace_and_case() {
+        let old = "fn main() {\n    Run();\n}\n";
+        let new = "fn main()  {\n\trun();\n}\n";
+        assert!(DiffTool::default().diff_strings(old, new).unwrap().has_differences);
+
+        let config = DiffConfig {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        let result = tool(config.clone()).diff_strings(old, new).unwrap();
+        assert_eq!(result.deletions, 1);
+        let config = DiffConfig {
+            ignore_case: true,
+            ..config
+        };
+        assert!(!tool(config).diff_strings(old, new).unwrap().has_differences);
+    }
+
+    #[test]
+    fn test_formats() {
+        let old = "a\nb\nc\n";
+        let new = "a\nB\nc\nd\n";
+        let config = |format| DiffConfig {
+            format,
+            width: 11,
+            ..Default::default()
+        };
+
+        let context = tool(config(DiffFormat::Context)).diff_strings(old, new).unwrap();
+        assert_eq!(
+            context.diff_output,
+            "*** a\n--- b\n***************\n*** 1,3 ****\n  a\n! b\n  c\n--- 1,4 ----\n  a\n! B\n  c\n+ d\n"
+        );
+
+        let side = tool(config(DiffFormat::SideBySide)).diff_strings(old, new).unwrap();
+        assert_eq!(side.diff_output, "@@ -1,3 +1,4 @@\na      a\nb    | B\nc      c\n     > d\n");
+
+        let json = tool(config(DiffFormat::Json)).diff_strings(old, new).unwrap();
+        let hunks: serde_json::Value = serde_json::from_str(&json.diff_output).unwrap();
+        assert_eq!(hunks[0]["new_lines"], 4);
+        assert_eq!(hunks[0]["lines"][4]["kind"], "insert");
+        assert_eq!(hunks[0]["lines"][4]["new_line"], 4);
+        assert!(hunks[0]["lines"][4].get("old_line").is_none());
+    }
+
+    #[test]
+    fn test_inline_highlights() {
+        let old = "let total = price * count;\n";
+        let new = "let total = price * amount;\n";
+        let config = |inline| DiffConfig {
+            inline,
+            ..Default::default()
+        };
+
+        let words = tool(config(InlineDiff::Word)).diff_strings(old, new).unwrap();
+        let lines = &words.hunks[0].lines;
+        assert_eq!(lines[0].highlights, [(20, 25)]);
+        assert_eq!(lines[1].highlights, [(20, 26)]);
+
+        let chars = tool(config(InlineDiff::Char)).diff_strings("colour\n", "color\n").unwrap();
+        assert_eq!(chars.hunks[0].lines[0].highlights, [(4, 5)]);
+        assert!(chars.hunks[0].lines[1].highlights.is_empty());
+    }
+}
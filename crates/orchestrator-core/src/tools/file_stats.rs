@@ -1,8 +1,10 @@
 //! File statistics tool
 
+use super::cancel::CancelToken;
+use super::progress::ProgressReporter;
+use super::walk::{WalkConfig, Walker};
 use crate::Result;
 use std::path::Path;
-use walkdir::WalkDir;
 
 /// File type statistics
 #[derive(Debug, Clone, Default)]
@@ -25,11 +27,37 @@ pub struct DirStats {
 }
 
 /// File statistics tool
-pub struct FileStatsTool;
+pub struct FileStatsTool {
+    cancel: CancelToken,
+    progress: ProgressReporter,
+    walk: WalkConfig,
+}
 
 impl FileStatsTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            cancel: CancelToken::default(),
+            progress: ProgressReporter::default(),
+            walk: WalkConfig::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Report files scanned and matches found to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Choose which files are counted; ignored and hidden files are skipped by default
+    pub fn with_walk_config(mut self, walk: WalkConfig) -> Self {
+        self.walk = walk;
+        self
     }
 
     /// Get statistics for a directory
@@ -41,13 +69,14 @@ impl FileStatsTool {
         let mut file_types: std::collections::HashMap<String, FileTypeStats> = std::collections::HashMap::new();
         let mut files_with_sizes: Vec<(String, u64)> = Vec::new();
 
-        let walker = if let Some(depth) = max_depth {
-            WalkDir::new(directory).max_depth(depth)
-        } else {
-            WalkDir::new(directory)
-        };
+        let walker = Walker::new(WalkConfig {
+            max_depth,
+            ..self.walk.clone()
+        })?;
+
+        for entry in walker.walk(directory) {
+            self.cancel.check()?;
 
-        for entry in walker.into_iter().filter_map(|e| e.ok()) {
             let path = entry.path();
             
             if path.is_dir() {
@@ -56,6 +85,7 @@ impl FileStatsTool {
             }
 
             total_files += 1;
+            self.progress.file_scanned();
 
             // File size
             if let Ok(metadata) = entry.metadata() {
@@ -90,12 +120,12 @@ impl FileStatsTool {
         }
 
         // Sort files by size and get top 10
-        files_with_sizes.sort_by(|a, b| b.1.cmp(&a.1));
+        files_with_sizes.sort_by_key(|f| std::cmp::Reverse(f.1));
         let largest_files: Vec<(String, u64)> = files_with_sizes.into_iter().take(10).collect();
 
         // Convert file_types to sorted vec
         let mut file_types_vec: Vec<FileTypeStats> = file_types.into_values().collect();
-        file_types_vec.sort_by(|a, b| b.count.cmp(&a.count));
+        file_types_vec.sort_by_key(|t| std::cmp::Reverse(t.count));
 
         Ok(DirStats {
             total_files,
@@ -1,5 +1,7 @@
 //! Git operations tool
 
+use super::cancel::CancelToken;
+use super::process::run_command;
 use crate::Result;
 use std::path::Path;
 use std::process::Command;
@@ -21,11 +23,21 @@ pub struct GitFileStatus {
 }
 
 /// Git tool for repository operations
-pub struct GitTool;
+pub struct GitTool {
+    cancel: CancelToken,
+}
 
 impl GitTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Get diff for uncommitted changes
@@ -38,7 +50,7 @@ impl GitTool {
             cmd.arg("--staged");
         }
         
-        let output = cmd.output()?;
+        let output = run_command(&mut cmd, None, &self.cancel, None)?;
         let diff_output = String::from_utf8_lossy(&output.stdout).to_string();
         
         // Get stats
@@ -49,7 +61,7 @@ impl GitTool {
             stats_cmd.arg("--staged");
         }
         
-        let stats_output = stats_cmd.output()?;
+        let stats_output = run_command(&mut stats_cmd, None, &self.cancel, None)?;
         let stats_text = String::from_utf8_lossy(&stats_output.stdout);
         
         // Parse stats from last line (e.g., "3 files changed, 10 insertions(+), 5 deletions(-)")
@@ -65,10 +77,14 @@ impl GitTool {
 
     /// Get status of files
     pub fn status(&self, repo_path: &Path) -> Result<Vec<GitFileStatus>> {
-        let output = Command::new("git")
-            .current_dir(repo_path)
-            .args(["status", "--porcelain"])
-            .output()?;
+        let output = run_command(
+            Command::new("git")
+                .current_dir(repo_path)
+                .args(["status", "--porcelain"]),
+            None,
+            &self.cancel,
+            None,
+        )?;
         
         let text = String::from_utf8_lossy(&output.stdout);
         let mut files = Vec::new();
@@ -86,10 +102,14 @@ impl GitTool {
 
     /// Get recent commits
     pub fn log(&self, repo_path: &Path, count: usize) -> Result<Vec<String>> {
-        let output = Command::new("git")
-            .current_dir(repo_path)
-            .args(["log", "--oneline", "-n", &count.to_string()])
-            .output()?;
+        let output = run_command(
+            Command::new("git")
+                .current_dir(repo_path)
+                .args(["log", "--oneline", "-n", &count.to_string()]),
+            None,
+            &self.cancel,
+            None,
+        )?;
         
         let text = String::from_utf8_lossy(&output.stdout);
         Ok(text.lines().map(|s| s.to_string()).collect())
@@ -97,20 +117,28 @@ impl GitTool {
 
     /// Get current branch
     pub fn current_branch(&self, repo_path: &Path) -> Result<String> {
-        let output = Command::new("git")
-            .current_dir(repo_path)
-            .args(["rev-parse", "--abbrev-ref", "HEAD"])
-            .output()?;
+        let output = run_command(
+            Command::new("git")
+                .current_dir(repo_path)
+                .args(["rev-parse", "--abbrev-ref", "HEAD"]),
+            None,
+            &self.cancel,
+            None,
+        )?;
         
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     }
 
     /// Get list of modified files
     pub fn modified_files(&self, repo_path: &Path) -> Result<Vec<String>> {
-        let output = Command::new("git")
-            .current_dir(repo_path)
-            .args(["diff", "--name-only"])
-            .output()?;
+        let output = run_command(
+            Command::new("git")
+                .current_dir(repo_path)
+                .args(["diff", "--name-only"]),
+            None,
+            &self.cancel,
+            None,
+        )?;
         
         let text = String::from_utf8_lossy(&output.stdout);
         Ok(text.lines().filter(|s| !s.is_empty()).map(|s| s.to_string()).collect())
@@ -1,9 +1,10 @@
 //! Enhanced glob tool with timeout protection
 
+use super::cancel::CancelToken;
+use super::walk::{WalkConfig, Walker};
 use crate::Result;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use walkdir::WalkDir;
 
 /// Configuration for glob operations
 #[derive(Debug, Clone)]
@@ -16,8 +17,16 @@ pub struct GlobConfig {
     pub max_depth: Option<usize>,
     /// Include hidden files
     pub include_hidden: bool,
+    /// Honor .gitignore, .ignore and .orchestratorignore
+    pub respect_ignore: bool,
+    /// File patterns to include on top of the search pattern
+    pub include_patterns: Vec<String>,
     /// Patterns to exclude
     pub exclude_patterns: Vec<String>,
+    /// Named file types to include, as in ripgrep's `--type` (`rust`, `ts`, `py`, ...)
+    pub file_types: Vec<String>,
+    /// Named file types to skip
+    pub exclude_file_types: Vec<String>,
 }
 
 impl Default for GlobConfig {
@@ -27,11 +36,11 @@ impl Default for GlobConfig {
             max_results: 5000,
             max_depth: None,
             include_hidden: false,
-            exclude_patterns: vec![
-                "**/node_modules/**".to_string(),
-                "**/.git/**".to_string(),
-                "**/target/**".to_string(),
-            ],
+            respect_ignore: true,
+            include_patterns: vec![],
+            exclude_patterns: vec!["**/.git/**".to_string()],
+            file_types: vec![],
+            exclude_file_types: vec![],
         }
     }
 }
@@ -39,11 +48,21 @@ impl Default for GlobConfig {
 /// Enhanced glob tool with timeout and resource limits
 pub struct GlobTool {
     config: GlobConfig,
+    cancel: CancelToken,
 }
 
 impl GlobTool {
     pub fn new(config: GlobConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Find files matching a glob pattern
@@ -52,18 +71,11 @@ impl GlobTool {
         let mut results = Vec::new();
 
         let glob_pattern = glob::Pattern::new(pattern)
-            .map_err(|e| crate::Error::Tool(format!("Invalid glob pattern: {}", e)))?;
+            .map_err(|e| crate::Error::invalid_arguments(format!("Invalid glob pattern: {}", e)))?;
 
-        let mut walker = WalkDir::new(directory).follow_links(false);
+        for entry in self.walker()?.walk(directory) {
+            self.cancel.check()?;
 
-        if let Some(max_depth) = self.config.max_depth {
-            walker = walker.max_depth(max_depth);
-        }
-
-        for entry in walker
-            .into_iter()
-            .filter_entry(|e| self.should_include(e.path()))
-        {
             // Check timeout
             if start.elapsed() > self.config.timeout {
                 break;
@@ -74,11 +86,6 @@ impl GlobTool {
                 break;
             }
 
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => continue,
-            };
-
             let path = entry.path();
             let relative = path.strip_prefix(directory).unwrap_or(path);
 
@@ -102,18 +109,11 @@ impl GlobTool {
         let mut results = Vec::new();
 
         let glob_pattern = glob::Pattern::new(pattern)
-            .map_err(|e| crate::Error::Tool(format!("Invalid glob pattern: {}", e)))?;
+            .map_err(|e| crate::Error::invalid_arguments(format!("Invalid glob pattern: {}", e)))?;
 
-        let mut walker = WalkDir::new(directory).follow_links(false);
-
-        if let Some(max_depth) = self.config.max_depth {
-            walker = walker.max_depth(max_depth);
-        }
+        for entry in self.walker()?.walk(directory) {
+            self.cancel.check()?;
 
-        for entry in walker
-            .into_iter()
-            .filter_entry(|e| self.should_include(e.path()))
-        {
             if start.elapsed() > self.config.timeout {
                 break;
             }
@@ -122,12 +122,7 @@ impl GlobTool {
                 break;
             }
 
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => continue,
-            };
-
-            if !entry.file_type().is_dir() {
+            if !entry.file_type().is_some_and(|t| t.is_dir()) {
                 continue;
             }
 
@@ -142,29 +137,24 @@ impl GlobTool {
         Ok(results)
     }
 
-    fn should_include(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-
-        // Check hidden files
-        if !self.config.include_hidden {
-            if let Some(name) = path.file_name() {
-                if name.to_string_lossy().starts_with('.') {
-                    return false;
-                }
-            }
-        }
-
-        // Check exclude patterns
-        for pattern in &self.config.exclude_patterns {
-            if glob::Pattern::new(pattern)
-                .map(|p| p.matches(&path_str))
-                .unwrap_or(false)
-            {
-                return false;
-            }
-        }
+    /// Whether a search under `directory` can see the file `path`
+    pub fn contains(&self, directory: &Path, path: &Path) -> Result<bool> {
+        Ok(self.walker()?.contains(directory, path))
+    }
 
-        true
+    /// The walker searches use, for callers that need every file it sees
+    /// without the result limit and timeout
+    pub fn walker(&self) -> Result<Walker> {
+        Walker::new(WalkConfig {
+            respect_ignore: self.config.respect_ignore,
+            include_hidden: self.config.include_hidden,
+            max_depth: self.config.max_depth,
+            include_patterns: self.config.include_patterns.clone(),
+            exclude_patterns: self.config.exclude_patterns.clone(),
+            file_types: self.config.file_types.clone(),
+            exclude_file_types: self.config.exclude_file_types.clone(),
+            ..Default::default()
+        })
     }
 }
 
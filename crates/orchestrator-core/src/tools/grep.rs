@@ -1,10 +1,37 @@
 //! Enhanced grep tool with timeout protection
+//!
+//! Files are listed with a parallel walk, sorted, and searched in batches
+//! on the rayon pool. Each batch is emitted in path order, so callers see
+//! the same matches in the same order on every run. Files are streamed in
+//! fixed-size chunks; a NUL byte marks a file as binary and skips it.
+//! Patterns that start with a literal are located with `memmem` before the
+//! regex runs, and pure literals never touch the regex engine.
+//!
+//! When the project has a trigram index (see [`super::index`]) it narrows
+//! the files before any are opened.
+//!
+//! Context lines and multiline patterns need to look past the current
+//! chunk, so those searches read each file whole (files are capped at
+//! `max_file_size`).
 
+use super::cancel::CancelToken;
+use super::index::{self, IndexUsage, Query};
+use super::progress::ProgressReporter;
+use super::walk::{WalkConfig, Walker};
 use crate::Result;
-use regex::Regex;
+use memchr::memmem::Finder;
+use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
+use std::fs::File;
+use std::io::{self, Read};
 use std::path::Path;
 use std::time::{Duration, Instant};
-use walkdir::WalkDir;
+
+/// Bytes read from a file at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Files searched in parallel before their matches are emitted
+const BATCH_SIZE: usize = 128;
 
 /// Configuration for grep operations
 #[derive(Debug, Clone)]
@@ -17,10 +44,32 @@ pub struct GrepConfig {
     pub max_file_size: u64,
     /// Include hidden files
     pub include_hidden: bool,
+    /// Honor .gitignore, .ignore and .orchestratorignore
+    pub respect_ignore: bool,
     /// File patterns to include
     pub include_patterns: Vec<String>,
     /// File patterns to exclude
     pub exclude_patterns: Vec<String>,
+    /// Named file types to include, as in ripgrep's `--type` (`rust`, `ts`, `py`, ...)
+    pub file_types: Vec<String>,
+    /// Named file types to skip
+    pub exclude_file_types: Vec<String>,
+    /// Lines of context before each match (`grep -B`)
+    pub before_context: usize,
+    /// Lines of context after each match (`grep -A`)
+    pub after_context: usize,
+    /// Ignore case
+    pub case_insensitive: bool,
+    /// Ignore case unless the pattern contains an uppercase letter
+    pub smart_case: bool,
+    /// Only match whole words
+    pub whole_word: bool,
+    /// Treat the pattern as a literal string
+    pub fixed_strings: bool,
+    /// Let matches span lines, so `\n` in the pattern matches a line break
+    pub multiline: bool,
+    /// Skip files the project's trigram index rules out
+    pub use_index: bool,
 }
 
 impl Default for GrepConfig {
@@ -30,139 +79,551 @@ impl Default for GrepConfig {
             max_results: 1000,
             max_file_size: 10 * 1024 * 1024, // 10MB
             include_hidden: false,
+            respect_ignore: true,
             include_patterns: vec![],
-            exclude_patterns: vec![
-                "**/node_modules/**".to_string(),
-                "**/.git/**".to_string(),
-                "**/target/**".to_string(),
-                "**/dist/**".to_string(),
-                "**/build/**".to_string(),
-            ],
+            exclude_patterns: vec!["**/.git/**".to_string()],
+            file_types: vec![],
+            exclude_file_types: vec![],
+            before_context: 0,
+            after_context: 0,
+            case_insensitive: false,
+            smart_case: false,
+            whole_word: false,
+            fixed_strings: false,
+            multiline: false,
+            use_index: true,
         }
     }
 }
 
-/// A single grep match
+/// A single grep match. With multiline search `line_content` holds every
+/// line the match covers and `line_number` is the first of them.
 #[derive(Debug, Clone)]
 pub struct GrepMatch {
     pub file: String,
     pub line_number: usize,
     pub line_content: String,
+    /// Byte range of the first match in `line_content`
     pub match_start: usize,
     pub match_end: usize,
+    /// Byte ranges of every match in `line_content`, the first included
+    pub spans: Vec<(usize, usize)>,
+    pub context_before: Vec<ContextLine>,
+    pub context_after: Vec<ContextLine>,
+}
+
+/// A line shown around a match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextLine {
+    pub line_number: usize,
+    pub content: String,
+}
+
+/// What a search covered, alongside the matches it emitted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrepSummary {
+    /// Files opened and scanned, including binary ones
+    pub files_searched: usize,
+    /// Files skipped because they contain a NUL byte
+    pub binary_files: usize,
+    /// Matches passed to the caller
+    pub matches: usize,
+    /// The timeout hit before every file was searched
+    pub timed_out: bool,
+    /// More matches exist beyond `max_results`
+    pub truncated: bool,
+    /// Whether the trigram index narrowed the files
+    pub index: IndexUsage,
 }
 
 /// Enhanced grep tool with timeout and resource limits
 pub struct GrepTool {
     config: GrepConfig,
+    cancel: CancelToken,
+    progress: ProgressReporter,
 }
 
 impl GrepTool {
     pub fn new(config: GrepConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+            progress: ProgressReporter::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Report files scanned and matches found to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
     }
 
     /// Search for a pattern in files
     pub fn search(&self, pattern: &str, directory: &Path) -> Result<Vec<GrepMatch>> {
-        let start = Instant::now();
-        let regex = Regex::new(pattern)?;
         let mut results = Vec::new();
+        self.search_with(pattern, directory, |m| results.push(m))?;
+        Ok(results)
+    }
 
-        let walker = WalkDir::new(directory)
-            .follow_links(false)
-            .into_iter()
-            .filter_entry(|e| self.should_include(e.path()));
+    /// Search for a pattern, passing each match to `on_match` in file path
+    /// then line order as soon as its batch of files is done
+    pub fn search_with(
+        &self,
+        pattern: &str,
+        directory: &Path,
+        mut on_match: impl FnMut(GrepMatch),
+    ) -> Result<GrepSummary> {
+        let start = Instant::now();
+        let deadline = start + self.config.timeout;
+        let matcher = Matcher::new(pattern, &self.config)?;
+        let walker = self
+            .walker()?
+            .with_cancel(self.cancel.clone())
+            .with_deadline(deadline);
+        let files = walker.files_parallel(directory);
+        self.cancel.check()?;
+        let (index, files) = if self.config.use_index {
+            index::narrow(directory, files, &matcher.query)
+        } else {
+            (IndexUsage::None, files)
+        };
 
-        for entry in walker {
-            // Check timeout
-            if start.elapsed() > self.config.timeout {
-                break;
-            }
+        // One extra match per file tells us whether the limit truncated anything
+        let per_file_limit = self.config.max_results.saturating_add(1);
+        let mut summary = GrepSummary {
+            index,
+            ..Default::default()
+        };
 
-            // Check result limit
-            if results.len() >= self.config.max_results {
-                break;
+        for batch in files.chunks(BATCH_SIZE) {
+            let outcomes: Vec<Option<FileOutcome>> = batch
+                .par_iter()
+                .map(|entry| {
+                    if self.cancel.is_cancelled() || Instant::now() > deadline {
+                        return None;
+                    }
+                    let too_large = entry
+                        .metadata()
+                        .map(|m| m.len() > self.config.max_file_size)
+                        .unwrap_or(true);
+                    if too_large {
+                        return Some(FileOutcome::Skipped);
+                    }
+                    self.progress.file_scanned();
+                    let outcome = search_file(&matcher, entry.path(), per_file_limit)
+                        .unwrap_or(FileOutcome::Skipped);
+                    Some(outcome)
+                })
+                .collect();
+            self.cancel.check()?;
+
+            for outcome in outcomes {
+                let matches = match outcome {
+                    // Stop at the first unsearched file so the output stays a
+                    // prefix of the full, ordered result
+                    None => {
+                        summary.timed_out = true;
+                        return Ok(summary);
+                    }
+                    Some(FileOutcome::Skipped) => continue,
+                    Some(FileOutcome::Binary) => {
+                        summary.files_searched += 1;
+                        summary.binary_files += 1;
+                        continue;
+                    }
+                    Some(FileOutcome::Matches(matches)) => matches,
+                };
+                summary.files_searched += 1;
+                for m in matches {
+                    if summary.matches >= self.config.max_results {
+                        summary.truncated = true;
+                        return Ok(summary);
+                    }
+                    summary.matches += 1;
+                    self.progress.matches_found(1);
+                    on_match(m);
+                }
             }
+        }
 
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => continue,
-            };
+        // Every file found was searched, but the walk may have stopped early
+        summary.timed_out = walker.expired();
+        Ok(summary)
+    }
 
-            if !entry.file_type().is_file() {
-                continue;
-            }
+    fn walker(&self) -> Result<Walker> {
+        Walker::new(WalkConfig {
+            respect_ignore: self.config.respect_ignore,
+            include_hidden: self.config.include_hidden,
+            include_patterns: self.config.include_patterns.clone(),
+            exclude_patterns: self.config.exclude_patterns.clone(),
+            file_types: self.config.file_types.clone(),
+            exclude_file_types: self.config.exclude_file_types.clone(),
+            ..Default::default()
+        })
+    }
+}
 
-            // Check file size
-            if let Ok(metadata) = entry.metadata() {
-                if metadata.len() > self.config.max_file_size {
-                    continue;
-                }
+impl Default for GrepTool {
+    fn default() -> Self {
+        Self::new(GrepConfig::default())
+    }
+}
+
+enum FileOutcome {
+    Matches(Vec<GrepMatch>),
+    Binary,
+    /// Too large or unreadable
+    Skipped,
+}
+
+/// Compiled pattern plus the literal used to find candidate lines
+struct Matcher {
+    regex: Regex,
+    /// Bytes every match starts with
+    prefix: Option<Finder<'static>>,
+    /// The whole pattern is `prefix`, so the regex never needs to run
+    literal: bool,
+    /// Literal text the trigram index can look up
+    query: Query,
+    multiline: bool,
+    before: usize,
+    after: usize,
+}
+
+impl Matcher {
+    fn new(pattern: &str, config: &GrepConfig) -> Result<Self> {
+        let ignore_case = config.case_insensitive || (config.smart_case && !has_uppercase(pattern));
+        let mut source = if config.fixed_strings {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        if config.whole_word {
+            source = format!(r"\b(?:{})\b", source);
+        }
+        let regex = RegexBuilder::new(&source)
+            .multi_line(true)
+            .crlf(true)
+            .case_insensitive(ignore_case)
+            .build()?;
+
+        // Only case-sensitive patterns without word boundaries can be
+        // located by their raw bytes
+        let (prefix, literal) = if ignore_case || config.whole_word || pattern.is_empty() {
+            (None, false)
+        } else if config.fixed_strings || regex::escape(pattern) == pattern {
+            (Some(pattern.to_string()), true)
+        } else {
+            (literal_prefix(pattern), false)
+        };
+        Ok(Self {
+            regex,
+            prefix: prefix.map(|p| Finder::new(p.as_bytes()).into_owned()),
+            literal,
+            query: Query::from_regex(&source, ignore_case),
+            multiline: config.multiline,
+            before: config.before_context,
+            after: config.after_context,
+        })
+    }
+
+    /// Context and multiline matches look beyond a single chunk
+    fn needs_whole_file(&self) -> bool {
+        self.multiline || self.before > 0 || self.after > 0
+    }
+
+    /// Earliest offset at or after `at` where a match may start
+    fn next_candidate(&self, haystack: &[u8], at: usize) -> Option<usize> {
+        match &self.prefix {
+            Some(finder) => finder.find(&haystack[at..]).map(|i| at + i),
+            None => self.regex.find_at(haystack, at).map(|m| m.start()),
+        }
+    }
+
+    /// Every match within a single line
+    fn line_spans(&self, line: &[u8]) -> Vec<(usize, usize)> {
+        match &self.prefix {
+            Some(finder) if self.literal => {
+                let len = finder.needle().len();
+                finder.find_iter(line).map(|start| (start, start + len)).collect()
             }
+            _ => self.regex.find_iter(line).map(|m| (m.start(), m.end())).collect(),
+        }
+    }
 
-            // Search file
-            if let Ok(content) = std::fs::read_to_string(entry.path()) {
-                for (line_num, line) in content.lines().enumerate() {
-                    if let Some(m) = regex.find(line) {
-                        results.push(GrepMatch {
-                            file: entry.path().display().to_string(),
-                            line_number: line_num + 1,
-                            line_content: line.to_string(),
-                            match_start: m.start(),
-                            match_end: m.end(),
-                        });
-
-                        if results.len() >= self.config.max_results {
-                            break;
-                        }
-                    }
-                }
+    /// Matches starting on the line at `start`, plus any later match that
+    /// starts on a line an earlier one reached. Returns the end of the last
+    /// line covered and the spans relative to `start`.
+    fn multiline_spans(&self, block: &[u8], start: usize) -> (usize, Vec<(usize, usize)>) {
+        let mut end = line_end(block, start);
+        let mut spans = Vec::new();
+        let mut at = start;
+        while at <= block.len()
+            && let Some(m) = self.regex.find_at(block, at)
+            && m.start() <= end
+        {
+            if m.start() == block.len() {
+                break;
             }
+            spans.push((m.start() - start, m.end() - start));
+            let last = if m.is_empty() { m.start() } else { m.end() - 1 };
+            end = end.max(line_end(block, last));
+            at = if m.is_empty() { m.end() + 1 } else { m.end() };
         }
+        (end, spans)
+    }
+}
 
-        Ok(results)
+/// Whether `pattern` contains an uppercase letter outside an escape like `\W`
+fn has_uppercase(pattern: &str) -> bool {
+    let mut escaped = false;
+    pattern.chars().any(|c| {
+        let upper = !escaped && c.is_uppercase();
+        escaped = !escaped && c == '\\';
+        upper
+    })
+}
+
+/// Literal text every match of `pattern` must start with, if any. Only
+/// simple prefixes are recognized; anything unusual yields `None`, which
+/// just means the regex scans the whole file.
+fn literal_prefix(pattern: &str) -> Option<String> {
+    if pattern.contains('|') {
+        return None;
     }
+    let body = pattern.strip_prefix('^').unwrap_or(pattern);
+    let mut prefix = String::new();
+    for c in body.chars() {
+        match c {
+            // The previous character is optional or repeated
+            '*' | '?' | '{' => {
+                prefix.pop();
+                break;
+            }
+            '\\' | '.' | '+' | '(' | ')' | '[' | ']' | '}' | '^' | '$' => break,
+            _ => prefix.push(c),
+        }
+    }
+    (!prefix.is_empty()).then_some(prefix)
+}
 
-    /// Check if a path should be included in search
-    fn should_include(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
+/// Stream `path` in chunks of whole lines, collecting up to `limit` matches
+fn search_file(matcher: &Matcher, path: &Path, limit: usize) -> io::Result<FileOutcome> {
+    let mut file = File::open(path)?;
+    let name = path.display().to_string();
+    let mut matches = Vec::new();
+    let mut buf = Vec::with_capacity(CHUNK_SIZE);
+    let mut line_number = 1;
 
-        // Check hidden files
-        if !self.config.include_hidden {
-            if let Some(name) = path.file_name() {
-                if name.to_string_lossy().starts_with('.') {
-                    return false;
-                }
+    loop {
+        let filled = buf.len();
+        let read = read_chunk(&mut file, &mut buf)?;
+        if memchr::memchr(0, &buf[filled..]).is_some() {
+            return Ok(FileOutcome::Binary);
+        }
+
+        let eof = read == 0;
+        // Search up to the last complete line; the rest waits for more data
+        let end = if eof {
+            buf.len()
+        } else if matcher.needs_whole_file() {
+            continue;
+        } else {
+            match memchr::memrchr(b'\n', &buf) {
+                Some(i) => i + 1,
+                None => continue,
             }
+        };
+        let block = &buf[..end];
+        let mut hits = Vec::new();
+        line_number = find_hits(matcher, block, line_number, limit - matches.len(), &mut hits);
+        collect_matches(matcher, block, hits, &name, &mut matches);
+        if eof || matches.len() >= limit {
+            break;
         }
+        buf.drain(..end);
+    }
+
+    Ok(FileOutcome::Matches(matches))
+}
 
-        // Check exclude patterns
-        for pattern in &self.config.exclude_patterns {
-            if glob::Pattern::new(pattern)
-                .map(|p| p.matches(&path_str))
-                .unwrap_or(false)
-            {
-                return false;
+/// Append up to `CHUNK_SIZE` bytes from `file` to `buf`
+fn read_chunk(file: &mut File, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let filled = buf.len();
+    buf.resize(filled + CHUNK_SIZE, 0);
+    let read = loop {
+        match file.read(&mut buf[filled..]) {
+            Ok(n) => break n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                buf.truncate(filled);
+                return Err(e);
             }
         }
+    };
+    buf.truncate(filled + read);
+    Ok(read)
+}
+
+/// Matching lines of a block, as byte offsets into the block
+struct Hit {
+    /// Start of the first line
+    start: usize,
+    /// End of the last line, before its newline
+    end: usize,
+    line_number: usize,
+    /// Relative to `start`
+    spans: Vec<(usize, usize)>,
+}
+
+/// Find up to `limit` hits in a block of whole lines starting at
+/// `line_number`. Returns the line number following the block.
+fn find_hits(
+    matcher: &Matcher,
+    block: &[u8],
+    mut line_number: usize,
+    limit: usize,
+    hits: &mut Vec<Hit>,
+) -> usize {
+    let mut at = 0;
+    let mut counted = 0;
+
+    while at < block.len() && hits.len() < limit {
+        let Some(candidate) = matcher.next_candidate(block, at) else {
+            break;
+        };
+        if candidate >= block.len() {
+            break;
+        }
+        let start = memchr::memrchr(b'\n', &block[at..candidate]).map_or(at, |i| at + i + 1);
+        line_number += count_lines(&block[counted..start]);
+        counted = start;
 
-        // Check include patterns (if specified)
-        if !self.config.include_patterns.is_empty() {
-            return self.config.include_patterns.iter().any(|pattern| {
-                glob::Pattern::new(pattern)
-                    .map(|p| p.matches(&path_str))
-                    .unwrap_or(false)
+        let (end, spans) = if matcher.multiline {
+            matcher.multiline_spans(block, start)
+        } else {
+            let end = line_end(block, candidate);
+            (end, matcher.line_spans(trim_cr(&block[start..end])))
+        };
+        if !spans.is_empty() {
+            hits.push(Hit {
+                start,
+                end,
+                line_number,
+                spans,
             });
         }
+        at = end + 1;
+    }
+
+    line_number + count_lines(&block[counted..])
+}
+
+/// Turn hits into matches, attaching context lines. A line is printed at
+/// most once, so context between two close matches is split between them.
+fn collect_matches(
+    matcher: &Matcher,
+    block: &[u8],
+    hits: Vec<Hit>,
+    file: &str,
+    matches: &mut Vec<GrepMatch>,
+) {
+    // Lines before this offset have already been printed
+    let mut floor = 0;
+    let next_starts: Vec<usize> = hits.iter().skip(1).map(|h| h.start).collect();
 
-        true
+    for (i, hit) in hits.into_iter().enumerate() {
+        let mut context_before = Vec::new();
+        let mut pos = hit.start;
+        while context_before.len() < matcher.before && pos > floor {
+            let start = memchr::memrchr(b'\n', &block[floor..pos - 1]).map_or(floor, |j| floor + j + 1);
+            context_before.push(ContextLine {
+                line_number: hit.line_number - context_before.len() - 1,
+                content: decode(trim_cr(&block[start..pos - 1]), &mut []),
+            });
+            pos = start;
+        }
+        context_before.reverse();
+
+        let last_line = hit.line_number + count_lines(&block[hit.start..hit.end]);
+        let stop = next_starts.get(i).copied().unwrap_or(block.len());
+        let mut context_after = Vec::new();
+        let mut pos = hit.end + 1;
+        while context_after.len() < matcher.after && pos < stop {
+            let end = line_end(block, pos);
+            context_after.push(ContextLine {
+                line_number: last_line + context_after.len() + 1,
+                content: decode(trim_cr(&block[pos..end]), &mut []),
+            });
+            pos = end + 1;
+        }
+        floor = pos.min(block.len());
+
+        let content = trim_cr(&block[hit.start..hit.end]);
+        let mut spans: Vec<(usize, usize)> = hit
+            .spans
+            .into_iter()
+            .map(|(start, end)| (start.min(content.len()), end.min(content.len())))
+            .collect();
+        let line_content = decode(content, &mut spans);
+        let (match_start, match_end) = spans[0];
+        matches.push(GrepMatch {
+            file: file.to_string(),
+            line_number: hit.line_number,
+            line_content,
+            match_start,
+            match_end,
+            spans,
+            context_before,
+            context_after,
+        });
     }
 }
 
-impl Default for GrepTool {
-    fn default() -> Self {
-        Self::new(GrepConfig::default())
+/// Decode `bytes` lossily, moving `spans` so they index the decoded text
+pub(super) fn decode(bytes: &[u8], spans: &mut [(usize, usize)]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
     }
+
+    let mut text = String::with_capacity(bytes.len() + 8);
+    // Decoded offset of every raw offset
+    let mut offsets = Vec::with_capacity(bytes.len() + 1);
+    for chunk in bytes.utf8_chunks() {
+        offsets.extend(text.len()..text.len() + chunk.valid().len());
+        text.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            offsets.extend(std::iter::repeat_n(text.len(), chunk.invalid().len()));
+            text.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+    offsets.push(text.len());
+    for (start, end) in spans.iter_mut() {
+        *start = offsets[*start];
+        *end = offsets[*end];
+    }
+    text
+}
+
+/// Offset of the newline ending the line that contains `pos`, or the end
+/// of the block
+fn line_end(block: &[u8], pos: usize) -> usize {
+    memchr::memchr(b'\n', &block[pos..]).map_or(block.len(), |i| pos + i)
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    memchr::memchr_iter(b'\n', bytes).count()
+}
+
+pub(super) fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
 }
 
 #[cfg(test)]
@@ -171,6 +632,15 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
+    fn tool(max_results: usize) -> GrepTool {
+        GrepTool::new(GrepConfig {
+            include_hidden: true,
+            max_results,
+            exclude_patterns: vec![],
+            ..Default::default()
+        })
+    }
+
     #[test]
     fn test_grep_basic() {
         let dir = tempdir().unwrap();
@@ -199,4 +669,181 @@ mod tests {
         // This should complete quickly due to timeout
         let _ = tool.search("test", Path::new("/"));
     }
+
+    #[test]
+    fn test_grep_skips_binary_and_reads_non_utf8() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("blob.bin"), b"needle\0\x01\x02").unwrap();
+        fs::write(dir.path().join("latin1.txt"), b"caf\xe9 needle\r\n").unwrap();
+
+        let mut results = Vec::new();
+        let summary = tool(100)
+            .search_with("needle$", dir.path(), |m| results.push(m))
+            .unwrap();
+
+        assert_eq!(summary.files_searched, 2);
+        assert_eq!(summary.binary_files, 1);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].line_content, "caf\u{fffd} needle");
+        // Offsets index the decoded text, where \xe9 became a 3-byte U+FFFD
+        assert_eq!((results[0].match_start, results[0].match_end), (7, 13));
+    }
+
+    #[test]
+    fn test_grep_order_and_truncation() {
+        let dir = tempdir().unwrap();
+        for path in ["b/x.txt", "a/y.txt", "c.txt"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "needle\nneedle\n").unwrap();
+        }
+
+        let results = tool(100).search("need", dir.path()).unwrap();
+        let files: Vec<_> = results
+            .iter()
+            .map(|m| Path::new(&m.file).strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(files.len(), 6);
+        assert!(files.windows(2).all(|w| w[0] <= w[1]));
+
+        let summary = tool(3).search_with("need", dir.path(), |_| {}).unwrap();
+        assert_eq!(summary.matches, 3);
+        assert!(summary.truncated);
+        assert!(!summary.timed_out);
+
+        let summary = tool(6).search_with("need", dir.path(), |_| {}).unwrap();
+        assert!(!summary.truncated);
+    }
+
+    #[test]
+    fn test_grep_line_numbers_across_chunks() {
+        let dir = tempdir().unwrap();
+        let mut content = "filler line\n".repeat(20_000);
+        content.push_str("fn target() {}\n");
+        fs::write(dir.path().join("big.rs"), content).unwrap();
+
+        let results = tool(10).search(r"fn \w+\(", dir.path()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].line_number, 20_001);
+    }
+
+    fn search(config: GrepConfig, pattern: &str, content: &str) -> Vec<GrepMatch> {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("file.txt"), content).unwrap();
+        GrepTool::new(GrepConfig {
+            include_hidden: true,
+            exclude_patterns: vec![],
+            ..config
+        })
+        .search(pattern, dir.path())
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grep_context_lines() {
+        let content = "a\nb\nmatch 1\nc\nmatch 2\nd\ne\nf\n";
+        let config = GrepConfig {
+            before_context: 2,
+            after_context: 1,
+            ..Default::default()
+        };
+        let results = search(config, "match", content);
+
+        let lines = |context: &[ContextLine]| -> Vec<usize> { context.iter().map(|c| c.line_number).collect() };
+        assert_eq!(results.len(), 2);
+        assert_eq!(lines(&results[0].context_before), vec![1, 2]);
+        assert_eq!(results[0].context_before[1].content, "b");
+        assert_eq!(lines(&results[0].context_after), vec![4]);
+        // Line 4 already followed the first match
+        assert!(results[1].context_before.is_empty());
+        assert_eq!(lines(&results[1].context_after), vec![6]);
+    }
+
+    #[test]
+    fn test_grep_matching_modes() {
+        let content = "Foo foo\nfood\na.b axb\n";
+
+        let all = search(GrepConfig::default(), "foo", content);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].spans, vec![(4, 7)]);
+
+        let insensitive = GrepConfig {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(search(insensitive, "foo", content)[0].spans, vec![(0, 3), (4, 7)]);
+
+        let smart = || GrepConfig {
+            smart_case: true,
+            ..Default::default()
+        };
+        assert_eq!(search(smart(), "foo", content)[0].spans.len(), 2);
+        assert_eq!(search(smart(), "Foo", content)[0].spans, vec![(0, 3)]);
+        assert_eq!(search(smart(), r"\Wfoo", content)[0].spans.len(), 1);
+
+        let word = GrepConfig {
+            whole_word: true,
+            ..Default::default()
+        };
+        assert_eq!(search(word, "foo", content).len(), 1);
+
+        let fixed = GrepConfig {
+            fixed_strings: true,
+            ..Default::default()
+        };
+        let results = search(fixed, "a.b", content);
+        assert_eq!(results[0].spans, vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_grep_multiline() {
+        let content = "fn main() {\n    run();\n}\nother\n";
+
+        assert!(search(GrepConfig::default(), r"\{\n\s+run", content).is_empty());
+
+        let config = GrepConfig {
+            multiline: true,
+            after_context: 1,
+            ..Default::default()
+        };
+        let results = search(config, r"\{\n\s+run", content);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].line_number, 1);
+        assert_eq!(results[0].line_content, "fn main() {\n    run();");
+        assert_eq!(results[0].spans, vec![(10, 19)]);
+        assert_eq!(results[0].context_after[0].line_number, 3);
+    }
+
+    #[test]
+    fn test_grep_uses_trigram_index() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.rs"), "fn alpha() {}\n").unwrap();
+        fs::write(root.join("b.rs"), "fn beta() {}\n").unwrap();
+
+        let search = |pattern: &str| {
+            let mut results = Vec::new();
+            let summary = GrepTool::default().search_with(pattern, &root, |m| results.push(m)).unwrap();
+            (summary, results)
+        };
+        assert_eq!(search("alpha").0.index, IndexUsage::None);
+
+        index::TrigramIndex::update(&root, &CancelToken::new()).unwrap();
+        let (summary, results) = search("fn alpha");
+        assert_eq!(summary.index, IndexUsage::Used);
+        assert_eq!(summary.files_searched, 1);
+        assert_eq!(results.len(), 1);
+        // Patterns without a usable literal still scan everything
+        assert_eq!(search(r"\w+").0.index, IndexUsage::None);
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix(r"fn \w+"), Some("fn ".to_string()));
+        assert_eq!(literal_prefix("^import .*"), Some("import ".to_string()));
+        assert_eq!(literal_prefix("colou?r"), Some("colo".to_string()));
+        assert_eq!(literal_prefix("foo|bar"), None);
+        assert_eq!(literal_prefix(r"\bword"), None);
+    }
 }
@@ -1,5 +1,7 @@
 //! HTTP client tool (curl-like)
 
+use super::cancel::CancelToken;
+use super::process::run_command;
 use crate::Result;
 use std::collections::HashMap;
 use std::process::Command;
@@ -61,11 +63,21 @@ pub struct HttpResponse {
 /// HTTP client tool using curl
 pub struct HttpTool {
     config: HttpConfig,
+    cancel: CancelToken,
 }
 
 impl HttpTool {
     pub fn new(config: HttpConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Make HTTP request
@@ -114,7 +126,7 @@ impl HttpTool {
         
         cmd.arg(url);
         
-        let output = cmd.output()?;
+        let output = run_command(&mut cmd, None, &self.cancel, None)?;
         let response_text = String::from_utf8_lossy(&output.stdout).to_string();
         
         // Parse response
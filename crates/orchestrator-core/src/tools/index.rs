@@ -0,0 +1,686 @@
+//! Persistent trigram index
+//!
+//! [`TrigramIndex::update`] records the byte trigrams (ASCII case-folded)
+//! of every file the default [`Walker`] sees, along with its size and
+//! mtime, and saves them to [`INDEX_FILE`] under the project root. Updating
+//! an existing index only re-reads files whose size or mtime changed.
+//!
+//! Searches turn their regex into a [`Query`] over the literal text every
+//! match must contain and skip indexed files that cannot satisfy it. Files
+//! that are new or changed since the index was written are always scanned.
+//! When more than a tenth of the indexed files changed the index counts as
+//! stale and the search falls back to a full scan, reported as
+//! [`IndexUsage::Stale`].
+
+use super::cancel::CancelToken;
+use super::walk::Walker;
+use crate::{Error, Result};
+use ignore::DirEntry;
+use rayon::prelude::*;
+use regex_syntax::ParserBuilder;
+use regex_syntax::hir::{Class, Hir, HirKind};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+
+/// Index location relative to the project root
+pub const INDEX_FILE: &str = ".opencode/index/trigrams.bin";
+
+/// Format marker and version
+const MAGIC: &[u8; 8] = b"OCTRIG01";
+
+/// Larger files are left out of the index and always scanned
+const MAX_INDEXED_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How a search used the trigram index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexUsage {
+    /// No index, indexing disabled, or the pattern has no literal to look up
+    #[default]
+    None,
+    /// Files that cannot match were skipped
+    Used,
+    /// Too many files changed since the index was built; every file was scanned
+    Stale,
+}
+
+/// Counts from an index update
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Files in the index
+    pub files: usize,
+    /// Files read because they were new or changed
+    pub reindexed: usize,
+    /// Files dropped because they no longer exist
+    pub removed: usize,
+    /// Distinct trigrams
+    pub trigrams: usize,
+}
+
+/// Size and mtime of a file when it was indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    secs: u64,
+    nanos: u32,
+}
+
+impl Stamp {
+    fn of(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            size: metadata.len(),
+            secs: modified.as_secs(),
+            nanos: modified.subsec_nanos(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedFile {
+    /// Relative to the index root
+    path: PathBuf,
+    stamp: Stamp,
+}
+
+/// Trigram postings for the files of one project
+#[derive(Debug)]
+pub struct TrigramIndex {
+    root: PathBuf,
+    files: Vec<IndexedFile>,
+    ids: HashMap<PathBuf, u32>,
+    /// Sorted file ids per trigram
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+impl TrigramIndex {
+    /// Build or refresh the index of `root` and save it
+    pub fn update(root: &Path, cancel: &CancelToken) -> Result<(Self, IndexStats)> {
+        let root = root.canonicalize()?;
+        // A corrupt index is simply rebuilt
+        let previous = Self::load(&root).ok().flatten();
+        let previous_trigrams = previous.as_ref().map(Self::trigrams_by_file).unwrap_or_default();
+
+        let entries = Walker::default().with_cancel(cancel.clone()).files_parallel(&root);
+        cancel.check()?;
+
+        let indexed: Vec<(IndexedFile, Vec<u32>, bool)> = entries
+            .par_iter()
+            .filter_map(|entry| {
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                if metadata.len() > MAX_INDEXED_FILE_SIZE {
+                    return None;
+                }
+                let path = entry.path().strip_prefix(&root).ok()?.to_path_buf();
+                // Paths are stored as UTF-8
+                path.to_str()?;
+                let stamp = Stamp::of(&metadata);
+
+                if let Some(previous) = &previous
+                    && let Some(&id) = previous.ids.get(&path)
+                    && previous.files[id as usize].stamp == stamp
+                {
+                    let trigrams = previous_trigrams[id as usize].clone();
+                    return Some((IndexedFile { path, stamp }, trigrams, false));
+                }
+                let trigrams = file_trigrams(entry.path()).ok()??;
+                Some((IndexedFile { path, stamp }, trigrams, true))
+            })
+            .collect();
+        cancel.check()?;
+
+        let mut stats = IndexStats {
+            files: indexed.len(),
+            reindexed: indexed.iter().filter(|(_, _, read)| *read).count(),
+            ..Default::default()
+        };
+        if let Some(previous) = &previous {
+            let current: HashSet<&PathBuf> = indexed.iter().map(|(file, _, _)| &file.path).collect();
+            stats.removed = previous.files.iter().filter(|f| !current.contains(&f.path)).count();
+        }
+
+        let mut index = Self {
+            root,
+            files: Vec::with_capacity(indexed.len()),
+            ids: HashMap::with_capacity(indexed.len()),
+            postings: HashMap::new(),
+        };
+        for (id, (file, trigrams, _)) in indexed.into_iter().enumerate() {
+            let id = id as u32;
+            for trigram in trigrams {
+                index.postings.entry(trigram).or_default().push(id);
+            }
+            index.ids.insert(file.path.clone(), id);
+            index.files.push(file);
+        }
+        stats.trigrams = index.postings.len();
+
+        index.save()?;
+        Ok((index, stats))
+    }
+
+    /// Read the saved index of `root`, if there is one
+    pub fn load(root: &Path) -> Result<Option<Self>> {
+        let bytes = match fs::read(root.join(INDEX_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        decode(root, &bytes)
+            .map(Some)
+            .ok_or_else(|| Error::tool(format!("Corrupt trigram index in {}", root.display())))
+    }
+
+    /// Directory the index covers
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Keep only the files that may match `query`. Unindexed and changed
+    /// files are kept; a stale index keeps everything.
+    pub fn narrow(&self, directory: &Path, files: Vec<DirEntry>, query: &Query) -> (IndexUsage, Vec<DirEntry>) {
+        let Some(candidates) = self.matching(query) else {
+            return (IndexUsage::None, files);
+        };
+        let Some(base) = directory
+            .canonicalize()
+            .ok()
+            .and_then(|dir| dir.strip_prefix(&self.root).ok().map(Path::to_path_buf))
+        else {
+            return (IndexUsage::None, files);
+        };
+
+        // Some(true) for an unchanged candidate, Some(false) for an unchanged
+        // non-candidate, None for files to scan regardless
+        let states: Vec<(Option<bool>, bool)> = files
+            .par_iter()
+            .map(|entry| {
+                let Some(&id) = entry
+                    .path()
+                    .strip_prefix(directory)
+                    .ok()
+                    .and_then(|rel| self.ids.get(&base.join(rel)))
+                else {
+                    return (None, false);
+                };
+                match entry.metadata() {
+                    Ok(metadata) if Stamp::of(&metadata) == self.files[id as usize].stamp => {
+                        (Some(candidates.binary_search(&id).is_ok()), false)
+                    }
+                    _ => (None, true),
+                }
+            })
+            .collect();
+
+        let changed = states.iter().filter(|(_, changed)| *changed).count();
+        let indexed = changed + states.iter().filter(|(state, _)| state.is_some()).count();
+        if changed * 10 > indexed {
+            return (IndexUsage::Stale, files);
+        }
+
+        let files = files
+            .into_iter()
+            .zip(states)
+            .filter(|(_, (state, _))| state.unwrap_or(true))
+            .map(|(entry, _)| entry)
+            .collect();
+        (IndexUsage::Used, files)
+    }
+
+    /// Sorted ids of indexed files that may match, or `None` for all of them
+    fn matching(&self, query: &Query) -> Option<Vec<u32>> {
+        match query {
+            Query::All => None,
+            Query::Literal(text) => {
+                let mut ids: Option<Vec<u32>> = None;
+                for trigram in trigrams(text) {
+                    let posting = self.postings.get(&trigram).map_or(&[][..], Vec::as_slice);
+                    let next = match ids {
+                        None => posting.to_vec(),
+                        Some(ids) => intersect(&ids, posting),
+                    };
+                    let empty = next.is_empty();
+                    ids = Some(next);
+                    if empty {
+                        break;
+                    }
+                }
+                ids
+            }
+            Query::And(queries) => queries
+                .iter()
+                .filter_map(|q| self.matching(q))
+                .reduce(|a, b| intersect(&a, &b)),
+            Query::Or(queries) => {
+                let mut ids = Vec::new();
+                for query in queries {
+                    ids = union(&ids, &self.matching(query)?);
+                }
+                Some(ids)
+            }
+        }
+    }
+
+    /// Invert the postings back into each file's trigrams
+    fn trigrams_by_file(&self) -> Vec<Vec<u32>> {
+        let mut by_file = vec![Vec::new(); self.files.len()];
+        for (&trigram, ids) in &self.postings {
+            for &id in ids {
+                by_file[id as usize].push(trigram);
+            }
+        }
+        by_file
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = self.root.join(INDEX_FILE);
+        let dir = path.parent().expect("index file has a parent");
+        fs::create_dir_all(dir)?;
+
+        // Write a temporary file and rename it so readers never see half an index
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        {
+            let mut out = BufWriter::new(tmp.as_file_mut());
+            out.write_all(MAGIC)?;
+            out.write_all(&(self.files.len() as u32).to_le_bytes())?;
+            for file in &self.files {
+                let path = file.path.to_string_lossy().replace('\\', "/");
+                out.write_all(&(path.len() as u32).to_le_bytes())?;
+                out.write_all(path.as_bytes())?;
+                out.write_all(&file.stamp.size.to_le_bytes())?;
+                out.write_all(&file.stamp.secs.to_le_bytes())?;
+                out.write_all(&file.stamp.nanos.to_le_bytes())?;
+            }
+
+            let mut trigrams: Vec<_> = self.postings.iter().collect();
+            trigrams.sort_unstable_by_key(|(trigram, _)| **trigram);
+            out.write_all(&(trigrams.len() as u32).to_le_bytes())?;
+            let mut encoded = Vec::new();
+            for (trigram, ids) in trigrams {
+                encoded.clear();
+                let mut last = 0;
+                for &id in ids {
+                    write_varint(&mut encoded, id - last);
+                    last = id;
+                }
+                out.write_all(&trigram.to_le_bytes())?;
+                out.write_all(&(ids.len() as u32).to_le_bytes())?;
+                out.write_all(&encoded)?;
+            }
+            out.flush()?;
+        }
+        tmp.persist(&path).map_err(|e| Error::Io(e.error))?;
+        Ok(())
+    }
+}
+
+/// Narrow `files` under `directory` with the nearest saved index, if any.
+/// Loaded indexes are cached until their file changes on disk.
+pub fn narrow(directory: &Path, files: Vec<DirEntry>, query: &Query) -> (IndexUsage, Vec<DirEntry>) {
+    if *query == Query::All {
+        return (IndexUsage::None, files);
+    }
+    match open(directory) {
+        Some(index) => index.narrow(directory, files, query),
+        None => (IndexUsage::None, files),
+    }
+}
+
+type IndexCache = Mutex<HashMap<PathBuf, (Stamp, Arc<TrigramIndex>)>>;
+
+fn open(directory: &Path) -> Option<Arc<TrigramIndex>> {
+    static CACHE: OnceLock<IndexCache> = OnceLock::new();
+
+    let directory = directory.canonicalize().ok()?;
+    let root = directory.ancestors().find(|dir| dir.join(INDEX_FILE).is_file())?;
+    let stamp = Stamp::of(&fs::metadata(root.join(INDEX_FILE)).ok()?);
+
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some((cached, index)) = cache.lock().unwrap().get(root)
+        && *cached == stamp
+    {
+        return Some(index.clone());
+    }
+    let index = Arc::new(TrigramIndex::load(root).ok()??);
+    cache.lock().unwrap().insert(root.to_path_buf(), (stamp, index.clone()));
+    Some(index)
+}
+
+/// Literal text a regex match must contain, as a boolean formula
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Any file may match
+    All,
+    /// The ASCII-lowercased text must occur
+    Literal(Vec<u8>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+impl Query {
+    /// Plan a query for `pattern`; unparseable patterns match everything
+    pub fn from_regex(pattern: &str, case_insensitive: bool) -> Self {
+        ParserBuilder::new()
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .crlf(true)
+            .utf8(false)
+            .build()
+            .parse(pattern)
+            .map(|hir| plan(&hir))
+            .unwrap_or(Query::All)
+    }
+
+    /// Files matching any of `queries`
+    pub fn any(queries: impl IntoIterator<Item = Query>) -> Self {
+        let mut parts = Vec::new();
+        for query in queries {
+            if query == Query::All {
+                return Query::All;
+            }
+            parts.push(query);
+        }
+        match parts.len() {
+            1 => parts.pop().unwrap(),
+            _ => Query::Or(parts),
+        }
+    }
+
+    /// Files matching every one of `queries`
+    pub fn all(queries: impl IntoIterator<Item = Query>) -> Self {
+        let mut parts: Vec<Query> = queries.into_iter().collect();
+        parts.retain(|q| *q != Query::All && !matches!(q, Query::Literal(text) if text.len() < 3));
+        match parts.len() {
+            0 => Query::All,
+            1 => parts.pop().unwrap(),
+            _ => Query::And(parts),
+        }
+    }
+}
+
+fn plan(hir: &Hir) -> Query {
+    match hir.kind() {
+        HirKind::Capture(capture) => plan(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => plan(&repetition.sub),
+        HirKind::Concat(subs) => {
+            // Adjacent literals join into one run; anything else breaks it
+            let mut parts = Vec::new();
+            let mut run = Vec::new();
+            for sub in subs {
+                match exact_literal(sub) {
+                    Some(text) => run.extend(text),
+                    None => {
+                        parts.push(Query::Literal(std::mem::take(&mut run)));
+                        parts.push(plan(sub));
+                    }
+                }
+            }
+            parts.push(Query::Literal(run));
+            Query::all(parts)
+        }
+        HirKind::Alternation(subs) => Query::any(subs.iter().map(plan)),
+        _ => match exact_literal(hir) {
+            Some(text) => Query::all([Query::Literal(text)]),
+            None => Query::All,
+        },
+    }
+}
+
+/// Text `hir` matches exactly once, lowercased
+fn exact_literal(hir: &Hir) -> Option<Vec<u8>> {
+    match hir.kind() {
+        HirKind::Literal(literal) => Some(literal.0.to_ascii_lowercase()),
+        HirKind::Class(class) => folded_byte(class).map(|b| vec![b]),
+        HirKind::Capture(capture) => exact_literal(&capture.sub),
+        _ => None,
+    }
+}
+
+/// The byte a class reduces to after ASCII case folding, like `[Kk]`
+fn folded_byte(class: &Class) -> Option<u8> {
+    let mut members = Vec::new();
+    match class {
+        Class::Unicode(class) => {
+            for range in class.iter() {
+                for c in range.start()..=range.end() {
+                    if !c.is_ascii() || members.len() > 2 {
+                        return None;
+                    }
+                    members.push(c as u8);
+                }
+            }
+        }
+        Class::Bytes(class) => {
+            for range in class.iter() {
+                for b in range.start()..=range.end() {
+                    if !b.is_ascii() || members.len() > 2 {
+                        return None;
+                    }
+                    members.push(b);
+                }
+            }
+        }
+    }
+    let first = members.first()?.to_ascii_lowercase();
+    members.iter().all(|b| b.to_ascii_lowercase() == first).then_some(first)
+}
+
+fn trigrams(text: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    text.windows(3)
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+}
+
+/// Sorted distinct trigrams of a text file, `None` for binary files
+fn file_trigrams(path: &Path) -> io::Result<Option<Vec<u32>>> {
+    let mut bytes = fs::read(path)?;
+    if memchr::memchr(0, &bytes).is_some() {
+        return Ok(None);
+    }
+    bytes.make_ascii_lowercase();
+    let mut trigrams: Vec<u32> = trigrams(&bytes).collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    Ok(Some(trigrams))
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+fn union(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out: Vec<u32> = a.iter().chain(b).copied().collect();
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads the saved format; `None` on any inconsistency
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let (head, rest) = self.bytes.split_at_checked(n)?;
+        self.bytes = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn varint(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = *self.take(1)?.first()?;
+            value |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+fn decode(root: &Path, bytes: &[u8]) -> Option<TrigramIndex> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return None;
+    }
+
+    let count = reader.u32()? as usize;
+    let mut files = Vec::with_capacity(count.min(1 << 20));
+    let mut ids = HashMap::with_capacity(count.min(1 << 20));
+    for id in 0..count {
+        let len = reader.u32()? as usize;
+        let path = PathBuf::from(std::str::from_utf8(reader.take(len)?).ok()?);
+        let stamp = Stamp {
+            size: reader.u64()?,
+            secs: reader.u64()?,
+            nanos: reader.u32()?,
+        };
+        ids.insert(path.clone(), id as u32);
+        files.push(IndexedFile { path, stamp });
+    }
+
+    let count = reader.u32()? as usize;
+    let mut postings = HashMap::with_capacity(count.min(1 << 24));
+    for _ in 0..count {
+        let trigram = reader.u32()?;
+        let len = reader.u32()? as usize;
+        let mut posting = Vec::with_capacity(len.min(files.len()));
+        let mut id = 0u32;
+        for _ in 0..len {
+            id = id.checked_add(reader.varint()?)?;
+            if id as usize >= files.len() {
+                return None;
+            }
+            posting.push(id);
+        }
+        postings.insert(trigram, posting);
+    }
+
+    Some(TrigramIndex {
+        root: root.to_path_buf(),
+        files,
+        ids,
+        postings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn literal(text: &str) -> Query {
+        Query::Literal(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_query_from_regex() {
+        assert_eq!(Query::from_regex("HashMap", false), literal("hashmap"));
+        assert_eq!(Query::from_regex(r"fn \w+\(", false), literal("fn "));
+        assert_eq!(Query::from_regex("(?i)needle", false), literal("needle"));
+        assert_eq!(Query::from_regex("needle", true), literal("needle"));
+        assert_eq!(
+            Query::from_regex("alpha|beta", false),
+            Query::Or(vec![literal("alpha"), literal("beta")])
+        );
+        assert_eq!(
+            Query::from_regex("foo.*bar", false),
+            Query::And(vec![literal("foo"), literal("bar")])
+        );
+        // `xa+b` must not require "xab"
+        assert_eq!(Query::from_regex("xyza+bcd", false), Query::And(vec![literal("xyz"), literal("bcd")]));
+        assert_eq!(Query::from_regex("a|bcd", false), Query::All);
+        assert_eq!(Query::from_regex("(", false), Query::All);
+    }
+
+    #[test]
+    fn test_index_narrows_and_detects_staleness() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        for i in 0..20 {
+            fs::write(root.join(format!("src/f{i:02}.rs")), format!("fn filler_{i}() {{}}\n")).unwrap();
+        }
+        fs::write(root.join("src/target.rs"), "struct NeedleType;\n").unwrap();
+
+        let (index, stats) = TrigramIndex::update(&root, &CancelToken::new()).unwrap();
+        assert_eq!(stats.files, 21);
+        assert_eq!(stats.reindexed, 21);
+        assert!(root.join(INDEX_FILE).is_file());
+
+        let loaded = TrigramIndex::load(index.root()).unwrap().unwrap();
+        let files = Walker::default().files_parallel(&root);
+        let query = Query::from_regex("Needle", true);
+        let (usage, kept) = loaded.narrow(&root, files, &query);
+        assert_eq!(usage, IndexUsage::Used);
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].path().ends_with("src/target.rs"));
+
+        // New files are kept even though the index has never seen them
+        fs::write(root.join("src/new.rs"), "// NeedleType\n").unwrap();
+        let files = Walker::default().files_parallel(&root);
+        let (_, kept) = loaded.narrow(&root.join("src"), files_under(&root, "src", files), &query);
+        assert_eq!(kept.len(), 2);
+
+        // Changing most files makes the index stale
+        for i in 0..10 {
+            fs::write(root.join(format!("src/f{i:02}.rs")), "changed\n").unwrap();
+        }
+        let files = Walker::default().files_parallel(&root);
+        let (usage, kept) = loaded.narrow(&root, files, &query);
+        assert_eq!(usage, IndexUsage::Stale);
+        assert_eq!(kept.len(), 22);
+
+        // Updating re-reads only what changed
+        let (_, stats) = TrigramIndex::update(&root, &CancelToken::new()).unwrap();
+        assert_eq!(stats.files, 22);
+        assert_eq!(stats.reindexed, 11);
+    }
+
+    fn files_under(root: &Path, sub: &str, files: Vec<DirEntry>) -> Vec<DirEntry> {
+        files.into_iter().filter(|e| e.path().starts_with(root.join(sub))).collect()
+    }
+}
@@ -1,10 +1,24 @@
 //! JSON Query tool (jq-like)
+//!
+//! Filters run in process on jaq, a jq implementation, with jq's standard
+//! library. The input is a stream of JSON values, so JSON Lines and
+//! concatenated documents are filtered one value at a time as jq does,
+//! and objects keep their key order unless `sort_keys` is set.
+//! [`JqTool::get_value`] and [`JqTool::set_value`] take a plain path such
+//! as `.a.b[0]` and never build a filter from their arguments.
 
-use crate::Result;
-use std::process::Command;
+use super::cancel::CancelToken;
+use crate::{Error, Result};
+use jaq_core::box_iter::box_once;
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, Exn, Filter, Native, RcIter};
+use jaq_json::Val;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::rc::Rc;
 
 /// Configuration for jq operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct JqConfig {
     /// Raw output (no JSON encoding for strings)
     pub raw_output: bool,
@@ -12,73 +26,49 @@ pub struct JqConfig {
     pub compact: bool,
     /// Sort keys
     pub sort_keys: bool,
-}
-
-impl Default for JqConfig {
-    fn default() -> Self {
-        Self {
-            raw_output: false,
-            compact: false,
-            sort_keys: false,
-        }
-    }
+    /// Filter one array of every input value instead of each value
+    pub slurp: bool,
 }
 
 /// JSON Query tool using jq
 pub struct JqTool {
     config: JqConfig,
+    cancel: CancelToken,
 }
 
 impl JqTool {
     pub fn new(config: JqConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Query JSON string with jq expression
     pub fn query(&self, json_input: &str, expression: &str) -> Result<String> {
-        let mut cmd = Command::new("jq");
-        
-        if self.config.raw_output {
-            cmd.arg("-r");
-        }
-        if self.config.compact {
-            cmd.arg("-c");
-        }
-        if self.config.sort_keys {
-            cmd.arg("-S");
-        }
-        
-        cmd.arg(expression);
-        
-        use std::io::Write;
-        use std::process::Stdio;
-        
-        let mut child = cmd
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin.write_all(json_input.as_bytes())?;
-        }
-        
-        let output = child.wait_with_output()?;
-        
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-        } else {
-            Err(crate::Error::Tool(format!(
-                "jq error: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )))
-        }
+        self.run(parse_values(json_input.as_bytes())?, expression)
     }
 
     /// Query JSON file with jq expression
-    pub fn query_file(&self, file_path: &std::path::Path, expression: &str) -> Result<String> {
-        let content = std::fs::read_to_string(file_path)?;
-        self.query(&content, expression)
+    pub fn query_file(&self, file_path: &Path, expression: &str) -> Result<String> {
+        self.query_files(&[file_path], expression)
+    }
+
+    /// Query the values of several files, in order, as one input
+    pub fn query_files(&self, file_paths: &[&Path], expression: &str) -> Result<String> {
+        let mut values = Vec::new();
+        for path in file_paths {
+            self.cancel.check()?;
+            let content = std::fs::read(path)?;
+            values.extend(parse_values(&content).map_err(|e| Error::invalid_arguments(format!("{}: {}", path.display(), e)))?);
+        }
+        self.run(values, expression)
     }
 
     /// Pretty print JSON
@@ -86,15 +76,109 @@ impl JqTool {
         self.query(json_input, ".")
     }
 
-    /// Get value at path (e.g., ".foo.bar[0]")
+    /// Get value at path (e.g., ".foo.bar[0]"), `null` where it is missing
     pub fn get_value(&self, json_input: &str, path: &str) -> Result<String> {
-        self.query(json_input, path)
+        let steps = parse_path(path)?;
+        let outputs = parse_values(json_input.as_bytes())?
+            .iter()
+            .map(|value| get(value, &steps).map(|value| self.format(&value)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(outputs.join("\n"))
     }
 
-    /// Set value at path
+    /// Set value at path, creating missing objects and arrays on the
+    /// way; `value` is JSON text. An index may append to an array but not
+    /// skip past its end.
     pub fn set_value(&self, json_input: &str, path: &str, value: &str) -> Result<String> {
-        let expression = format!("{} = {}", path, value);
-        self.query(json_input, &expression)
+        let steps = parse_path(path)?;
+        let [value] = <[Val; 1]>::try_from(parse_values(value.as_bytes())?)
+            .map_err(|_| Error::invalid_arguments("The value must be exactly one JSON value"))?;
+        let outputs = parse_values(json_input.as_bytes())?
+            .into_iter()
+            .map(|input| set(input, &steps, value.clone()).map(|output| self.format(&output)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(outputs.join("\n"))
+    }
+
+    /// Run `expression` on each of `values`, or on all of them when
+    /// slurping, one output per line
+    fn run(&self, values: Vec<Val>, expression: &str) -> Result<String> {
+        self.cancel.check()?;
+        let filter = compile(expression)?;
+        let values = if self.config.slurp {
+            vec![Val::Arr(Rc::new(values))]
+        } else {
+            values
+        };
+
+        // `input` and `inputs` read the values not yet filtered
+        let inputs = RcIter::new(values.into_iter().map(Ok));
+        let mut outputs = Vec::new();
+        for value in &inputs {
+            let value: Val = value.map_err(Error::tool)?;
+            for output in filter.run((Ctx::new([], &inputs), value)) {
+                self.cancel.check()?;
+                let output = output.map_err(|e| Error::tool(format!("jq error: {}", e)))?;
+                outputs.push(self.format(&output));
+            }
+        }
+        Ok(outputs.join("\n"))
+    }
+
+    /// One output value as jq prints it
+    fn format(&self, value: &Val) -> String {
+        match value {
+            Val::Str(s) if self.config.raw_output => s.to_string(),
+            _ => {
+                let mut out = String::new();
+                self.write(&mut out, value, 0);
+                out
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String, value: &Val, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            if !self.config.compact {
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+            }
+        };
+        match value {
+            Val::Arr(items) if !items.is_empty() => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    self.write(out, item, depth + 1);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Val::Obj(map) if !map.is_empty() => {
+                let mut entries: Vec<_> = map.iter().collect();
+                if self.config.sort_keys {
+                    entries.sort_by(|a, b| a.0.cmp(b.0));
+                }
+                out.push('{');
+                for (i, (key, item)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    let _ = write!(out, "{}", Val::Str(key.clone()));
+                    out.push_str(if self.config.compact { ":" } else { ": " });
+                    self.write(out, item, depth + 1);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+            _ => {
+                let _ = write!(out, "{}", value);
+            }
+        }
     }
 }
 
@@ -103,3 +187,268 @@ impl Default for JqTool {
         Self::new(JqConfig::default())
     }
 }
+
+/// Compile a jq program with the standard library. `halt` and
+/// `halt_error` fail instead of exiting the process.
+fn compile(expression: &str) -> Result<Filter<Native<Val>>> {
+    let program = File { code: expression, path: () };
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = Arena::default();
+    let modules = loader.load(&arena, program).map_err(|errors| {
+        let messages = errors.into_iter().flat_map(|(_, error)| match error {
+            jaq_core::load::Error::Io(errors) => errors.into_iter().map(|(_, e)| e).collect(),
+            jaq_core::load::Error::Lex(errors) => errors
+                .into_iter()
+                .map(|(expected, found)| syntax_error(expression, expected.as_str(), found))
+                .collect(),
+            jaq_core::load::Error::Parse(errors) => errors
+                .into_iter()
+                .map(|(expected, found)| syntax_error(expression, expected.as_str(), found))
+                .collect::<Vec<_>>(),
+        });
+        Error::invalid_arguments(format!("Invalid jq expression: {}", messages.collect::<Vec<_>>().join("; ")))
+    })?;
+
+    let funs = jaq_std::funs().chain(jaq_json::funs()).map(|(name, args, native)| match name {
+        "halt" | "halt_error" => (name, args, Native::new(|_, _| box_once(Err(Exn::from(jaq_core::Error::str("halt is not supported")))))),
+        _ => (name, args, native),
+    });
+    Compiler::default().with_funs(funs).compile(modules).map_err(|errors| {
+        let undefined: Vec<String> = errors
+            .into_iter()
+            .flat_map(|(_, errors)| errors)
+            .map(|(name, kind)| format!("undefined {} {}", kind.as_str(), name))
+            .collect();
+        Error::invalid_arguments(format!("Invalid jq expression: {}", undefined.join("; ")))
+    })
+}
+
+/// "expected X at column N", `found` being the rest of `expression`
+/// where parsing stopped
+fn syntax_error(expression: &str, expected: &str, found: &str) -> String {
+    let column = expression[..expression.len() - found.len()].chars().count() + 1;
+    format!("expected {} at column {}", expected, column)
+}
+
+/// The JSON values in `input`, separated by whitespace as in JSON Lines
+fn parse_values(input: &[u8]) -> Result<Vec<Val>> {
+    use hifijson::token::Lex;
+    let mut lexer = hifijson::SliceLexer::new(input);
+    let mut values = Vec::new();
+    while let Some(token) = lexer.ws_token() {
+        let value = Val::parse(token, &mut lexer).map_err(|e| {
+            let offset = input.len() - lexer.as_slice().len();
+            let line = input[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+            Error::invalid_arguments(format!("Invalid JSON at line {}: {}", line, e))
+        })?;
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// One step of a path
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(isize),
+}
+
+/// Parse a path of `.key`, `."key"`, `["key"]` and `[index]` steps, such
+/// as `.a."b c"[0]`; `.` alone is the whole value
+fn parse_path(path: &str) -> Result<Vec<Step>> {
+    let invalid = |why: &str| Error::invalid_arguments(format!("Invalid path {:?}: {}", path, why));
+    let path = path.trim();
+    let mut rest = path.strip_prefix('.').ok_or_else(|| invalid("it must start with '.'"))?;
+    let mut steps = Vec::new();
+    let mut dotted = true;
+    while !rest.is_empty() {
+        if let Some(bracket) = rest.strip_prefix('[') {
+            let end = bracket.find(']').ok_or_else(|| invalid("missing ']'"))?;
+            let inner = bracket[..end].trim();
+            let step = if inner.starts_with('"') {
+                // The key may itself contain ']'
+                let (key, after) = string_prefix(bracket.trim_start()).ok_or_else(|| invalid("bad string key"))?;
+                let after = after.trim_start().strip_prefix(']').ok_or_else(|| invalid("missing ']'"))?;
+                rest = after;
+                Step::Key(key)
+            } else {
+                rest = &bracket[end + 1..];
+                Step::Index(inner.parse().map_err(|_| invalid("indexes must be integers"))?)
+            };
+            steps.push(step);
+            dotted = false;
+        } else if let Some(after) = rest.strip_prefix('.').filter(|_| !dotted) {
+            rest = after;
+            dotted = true;
+        } else if dotted && rest.starts_with('"') {
+            let (key, after) = string_prefix(rest).ok_or_else(|| invalid("bad string key"))?;
+            steps.push(Step::Key(key));
+            rest = after;
+            dotted = false;
+        } else if dotted {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            if end == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(invalid("expected a key"));
+            }
+            steps.push(Step::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+            dotted = false;
+        } else {
+            return Err(invalid("expected '.' or '['"));
+        }
+    }
+    if dotted && !steps.is_empty() {
+        return Err(invalid("it ends with '.'"));
+    }
+    Ok(steps)
+}
+
+/// A JSON string at the start of `s` and the text after it
+fn string_prefix(s: &str) -> Option<(String, &str)> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some((serde_json::from_str(&s[..=i]).ok()?, &s[i + 1..])),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Name of a value's type in error messages
+fn type_name(value: &Val) -> &'static str {
+    match value {
+        Val::Null => "null",
+        Val::Bool(_) => "boolean",
+        Val::Int(_) | Val::Float(_) | Val::Num(_) => "number",
+        Val::Str(_) => "string",
+        Val::Arr(_) => "array",
+        Val::Obj(_) => "object",
+    }
+}
+
+fn cannot_index(value: &Val, step: &Step) -> Error {
+    match step {
+        Step::Key(key) => Error::tool(format!("Cannot index {} with {:?}", type_name(value), key)),
+        Step::Index(i) => Error::tool(format!("Cannot index {} with number {}", type_name(value), i)),
+    }
+}
+
+/// Value at `steps` under `value`, `null` where it is missing
+fn get(value: &Val, steps: &[Step]) -> Result<Val> {
+    let mut current = value.clone();
+    for step in steps {
+        current = match (step, &current) {
+            (_, Val::Null) => Val::Null,
+            (Step::Key(key), Val::Obj(map)) => map.get(key).cloned().unwrap_or_default(),
+            (Step::Index(i), Val::Arr(items)) => {
+                let index = if *i < 0 { items.len().checked_sub(i.unsigned_abs()) } else { Some(*i as usize) };
+                index.and_then(|index| items.get(index)).cloned().unwrap_or_default()
+            }
+            _ => return Err(cannot_index(&current, step)),
+        };
+    }
+    Ok(current)
+}
+
+/// `value` with `new` at `steps`, as jq's `setpath` does
+fn set(value: Val, steps: &[Step], new: Val) -> Result<Val> {
+    let Some((step, rest)) = steps.split_first() else {
+        return Ok(new);
+    };
+    match (step, value) {
+        (Step::Key(_), Val::Null) => set(Val::obj(Default::default()), steps, new),
+        (Step::Index(_), Val::Null) => set(Val::Arr(Rc::default()), steps, new),
+        (Step::Key(key), Val::Obj(mut map)) => {
+            let child = map.get(key).cloned().unwrap_or_default();
+            let child = set(child, rest, new)?;
+            Rc::make_mut(&mut map).insert(Rc::new(key.clone()), child);
+            Ok(Val::Obj(map))
+        }
+        (Step::Index(i), Val::Arr(mut items)) => {
+            let len = items.len();
+            let index = if *i < 0 {
+                len.checked_sub(i.unsigned_abs())
+                    .ok_or_else(|| Error::tool(format!("Out of bounds negative array index {}", i)))?
+            } else if *i as usize <= len {
+                *i as usize
+            } else {
+                return Err(Error::tool(format!("Out of bounds array index {} for length {}", i, len)));
+            };
+            let items_mut = Rc::make_mut(&mut items);
+            if index == len {
+                items_mut.push(Val::Null);
+            }
+            let child = std::mem::take(&mut items_mut[index]);
+            items_mut[index] = set(child, rest, new)?;
+            Ok(Val::Arr(items))
+        }
+        (step, value) => Err(cannot_index(&value, step)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(config: JqConfig) -> JqTool {
+        JqTool::new(config)
+    }
+
+    #[test]
+    fn test_query() {
+        let compact = tool(JqConfig { compact: true, ..Default::default() });
+        let input = r#"{"b": 1, "a": [1, 2, {"c": "x"}]}"#;
+        assert_eq!(compact.query(input, ".").unwrap(), r#"{"b":1,"a":[1,2,{"c":"x"}]}"#);
+        assert_eq!(compact.query(input, "[.a[] | numbers] | add").unwrap(), "3");
+        assert_eq!(compact.query(input, ".a[2].c, (keys | length)").unwrap(), "\"x\"\n2");
+        assert_eq!(compact.query(input, "map_values(type)").unwrap(), r#"{"b":"number","a":"array"}"#);
+
+        let pretty = tool(JqConfig { sort_keys: true, ..Default::default() });
+        assert_eq!(pretty.query(r#"{"b": [], "a": {"d": [1]}}"#, ".").unwrap(), "{\n  \"a\": {\n    \"d\": [\n      1\n    ]\n  },\n  \"b\": []\n}");
+
+        let raw = tool(JqConfig { raw_output: true, ..Default::default() });
+        assert_eq!(raw.query(r#"{"name": "a\"b"}"#, ".name").unwrap(), "a\"b");
+
+        assert!(matches!(compact.query(input, ".a |"), Err(Error::InvalidArguments(_))));
+        assert!(matches!(compact.query(input, "nope(1)"), Err(Error::InvalidArguments(_))));
+        assert!(matches!(compact.query(input, ".b | halt"), Err(Error::Tool(_))));
+        assert!(matches!(compact.query("{", "."), Err(Error::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_json_lines_and_files() {
+        let lines = "{\"id\": 1, \"ok\": true}\n{\"id\": 2, \"ok\": false}\n{\"id\": 3, \"ok\": true}\n";
+        let compact = tool(JqConfig { compact: true, ..Default::default() });
+        assert_eq!(compact.query(lines, "select(.ok) | .id").unwrap(), "1\n3");
+        assert_eq!(compact.query(lines, "[.id, inputs.id]").unwrap(), "[1,2,3]");
+
+        let slurp = tool(JqConfig { compact: true, slurp: true, ..Default::default() });
+        assert_eq!(slurp.query(lines, "map(.id) | add").unwrap(), "6");
+
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.jsonl"), dir.path().join("b.json"));
+        std::fs::write(&a, lines).unwrap();
+        std::fs::write(&b, "{\"id\": 4}").unwrap();
+        assert_eq!(slurp.query_files(&[&a, &b], "length").unwrap(), "4");
+        assert_eq!(compact.query_file(&b, ".id").unwrap(), "4");
+    }
+
+    #[test]
+    fn test_get_and_set_paths() {
+        let compact = tool(JqConfig { compact: true, ..Default::default() });
+        let input = r#"{"a": {"b c": [1, 2]}}"#;
+        assert_eq!(compact.get_value(input, r#".a."b c"[-1]"#).unwrap(), "2");
+        assert_eq!(compact.get_value(input, ".missing.x").unwrap(), "null");
+        assert_eq!(compact.set_value(input, r#".a["b c"][2]"#, "true").unwrap(), r#"{"a":{"b c":[1,2,true]}}"#);
+        assert!(matches!(compact.set_value("[]", ".[100000000000]", "1"), Err(Error::Tool(_))));
+        assert_eq!(compact.set_value(input, ".new[0].k", r#""v""#).unwrap(), r#"{"a":{"b c":[1,2]},"new":[{"k":"v"}]}"#);
+
+        // Paths and values are data, never jq code
+        assert!(compact.set_value(input, ".a | halt", "1").is_err());
+        assert!(compact.set_value(input, ".x", "1 | error").is_err());
+        assert_eq!(compact.set_value(input, ".x", r#""$__loc__ | env""#).unwrap(), r#"{"a":{"b c":[1,2]},"x":"$__loc__ | env"}"#);
+        assert!(compact.set_value(input, ".a.\"b c\".k", "1").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 //! LSP Diagnostics tool - runs tsc and eslint to get errors/warnings
 
-use crate::Result;
+use super::cancel::CancelToken;
+use super::process::run_command;
+use crate::{Error, Result};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -50,11 +52,21 @@ impl Default for DiagnosticsConfig {
 /// Diagnostics tool that runs TypeScript and ESLint checks
 pub struct DiagnosticsTool {
     config: DiagnosticsConfig,
+    cancel: CancelToken,
 }
 
 impl DiagnosticsTool {
     pub fn new(config: DiagnosticsConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Get diagnostics for a directory
@@ -62,20 +74,24 @@ impl DiagnosticsTool {
         let mut all_diagnostics = Vec::new();
 
         // Run TypeScript type checking
-        if let Ok(tsc_diags) = self.run_tsc(directory) {
-            all_diagnostics.extend(tsc_diags);
+        match self.run_tsc(directory) {
+            Ok(tsc_diags) => all_diagnostics.extend(tsc_diags),
+            Err(Error::Cancelled) => return Err(Error::Cancelled),
+            Err(_) => {}
         }
 
         // Run ESLint
-        if let Ok(eslint_diags) = self.run_eslint(directory, file_filter) {
-            all_diagnostics.extend(eslint_diags);
+        match self.run_eslint(directory, file_filter) {
+            Ok(eslint_diags) => all_diagnostics.extend(eslint_diags),
+            Err(Error::Cancelled) => return Err(Error::Cancelled),
+            Err(_) => {}
         }
 
         // Filter by file if specified
-        if let Some(filter) = file_filter {
-            if filter != "*" {
-                all_diagnostics.retain(|d| d.file.contains(filter) || d.file.ends_with(filter));
-            }
+        if let Some(filter) = file_filter
+            && filter != "*"
+        {
+            all_diagnostics.retain(|d| d.file.contains(filter) || d.file.ends_with(filter));
         }
 
         // Filter warnings if disabled
@@ -91,10 +107,14 @@ impl DiagnosticsTool {
 
     /// Run TypeScript compiler in noEmit mode
     fn run_tsc(&self, directory: &Path) -> Result<Vec<Diagnostic>> {
-        let output = Command::new("npx")
-            .args(["-y", "tsc", "--noEmit", "--pretty", "false"])
-            .current_dir(directory)
-            .output()?;
+        let output = run_command(
+            Command::new("npx")
+                .args(["-y", "tsc", "--noEmit", "--pretty", "false"])
+                .current_dir(directory),
+            None,
+            &self.cancel,
+            Some(self.config.timeout),
+        )?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -137,10 +157,14 @@ impl DiagnosticsTool {
     fn run_eslint(&self, directory: &Path, file_filter: Option<&str>) -> Result<Vec<Diagnostic>> {
         let target = file_filter.unwrap_or(".");
         
-        let output = Command::new("npx")
-            .args(["-y", "eslint", target, "--format", "json", "--no-error-on-unmatched-pattern"])
-            .current_dir(directory)
-            .output()?;
+        let output = run_command(
+            Command::new("npx")
+                .args(["-y", "eslint", target, "--format", "json", "--no-error-on-unmatched-pattern"])
+                .current_dir(directory),
+            None,
+            &self.cancel,
+            Some(self.config.timeout),
+        )?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         
@@ -0,0 +1,302 @@
+//! Merge tool - three-way merge of two edits of a common base
+//!
+//! Both versions are diffed against the base and split into stable runs,
+//! which neither side changed, and the changes between them. A change
+//! made by one side, or identically by both, is taken as is; different
+//! changes to the same lines are conflicts, returned as hunks and marked
+//! in the merged text the way `diff3 -m` does.
+
+use super::cancel::CancelToken;
+use super::diff::{Algorithm, DiffOp, OpKind, diff_with};
+use crate::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::ops::Range;
+use std::path::Path;
+
+/// Configuration for three-way merges
+#[derive(Debug, Clone, Default)]
+pub struct MergeConfig {
+    /// Line matching algorithm for both diffs
+    pub algorithm: Algorithm,
+    /// Names written after the conflict markers; the file paths or
+    /// `ours`, `base` and `theirs` by default
+    pub ours_label: Option<String>,
+    pub base_label: Option<String>,
+    pub theirs_label: Option<String>,
+}
+
+/// Lines both sides changed differently. Starts are 1-based; an empty
+/// side starts at the line before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct MergeConflict {
+    /// Line of the `<<<<<<<` marker in the merged text
+    pub merged_line: usize,
+    pub base_start: usize,
+    pub base: String,
+    pub ours_start: usize,
+    pub ours: String,
+    pub theirs_start: usize,
+    pub theirs: String,
+}
+
+/// Result of a three-way merge
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    /// The merged text, with markers around each conflict
+    pub merged: String,
+    pub conflicts: Vec<MergeConflict>,
+    /// Changes merged without conflict
+    pub resolved: usize,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Three-way merge tool
+pub struct MergeTool {
+    config: MergeConfig,
+    cancel: CancelToken,
+}
+
+impl MergeTool {
+    pub fn new(config: MergeConfig) -> Self {
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Merge the changes `ours` and `theirs` made to `base`
+    pub fn merge_files(&self, base: &Path, ours: &Path, theirs: &Path) -> Result<MergeResult> {
+        self.cancel.check()?;
+        let read = |path: &Path| -> Result<String> { Ok(String::from_utf8_lossy(&std::fs::read(path)?).into_owned()) };
+        let labels = [ours, base, theirs].map(|path| path.display().to_string());
+        self.merge(&read(base)?, &read(ours)?, &read(theirs)?, labels)
+    }
+
+    /// Merge the changes `ours` and `theirs` made to `base`
+    pub fn merge_strings(&self, base: &str, ours: &str, theirs: &str) -> Result<MergeResult> {
+        self.merge(base, ours, theirs, ["ours", "base", "theirs"].map(String::from))
+    }
+
+    fn merge(&self, base: &str, ours: &str, theirs: &str, [ours_label, base_label, theirs_label]: [String; 3]) -> Result<MergeResult> {
+        self.cancel.check()?;
+        let base: Vec<&str> = base.split_inclusive('\n').collect();
+        let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+        let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+        let ours_match = matches(&diff_with(self.config.algorithm, &base, &ours), base.len());
+        self.cancel.check()?;
+        let theirs_match = matches(&diff_with(self.config.algorithm, &base, &theirs), base.len());
+        self.cancel.check()?;
+
+        let label = |label: &Option<String>, default: String| label.clone().unwrap_or(default);
+        let ours_label = label(&self.config.ours_label, ours_label);
+        let base_label = label(&self.config.base_label, base_label);
+        let theirs_label = label(&self.config.theirs_label, theirs_label);
+
+        let mut merged = Merged::default();
+        let mut conflicts = Vec::new();
+        let mut resolved = 0;
+        for chunk in chunks(&ours_match, &theirs_match, base.len(), ours.len(), theirs.len()) {
+            let (b, o, t) = (&base[chunk.base.clone()], &ours[chunk.ours.clone()], &theirs[chunk.theirs.clone()]);
+            if !chunk.changed {
+                merged.push(b);
+                continue;
+            }
+            if o == t || t == b {
+                resolved += 1;
+                merged.push(o);
+                continue;
+            }
+            if o == b {
+                resolved += 1;
+                merged.push(t);
+                continue;
+            }
+
+            conflicts.push(MergeConflict {
+                merged_line: merged.lines + 1,
+                base_start: start(&chunk.base),
+                base: b.concat(),
+                ours_start: start(&chunk.ours),
+                ours: o.concat(),
+                theirs_start: start(&chunk.theirs),
+                theirs: t.concat(),
+            });
+            merged.marker("<<<<<<<", &ours_label);
+            merged.section(o);
+            merged.marker("|||||||", &base_label);
+            merged.section(b);
+            merged.marker("=======", "");
+            merged.section(t);
+            merged.marker(">>>>>>>", &theirs_label);
+        }
+        Ok(MergeResult {
+            merged: merged.text,
+            conflicts,
+            resolved,
+        })
+    }
+}
+
+impl Default for MergeTool {
+    fn default() -> Self {
+        Self::new(MergeConfig::default())
+    }
+}
+
+/// A run of lines: unchanged on both sides, or changed by either
+struct Chunk {
+    changed: bool,
+    base: Range<usize>,
+    ours: Range<usize>,
+    theirs: Range<usize>,
+}
+
+/// For each base line, the line it matches in the other version
+fn matches(ops: &[DiffOp], base_len: usize) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base_len];
+    for op in ops.iter().filter(|op| op.kind == OpKind::Equal) {
+        for (i, j) in op.old.clone().zip(op.new.clone()) {
+            matches[i] = Some(j);
+        }
+    }
+    matches
+}
+
+/// Split the three texts into stable runs, base lines both versions keep
+/// in place, and the changed runs between them
+fn chunks(ours: &[Option<usize>], theirs: &[Option<usize>], base_len: usize, ours_len: usize, theirs_len: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        let mut n = 0;
+        while i + n < base_len && ours[i + n] == Some(j + n) && theirs[i + n] == Some(k + n) {
+            n += 1;
+        }
+        if n > 0 {
+            chunks.push(Chunk {
+                changed: false,
+                base: i..i + n,
+                ours: j..j + n,
+                theirs: k..k + n,
+            });
+            (i, j, k) = (i + n, j + n, k + n);
+            continue;
+        }
+        if i == base_len && j == ours_len && k == theirs_len {
+            return chunks;
+        }
+
+        // The change runs to the next base line both versions kept
+        let (o, x, y) = (i..base_len)
+            .find_map(|o| Some((o, ours[o]?, theirs[o]?)))
+            .unwrap_or((base_len, ours_len, theirs_len));
+        chunks.push(Chunk {
+            changed: true,
+            base: i..o,
+            ours: j..x,
+            theirs: k..y,
+        });
+        (i, j, k) = (o, x, y);
+    }
+}
+
+/// 1-based start of a range, or the line before if it is empty
+fn start(range: &Range<usize>) -> usize {
+    range.start + usize::from(!range.is_empty())
+}
+
+/// Merged text and the number of lines in it
+#[derive(Default)]
+struct Merged {
+    text: String,
+    lines: usize,
+}
+
+impl Merged {
+    fn push(&mut self, lines: &[&str]) {
+        lines.iter().for_each(|line| self.text.push_str(line));
+        self.lines += lines.len();
+    }
+
+    /// One side of a conflict, ending in a line feed so the next marker
+    /// starts its own line
+    fn section(&mut self, lines: &[&str]) {
+        self.push(lines);
+        if lines.last().is_some_and(|line| !line.ends_with('\n')) {
+            self.text.push('\n');
+        }
+    }
+
+    fn marker(&mut self, marker: &str, label: &str) {
+        let line = if label.is_empty() { format!("{}\n", marker) } else { format!("{} {}\n", marker, label) };
+        self.push(&[&line]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_merge() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\nf\n";
+        let result = MergeTool::default().merge_strings(base, ours, theirs).unwrap();
+        assert!(result.is_clean());
+        assert_eq!(result.merged, "A\nb\nc\nd\nE\nf\n");
+        assert_eq!(result.resolved, 2);
+
+        // The same change on both sides is not a conflict
+        let result = MergeTool::default().merge_strings(base, ours, ours).unwrap();
+        assert_eq!((result.merged.as_str(), result.conflicts.len()), (ours, 0));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let base = "fn main() {\n    run(1);\n}\n";
+        let ours = "fn main() {\n    run(2);\n}\n";
+        let theirs = "fn main() {\n    run(3);\n}\n// end";
+        let result = MergeTool::default().merge_strings(base, ours, theirs).unwrap();
+        assert_eq!(
+            result.merged,
+            "fn main() {\n<<<<<<< ours\n    run(2);\n||||||| base\n    run(1);\n=======\n    run(3);\n>>>>>>> theirs\n}\n// end"
+        );
+        assert_eq!(
+            result.conflicts,
+            [MergeConflict {
+                merged_line: 2,
+                base_start: 2,
+                base: "    run(1);\n".to_string(),
+                ours_start: 2,
+                ours: "    run(2);\n".to_string(),
+                theirs_start: 2,
+                theirs: "    run(3);\n".to_string(),
+            }]
+        );
+        assert_eq!(result.resolved, 1);
+
+        // Insertions at the same place conflict too; labels are configurable
+        let config = MergeConfig {
+            ours_label: Some("worker-1".to_string()),
+            theirs_label: Some("worker-2".to_string()),
+            ..Default::default()
+        };
+        let result = MergeTool::new(config).merge_strings("a\n", "a\nx", "a\ny\n").unwrap();
+        assert_eq!(result.merged, "a\n<<<<<<< worker-1\nx\n||||||| base\n=======\ny\n>>>>>>> worker-2\n");
+        assert_eq!(result.conflicts[0].base_start, 1);
+        assert!(result.conflicts[0].base.is_empty());
+    }
+}
@@ -1,23 +1,52 @@
 //! Multi-pattern grep tool (mgrep)
-//! 
-//! Searches for multiple patterns in parallel using rayon.
+//!
+//! Every file is read once and checked against all patterns with a
+//! `RegexSet`; the per-pattern regexes only run on lines the set matched,
+//! to find spans. Files are scanned in parallel in path order, so results
+//! are deterministic. Patterns that fail to compile are reported instead of
+//! aborting the whole search.
+//!
+//! [`MgrepTool::query`] combines patterns instead: it returns the files
+//! that match all of one list, any of another and none of a third, either
+//! anywhere in the file or within a window of lines.
 
-use crate::Result;
+use super::cancel::CancelToken;
+use super::grep::{decode, trim_cr};
+use super::index::{self, IndexUsage, Query};
+use super::progress::ProgressReporter;
+use super::walk::{WalkConfig, Walker};
+use crate::{Error, Result};
+use ignore::DirEntry;
 use rayon::prelude::*;
-use regex::Regex;
-use std::collections::HashMap;
+use regex::bytes::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
 use std::path::Path;
 use std::time::{Duration, Instant};
-use walkdir::WalkDir;
+
+/// Files scanned in parallel between limit and timeout checks
+const BATCH_SIZE: usize = 128;
 
 /// Configuration for mgrep operations
 #[derive(Debug, Clone)]
 pub struct MgrepConfig {
     pub timeout: Duration,
     pub max_results_per_pattern: usize,
+    /// Maximum files returned by a query
+    pub max_files: usize,
     pub max_file_size: u64,
     pub include_hidden: bool,
+    /// Honor .gitignore, .ignore and .orchestratorignore
+    pub respect_ignore: bool,
+    /// File patterns to include; empty means every file
+    pub include_patterns: Vec<String>,
     pub exclude_patterns: Vec<String>,
+    /// Named file types to include, as in ripgrep's `--type` (`rust`, `ts`, `py`, ...)
+    pub file_types: Vec<String>,
+    /// Named file types to skip
+    pub exclude_file_types: Vec<String>,
+    /// Skip files the project's trigram index rules out
+    pub use_index: bool,
 }
 
 impl Default for MgrepConfig {
@@ -25,14 +54,15 @@ impl Default for MgrepConfig {
         Self {
             timeout: Duration::from_secs(60),
             max_results_per_pattern: 50,
+            max_files: 200,
             max_file_size: 10 * 1024 * 1024,
             include_hidden: false,
-            exclude_patterns: vec![
-                "**/node_modules/**".to_string(),
-                "**/.git/**".to_string(),
-                "**/target/**".to_string(),
-                "**/dist/**".to_string(),
-            ],
+            respect_ignore: true,
+            include_patterns: vec![],
+            exclude_patterns: vec!["**/.git/**".to_string()],
+            file_types: vec![],
+            exclude_file_types: vec![],
+            use_index: true,
         }
     }
 }
@@ -45,113 +75,289 @@ pub struct MgrepMatch {
     pub file: String,
     pub line: usize,
     pub content: String,
+    /// Byte ranges of every match of `pattern` in `content`
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// A pattern that failed to compile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPattern {
+    pub pattern: String,
+    pub error: String,
 }
 
 /// Multi-grep results grouped by pattern
 #[derive(Debug, Clone, Default)]
 pub struct MgrepResult {
     pub results: HashMap<String, Vec<MgrepMatch>>,
+    /// Patterns left out of the search
+    pub invalid_patterns: Vec<InvalidPattern>,
+    /// Patterns given more than once, searched and reported once
+    pub duplicate_patterns: Vec<String>,
+    /// Files opened and scanned
+    pub files_searched: usize,
+    /// The timeout hit before every file was searched
+    pub timed_out: bool,
+    /// Whether the trigram index narrowed the files
+    pub index: IndexUsage,
+}
+
+/// Patterns combined with AND / OR / NOT
+#[derive(Debug, Clone, Default)]
+pub struct MgrepQuery {
+    /// Every one of these must match
+    pub all: Vec<String>,
+    /// At least one of these must match, unless empty
+    pub any: Vec<String>,
+    /// None of these may match
+    pub none: Vec<String>,
+    /// Evaluate the query within this many consecutive lines instead of the
+    /// whole file
+    pub window: Option<usize>,
+}
+
+/// A file satisfying a query
+#[derive(Debug, Clone)]
+pub struct MgrepFileMatch {
+    pub file: String,
+    /// Lines matching the `all` and `any` patterns, in line order
+    pub evidence: Vec<MgrepMatch>,
+}
+
+/// Files satisfying a query, in path order
+#[derive(Debug, Clone, Default)]
+pub struct MgrepQueryResult {
+    pub files: Vec<MgrepFileMatch>,
+    /// Files opened and scanned
+    pub files_searched: usize,
+    /// The timeout hit before every file was searched
+    pub timed_out: bool,
+    /// More files matched than `max_files`
+    pub truncated: bool,
+    /// Whether the trigram index narrowed the files
+    pub index: IndexUsage,
 }
 
 /// Multi-pattern grep tool
 pub struct MgrepTool {
     config: MgrepConfig,
+    cancel: CancelToken,
+    progress: ProgressReporter,
 }
 
 impl MgrepTool {
     pub fn new(config: MgrepConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+            progress: ProgressReporter::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
-    /// Search for multiple patterns in parallel
+    /// Report files scanned and matches found to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Search for multiple patterns, reading each file once
     pub fn search(&self, patterns: &[String], directory: &Path) -> Result<MgrepResult> {
-        let start = Instant::now();
-        
-        // Compile all patterns
-        let regexes: Vec<(String, Regex)> = patterns
-            .iter()
-            .filter_map(|p| Regex::new(p).ok().map(|r| (p.clone(), r)))
-            .collect();
-
-        // Collect all files first
-        let files: Vec<_> = WalkDir::new(directory)
-            .follow_links(false)
-            .into_iter()
-            .filter_entry(|e| self.should_include(e.path()))
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .filter(|e| {
-                e.metadata()
-                    .map(|m| m.len() <= self.config.max_file_size)
-                    .unwrap_or(false)
-            })
-            .map(|e| e.path().to_path_buf())
-            .collect();
-
-        // Search in parallel
-        let results: HashMap<String, Vec<MgrepMatch>> = regexes
-            .par_iter()
-            .map(|(pattern, regex)| {
-                let mut matches = Vec::new();
-                
-                for file_path in &files {
-                    if start.elapsed() > self.config.timeout {
-                        break;
-                    }
-                    if matches.len() >= self.config.max_results_per_pattern {
-                        break;
-                    }
+        let deadline = Instant::now() + self.config.timeout;
+        let mut result = MgrepResult::default();
 
-                    if let Ok(content) = std::fs::read_to_string(file_path) {
-                        for (line_num, line) in content.lines().enumerate() {
-                            if regex.is_match(line) {
-                                matches.push(MgrepMatch {
-                                    pattern: pattern.clone(),
-                                    file: file_path.display().to_string(),
-                                    line: line_num + 1,
-                                    content: line.to_string(),
-                                });
-
-                                if matches.len() >= self.config.max_results_per_pattern {
-                                    break;
-                                }
-                            }
-                        }
-                    }
+        let mut valid = Vec::new();
+        let mut seen = HashSet::new();
+        for pattern in patterns {
+            if !seen.insert(pattern) {
+                if !result.duplicate_patterns.contains(pattern) {
+                    result.duplicate_patterns.push(pattern.clone());
                 }
+                continue;
+            }
+            match compile(pattern) {
+                Ok(regex) => valid.push((pattern.clone(), regex)),
+                Err(e) => result.invalid_patterns.push(InvalidPattern {
+                    pattern: pattern.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        if valid.is_empty() {
+            return Ok(result);
+        }
+        let set = build_set(&valid)?;
+
+        let query = Query::any(valid.iter().map(|(p, _)| Query::from_regex(p, false)));
+        let (index, files) = self.files(directory, deadline, &query)?;
+        result.index = index;
 
-                (pattern.clone(), matches)
-            })
-            .collect();
+        let limit = self.config.max_results_per_pattern;
+        let mut counts = vec![0; valid.len()];
+        let scan = |path: &Path| {
+            read_text(path)
+                .filter(|bytes| set.is_match(bytes))
+                .map(|bytes| scan_lines(&set, &valid, path, &bytes, limit))
+                .unwrap_or_default()
+        };
+        (result.files_searched, result.timed_out) = self.scan_files(&files, deadline, scan, |matches| {
+            for (i, m) in matches {
+                if counts[i] < limit {
+                    counts[i] += 1;
+                    self.progress.matches_found(1);
+                    result.results.entry(m.pattern.clone()).or_default().push(m);
+                }
+            }
+            counts.iter().any(|&count| count < limit)
+        })?;
 
-        Ok(MgrepResult { results })
+        for (pattern, _) in &valid {
+            result.results.entry(pattern.clone()).or_default();
+        }
+        Ok(result)
     }
 
-    fn should_include(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-        
-        // Check hidden files
-        if !self.config.include_hidden {
-            if let Some(name) = path.file_name() {
-                if name.to_string_lossy().starts_with('.') {
+    /// Files satisfying a boolean combination of patterns, with the lines
+    /// that matched each positive pattern as evidence. Fails if a pattern
+    /// does not compile, since the combination would be meaningless.
+    pub fn query(&self, query: &MgrepQuery, directory: &Path) -> Result<MgrepQueryResult> {
+        let deadline = Instant::now() + self.config.timeout;
+        if query.all.is_empty() && query.any.is_empty() {
+            return Err(Error::invalid_arguments("A query needs at least one 'all' or 'any' pattern"));
+        }
+
+        // Patterns are numbered all, then any, then none
+        let patterns: Vec<&String> = query.all.iter().chain(&query.any).chain(&query.none).collect();
+        let mut valid = Vec::with_capacity(patterns.len());
+        for pattern in &patterns {
+            let regex = compile(pattern)
+                .map_err(|e| Error::invalid_arguments(format!("Invalid pattern '{}': {}", pattern, e)))?;
+            valid.push(((*pattern).clone(), regex));
+        }
+        let set = build_set(&valid)?;
+        let roles = Roles {
+            all: 0..query.all.len(),
+            any: query.all.len()..query.all.len() + query.any.len(),
+            none: query.all.len() + query.any.len()..patterns.len(),
+        };
+
+        let needed = Query::all(
+            query
+                .all
+                .iter()
+                .map(|p| Query::from_regex(p, false))
+                .chain((!query.any.is_empty()).then(|| Query::any(query.any.iter().map(|p| Query::from_regex(p, false))))),
+        );
+        let (index, files) = self.files(directory, deadline, &needed)?;
+        let mut result = MgrepQueryResult {
+            index,
+            ..Default::default()
+        };
+
+        let limit = self.config.max_results_per_pattern;
+        let max_files = self.config.max_files;
+        let scan = |path: &Path| {
+            let bytes = read_text(path)?;
+            let lines = line_hits(&set, &bytes);
+            let evidence = match query.window {
+                Some(window) => roles.window_evidence(&lines, window.max(1)),
+                None => roles.file_evidence(&lines),
+            }?;
+            Some(evidence_matches(&valid, &roles, path, &bytes, &evidence, limit))
+        };
+        (result.files_searched, result.timed_out) = self.scan_files(&files, deadline, scan, |file| {
+            if let Some(file) = file {
+                if result.files.len() >= max_files {
+                    result.truncated = true;
                     return false;
                 }
+                self.progress.matches_found(1);
+                result.files.push(file);
             }
-        }
+            true
+        })?;
+        Ok(result)
+    }
 
-        // Check exclude patterns
-        for pattern in &self.config.exclude_patterns {
-            if glob::Pattern::new(pattern)
-                .map(|p| p.matches(&path_str))
-                .unwrap_or(false)
-            {
-                return false;
+    /// Files to scan under `directory`, narrowed by the trigram index
+    fn files(&self, directory: &Path, deadline: Instant, query: &Query) -> Result<(IndexUsage, Vec<DirEntry>)> {
+        let files = self
+            .walker()?
+            .with_cancel(self.cancel.clone())
+            .with_deadline(deadline)
+            .files_parallel(directory);
+        self.cancel.check()?;
+        Ok(if self.config.use_index {
+            index::narrow(directory, files, query)
+        } else {
+            (IndexUsage::None, files)
+        })
+    }
+
+    /// Run `scan` over `files` in parallel batches and hand the results to
+    /// `consume` in path order until it returns false. Returns the number
+    /// of files scanned and whether the timeout cut the scan short.
+    fn scan_files<T: Send>(
+        &self,
+        files: &[DirEntry],
+        deadline: Instant,
+        scan: impl Fn(&Path) -> T + Sync,
+        mut consume: impl FnMut(T) -> bool,
+    ) -> Result<(usize, bool)> {
+        let mut scanned = 0;
+        for batch in files.chunks(BATCH_SIZE) {
+            let outcomes: Vec<Option<Option<T>>> = batch
+                .par_iter()
+                .map(|entry| {
+                    if self.cancel.is_cancelled() || Instant::now() > deadline {
+                        return None;
+                    }
+                    let fits = entry
+                        .metadata()
+                        .map(|m| m.len() <= self.config.max_file_size)
+                        .unwrap_or(false);
+                    if !fits {
+                        return Some(None);
+                    }
+                    self.progress.file_scanned();
+                    Some(Some(scan(entry.path())))
+                })
+                .collect();
+            self.cancel.check()?;
+
+            for outcome in outcomes {
+                // Keep the output a prefix of the full, ordered result
+                let Some(outcome) = outcome else {
+                    return Ok((scanned, true));
+                };
+                let Some(value) = outcome else {
+                    continue;
+                };
+                scanned += 1;
+                if !consume(value) {
+                    return Ok((scanned, false));
+                }
             }
         }
-
-        true
+        Ok((scanned, false))
     }
 
+    fn walker(&self) -> Result<Walker> {
+        Walker::new(WalkConfig {
+            respect_ignore: self.config.respect_ignore,
+            include_hidden: self.config.include_hidden,
+            include_patterns: self.config.include_patterns.clone(),
+            exclude_patterns: self.config.exclude_patterns.clone(),
+            file_types: self.config.file_types.clone(),
+            exclude_file_types: self.config.exclude_file_types.clone(),
+            ..Default::default()
+        })
+    }
 }
 
 impl Default for MgrepTool {
@@ -160,6 +366,174 @@ impl Default for MgrepTool {
     }
 }
 
+/// Per-pattern regex with the same line semantics as the set
+fn compile(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).multi_line(true).crlf(true).build()
+}
+
+fn build_set(patterns: &[(String, Regex)]) -> Result<RegexSet> {
+    Ok(RegexSetBuilder::new(patterns.iter().map(|(p, _)| p))
+        .multi_line(true)
+        .crlf(true)
+        .build()?)
+}
+
+/// Contents of a text file; `None` for binary or unreadable files
+fn read_text(path: &Path) -> Option<Vec<u8>> {
+    let bytes = std::fs::read(path).ok()?;
+    memchr::memchr(0, &bytes).is_none().then_some(bytes)
+}
+
+fn lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.split(|&b| b == b'\n').map(trim_cr)
+}
+
+/// Build a match of pattern `i` on `line`
+fn line_match(patterns: &[(String, Regex)], i: usize, file: &str, number: usize, line: &[u8]) -> MgrepMatch {
+    let (pattern, regex) = &patterns[i];
+    let mut spans: Vec<(usize, usize)> = regex.find_iter(line).map(|m| (m.start(), m.end())).collect();
+    let content = decode(line, &mut spans);
+    MgrepMatch {
+        pattern: pattern.clone(),
+        file: file.to_string(),
+        line: number,
+        content,
+        spans,
+    }
+}
+
+/// Matches in one file as `(pattern index, match)`, at most `limit` per pattern
+fn scan_lines(
+    set: &RegexSet,
+    patterns: &[(String, Regex)],
+    path: &Path,
+    bytes: &[u8],
+    limit: usize,
+) -> Vec<(usize, MgrepMatch)> {
+    let file = path.display().to_string();
+    let mut counts = vec![0; patterns.len()];
+    let mut matches = Vec::new();
+    for (index, line) in lines(bytes).enumerate() {
+        for i in set.matches(line).iter() {
+            if counts[i] < limit {
+                counts[i] += 1;
+                matches.push((i, line_match(patterns, i, &file, index + 1, line)));
+            }
+        }
+        if counts.iter().all(|&count| count >= limit) {
+            break;
+        }
+    }
+    matches
+}
+
+/// Patterns matching each line that matched any, as `(line index, patterns)`
+fn line_hits(set: &RegexSet, bytes: &[u8]) -> Vec<(usize, Vec<usize>)> {
+    if !set.is_match(bytes) {
+        return Vec::new();
+    }
+    lines(bytes)
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let matched: Vec<usize> = set.matches(line).iter().collect();
+            (!matched.is_empty()).then_some((index, matched))
+        })
+        .collect()
+}
+
+/// Which query list each pattern index belongs to
+struct Roles {
+    all: Range<usize>,
+    any: Range<usize>,
+    none: Range<usize>,
+}
+
+impl Roles {
+    /// Whether the patterns in `present` satisfy the query
+    fn satisfied(&self, present: &[bool]) -> bool {
+        self.all.clone().all(|i| present[i])
+            && (self.any.is_empty() || self.any.clone().any(|i| present[i]))
+            && !self.none.clone().any(|i| present[i])
+    }
+
+    fn is_positive(&self, pattern: usize) -> bool {
+        !self.none.contains(&pattern)
+    }
+
+    /// Evidence `(line index, pattern)` pairs if the whole file satisfies the query
+    fn file_evidence(&self, lines: &[(usize, Vec<usize>)]) -> Option<BTreeSet<(usize, usize)>> {
+        let mut present = vec![false; self.none.end];
+        for (_, patterns) in lines {
+            for &i in patterns {
+                present[i] = true;
+            }
+        }
+        self.satisfied(&present).then(|| self.positive_hits(lines))
+    }
+
+    /// Evidence from every run of at most `window` lines that satisfies the
+    /// query on its own. A `none` pattern only disqualifies the runs it
+    /// falls in.
+    fn window_evidence(&self, lines: &[(usize, Vec<usize>)], window: usize) -> Option<BTreeSet<(usize, usize)>> {
+        let mut evidence = BTreeSet::new();
+        for (end, (last, _)) in lines.iter().enumerate() {
+            let first = last.saturating_sub(window - 1);
+            let mut start = lines[..=end].partition_point(|(line, _)| *line < first);
+            // The longest run ending here starts after the last `none` hit
+            if let Some(excluded) = lines[start..=end]
+                .iter()
+                .rposition(|(_, patterns)| patterns.iter().any(|i| self.none.contains(i)))
+            {
+                start += excluded + 1;
+            }
+            if start > end {
+                continue;
+            }
+            let run = &lines[start..=end];
+
+            let mut present = vec![false; self.none.end];
+            for (_, patterns) in run {
+                for &i in patterns {
+                    present[i] = true;
+                }
+            }
+            if self.satisfied(&present) {
+                evidence.extend(self.positive_hits(run));
+            }
+        }
+        (!evidence.is_empty()).then_some(evidence)
+    }
+
+    fn positive_hits(&self, lines: &[(usize, Vec<usize>)]) -> BTreeSet<(usize, usize)> {
+        lines
+            .iter()
+            .flat_map(|(line, patterns)| patterns.iter().filter(|&&i| self.is_positive(i)).map(move |&i| (*line, i)))
+            .collect()
+    }
+}
+
+/// Turn evidence pairs into matches, at most `limit` lines per pattern
+fn evidence_matches(
+    patterns: &[(String, Regex)],
+    roles: &Roles,
+    path: &Path,
+    bytes: &[u8],
+    evidence: &BTreeSet<(usize, usize)>,
+    limit: usize,
+) -> MgrepFileMatch {
+    let file = path.display().to_string();
+    let lines: Vec<&[u8]> = lines(bytes).collect();
+    let mut counts = vec![0; patterns.len()];
+    let mut matches = Vec::new();
+    for &(line, i) in evidence {
+        if roles.is_positive(i) && counts[i] < limit {
+            counts[i] += 1;
+            matches.push(line_match(patterns, i, &file, line + 1, lines[line]));
+        }
+    }
+    MgrepFileMatch { file, evidence: matches }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -190,5 +564,119 @@ mod tests {
         assert_eq!(result.results["const"].len(), 2);
         assert_eq!(result.results["let"].len(), 1);
     }
+
+    #[test]
+    fn test_mgrep_reports_invalid_patterns_and_limits() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("b.rs"), "fn b() { todo!() }\nfn c() {}\n").unwrap();
+        fs::write(dir.path().join("a.rs"), "fn a() { todo!() }\n").unwrap();
+
+        let tool = MgrepTool::new(MgrepConfig {
+            include_hidden: true,
+            exclude_patterns: vec![],
+            max_results_per_pattern: 2,
+            ..Default::default()
+        });
+        let patterns = ["fn \\w+", "todo!", "(", "absent", "todo!", "(", "todo!"].map(String::from);
+        let result = tool.search(&patterns, dir.path()).unwrap();
+
+        assert_eq!(result.invalid_patterns.len(), 1);
+        assert_eq!(result.invalid_patterns[0].pattern, "(");
+        assert_eq!(result.duplicate_patterns, vec!["todo!", "("]);
+        assert_eq!(result.files_searched, 2);
+
+        let functions = &result.results["fn \\w+"];
+        assert_eq!(functions.len(), 2);
+        assert!(functions[0].file.ends_with("a.rs"));
+        assert_eq!(functions[0].spans, vec![(0, 4)]);
+        assert_eq!(result.results["todo!"].len(), 2);
+        assert!(result.results["absent"].is_empty());
+    }
+
+    #[test]
+    fn test_mgrep_query_files_and_windows() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.rs"), "use tokio;
+async fn run() {}
+
+
+
+fn spawn() {}
+").unwrap();
+        fs::write(dir.path().join("b.rs"), "use tokio;
+fn spawn() {}
+").unwrap();
+        fs::write(dir.path().join("c.rs"), "use tokio;
+fn spawn() {}
+#[cfg(test)]
+").unwrap();
+        fs::write(dir.path().join("d.rs"), "fn spawn() {}
+").unwrap();
+
+        let tool = MgrepTool::new(MgrepConfig {
+            include_hidden: true,
+            exclude_patterns: vec![],
+            ..Default::default()
+        });
+        let mut query = MgrepQuery {
+            all: vec!["tokio".to_string()],
+            any: vec!["spawn".to_string(), "async".to_string()],
+            none: vec!["cfg\\(test\\)".to_string()],
+            window: None,
+        };
+        let result = tool.query(&query, dir.path()).unwrap();
+        let files: Vec<&str> = result.files.iter().map(|f| f.file.rsplit('/').next().unwrap()).collect();
+        assert_eq!(files, ["a.rs", "b.rs"]);
+        let evidence: Vec<(&str, usize)> = result.files[0].evidence.iter().map(|m| (m.pattern.as_str(), m.line)).collect();
+        assert_eq!(evidence, [("tokio", 1), ("async", 2), ("spawn", 6)]);
+
+        // Within two lines, "spawn" in a.rs is too far from "tokio" to count
+        query.window = Some(2);
+        let result = tool.query(&query, dir.path()).unwrap();
+        let evidence: Vec<usize> = result.files[0].evidence.iter().map(|m| m.line).collect();
+        assert_eq!(evidence, [1, 2]);
+        // The excluded line in c.rs only rules out windows that reach it
+        assert_eq!(result.files.len(), 3);
+        let evidence: Vec<usize> = result.files[2].evidence.iter().map(|m| m.line).collect();
+        assert_eq!(evidence, [1, 2]);
+
+        // A shorter run may leave out the excluded line
+        fs::write(dir.path().join("e.rs"), "#[cfg(test)]\nuse tokio;\nfn spawn() {}\n").unwrap();
+        query.window = Some(3);
+        let result = tool.query(&query, dir.path()).unwrap();
+        let e = result.files.iter().find(|f| f.file.ends_with("e.rs")).unwrap();
+        assert_eq!(e.evidence.iter().map(|m| m.line).collect::<Vec<_>>(), [2, 3]);
+
+        query.all.push("(".to_string());
+        assert!(tool.query(&query, dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_mgrep_query_with_index() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.ts"), "useEffect(() => {\n  fetch(url);\n});\n").unwrap();
+        fs::write(root.join("b.ts"), "useEffect(() => {\n  const c = new AbortController();\n  fetch(url, c);\n});\n").unwrap();
+        fs::write(root.join("c.ts"), "export const x = 1;\n").unwrap();
+        index::TrigramIndex::update(&root, &CancelToken::new()).unwrap();
+
+        let tool = MgrepTool::new(MgrepConfig {
+            include_hidden: true,
+            exclude_patterns: vec![],
+            ..Default::default()
+        });
+        // Without `any` patterns only `all` narrows the files
+        let query = MgrepQuery {
+            all: vec!["useEffect".to_string(), "fetch\\(".to_string()],
+            any: vec![],
+            none: vec!["AbortController".to_string()],
+            window: None,
+        };
+        let result = tool.query(&query, &root).unwrap();
+        assert_eq!(result.index, IndexUsage::Used);
+        let files: Vec<&str> = result.files.iter().map(|f| f.file.rsplit('/').next().unwrap()).collect();
+        assert_eq!(files, ["a.ts"]);
+    }
 }
 
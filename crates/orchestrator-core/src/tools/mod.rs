@@ -1,27 +1,45 @@
 //! Tool implementations
 
 pub mod ast;
+pub mod cancel;
+pub mod changeset;
 pub mod diff;
 pub mod file_stats;
 pub mod git;
 pub mod glob;
 pub mod grep;
 pub mod http;
+pub mod index;
 pub mod jq;
 pub mod lsp;
+pub mod merge;
 pub mod mgrep;
+pub mod patch;
+mod process;
+pub mod progress;
+pub mod registry;
 pub mod sed;
+pub mod walk;
 
 pub use ast::AstTool;
+pub use cancel::CancelToken;
+pub use changeset::{ChangeSet, Journal, Snapshot, Transaction};
 pub use diff::DiffTool;
 pub use file_stats::FileStatsTool;
 pub use git::GitTool;
 pub use glob::GlobTool;
 pub use grep::GrepTool;
 pub use http::HttpTool;
+pub use index::{IndexUsage, TrigramIndex};
 pub use jq::JqTool;
 pub use lsp::DiagnosticsTool;
+pub use merge::MergeTool;
 pub use mgrep::MgrepTool;
+pub use patch::PatchTool;
+pub use process::command_exists;
+pub use progress::{Progress, ProgressReporter};
+pub use registry::{Tool, ToolContext, ToolDefinition, ToolRegistry};
 pub use sed::SedTool;
+pub use walk::{WalkConfig, Walker};
 
 
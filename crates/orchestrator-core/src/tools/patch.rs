@@ -0,0 +1,744 @@
+//! Patch tool - apply unified diffs
+//!
+//! [`parse_patch`] reads `diff -u` and `git diff` output for any number of
+//! files, including created, deleted and renamed ones. [`PatchTool`]
+//! applies each hunk where its old lines are found: at the line the hunk
+//! names, or up to `max_offset` lines away, ignoring up to `fuzz` context
+//! lines at either end as `patch` does. The files are written together
+//! through a [`Transaction`], and only if every hunk applies.
+
+use super::cancel::CancelToken;
+use super::changeset::{ChangeSet, Journal, Transaction};
+use super::diff::{Hunk, HunkLine, LineKind};
+use crate::constants::tool;
+use crate::{Error, Result};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Lines of actual content shown around a conflicting hunk
+const SURROUNDING: usize = 2;
+
+/// Configuration for applying patches
+#[derive(Debug, Clone)]
+pub struct PatchConfig {
+    /// Directory the paths in the patch are relative to
+    pub root: PathBuf,
+    /// Leading components to remove from each path; `None` removes the
+    /// `a/` and `b/` prefixes of git diffs
+    pub strip: Option<usize>,
+    /// Context lines that may be ignored at each end of a hunk
+    pub fuzz: usize,
+    /// How many lines from its stated position a hunk may apply; `None`
+    /// searches the whole file
+    pub max_offset: Option<usize>,
+    /// Compare lines ignoring differences in whitespace
+    pub ignore_whitespace: bool,
+    /// Check that the patch applies without writing anything
+    pub dry_run: bool,
+    /// Records the files changed
+    pub journal: Journal,
+}
+
+impl Default for PatchConfig {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            strip: None,
+            fuzz: 2,
+            max_offset: None,
+            ignore_whitespace: false,
+            dry_run: false,
+            journal: Journal::default(),
+        }
+    }
+}
+
+/// What a [`FilePatch`] does to its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOperation {
+    Modify,
+    Create,
+    Delete,
+    Rename,
+}
+
+impl PatchOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Modify => "modify",
+            Self::Create => "create",
+            Self::Delete => "delete",
+            Self::Rename => "rename",
+        }
+    }
+}
+
+/// The changes to one file in a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// `None` when the file is created
+    pub old_path: Option<PathBuf>,
+    /// `None` when the file is deleted
+    pub new_path: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    pub fn operation(&self) -> PatchOperation {
+        match (&self.old_path, &self.new_path) {
+            (None, _) => PatchOperation::Create,
+            (_, None) => PatchOperation::Delete,
+            (Some(old), Some(new)) if old != new => PatchOperation::Rename,
+            _ => PatchOperation::Modify,
+        }
+    }
+
+    /// Where the file ends up, or the file deleted
+    pub fn path(&self) -> &Path {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or(Path::new(""))
+    }
+}
+
+/// Outcome of one hunk
+#[derive(Debug, Clone)]
+pub struct HunkResult {
+    /// 1-based line the hunk names in the old file
+    pub old_start: usize,
+    /// 1-based line its first line applied at, `None` on conflict
+    pub applied_at: Option<usize>,
+    /// Lines between where the hunk applied and where it said it would
+    pub offset: isize,
+    /// Context lines ignored at each end to apply it
+    pub fuzz: usize,
+    /// On conflict, the old lines the hunk looked for
+    pub expected: Vec<String>,
+    /// On conflict, the file's lines around where they should be
+    pub actual: Vec<String>,
+    /// 1-based line of the first of `actual`
+    pub actual_start: usize,
+}
+
+impl HunkResult {
+    pub fn is_applied(&self) -> bool {
+        self.applied_at.is_some()
+    }
+}
+
+/// Outcome of patching one file
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub path: PathBuf,
+    /// The path renamed from
+    pub old_path: Option<PathBuf>,
+    pub operation: PatchOperation,
+    pub hunks: Vec<HunkResult>,
+    /// Why the file could not be patched at all, such as it missing
+    pub error: Option<String>,
+}
+
+impl FileResult {
+    pub fn is_applied(&self) -> bool {
+        self.error.is_none() && self.hunks.iter().all(HunkResult::is_applied)
+    }
+}
+
+/// Outcome of applying a patch
+#[derive(Debug, Clone, Default)]
+pub struct PatchResult {
+    pub files: Vec<FileResult>,
+    /// `None` for dry runs and patches that did not apply
+    pub changeset: Option<ChangeSet>,
+}
+
+impl PatchResult {
+    /// Whether every file and hunk applied, or would for a dry run
+    pub fn is_applied(&self) -> bool {
+        self.files.iter().all(FileResult::is_applied)
+    }
+}
+
+/// Where a hunk matched: the first old line, the context lines ignored
+/// at each end, and the offset from where the hunk said it would be
+struct Placement {
+    start: usize,
+    front: usize,
+    back: usize,
+    offset: isize,
+}
+
+/// New file contents, `None` for a deleted file, in the order produced
+type Writes = Vec<(PathBuf, Option<String>)>;
+
+/// Tool applying unified diffs
+pub struct PatchTool {
+    config: PatchConfig,
+    cancel: CancelToken,
+}
+
+impl PatchTool {
+    pub fn new(config: PatchConfig) -> Self {
+        Self {
+            config,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Apply `patch`, writing every file it changes as one change set if
+    /// all of its hunks apply, and nothing otherwise
+    pub fn apply(&self, patch: &str) -> Result<PatchResult> {
+        let patches = parse_patch(patch, self.config.strip)?;
+        if patches.is_empty() {
+            return Err(Error::invalid_arguments("No file changes found in the patch"));
+        }
+
+        let mut result = PatchResult::default();
+        // Later patches to the same file see the earlier ones applied
+        let mut writes: Writes = Vec::new();
+        for file in &patches {
+            self.cancel.check()?;
+            let operation = file.operation();
+            let mut file_result = FileResult {
+                path: file.path().to_path_buf(),
+                old_path: file.old_path.clone().filter(|_| operation == PatchOperation::Rename),
+                operation,
+                hunks: Vec::new(),
+                error: None,
+            };
+            match self.patch_file(file, &writes, &mut file_result.hunks) {
+                Ok(changes) => writes.extend(changes),
+                Err(e) => file_result.error = Some(e),
+            }
+            result.files.push(file_result);
+        }
+
+        if !self.config.dry_run && result.is_applied() {
+            let mut transaction = Transaction::new(tool::APPLY_PATCH);
+            for (i, (path, contents)) in writes.iter().enumerate() {
+                // Only the last contents of a file patched twice are written
+                if writes[i + 1..].iter().any(|(later, _)| later == path) {
+                    continue;
+                }
+                match contents.clone() {
+                    Some(contents) => transaction.write(path, contents),
+                    None => transaction.remove(path),
+                }
+            }
+            result.changeset = Some(transaction.commit(&self.config.journal)?);
+        }
+        Ok(result)
+    }
+
+    /// Apply one file's hunks, recording each outcome in `hunks`, and
+    /// return the writes it makes; nothing if a hunk conflicts
+    fn patch_file(&self, file: &FilePatch, writes: &Writes, hunks: &mut Vec<HunkResult>) -> std::result::Result<Writes, String> {
+        let old = file.old_path.as_deref().map(|path| self.resolve(path)).transpose()?;
+        let new = file.new_path.as_deref().map(|path| self.resolve(path)).transpose()?;
+        let original = match &old {
+            Some(path) => read(path, writes)?.ok_or_else(|| format!("{} does not exist", path.display()))?,
+            None => String::new(),
+        };
+        if let Some(path) = &new
+            && old.as_ref() != Some(path)
+            && read(path, writes)?.is_some_and(|contents| old.is_some() || !contents.is_empty())
+        {
+            return Err(format!("{} already exists", path.display()));
+        }
+
+        let patched = self.apply_hunks(&original, &file.hunks, hunks);
+        if !hunks.iter().all(HunkResult::is_applied) {
+            return Ok(Vec::new());
+        }
+        match (old, new) {
+            (Some(old), None) if !patched.is_empty() => {
+                Err(format!("{} is not empty after the patch, so it was not deleted", old.display()))
+            }
+            (Some(old), None) => Ok(vec![(old, None)]),
+            (Some(old), Some(new)) if old != new => Ok(vec![(old, None), (new, Some(patched))]),
+            (_, Some(new)) => Ok(vec![(new, Some(patched))]),
+            (None, None) => Err("the patch names no file".to_string()),
+        }
+    }
+
+    /// `path` under the root; paths leaving it are refused
+    fn resolve(&self, path: &Path) -> std::result::Result<PathBuf, String> {
+        if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(format!("{} is outside the patch root", path.display()));
+        }
+        Ok(self.config.root.join(path))
+    }
+
+    /// `original` with every hunk that matches applied, recording where
+    /// each applied or what was found instead
+    fn apply_hunks(&self, original: &str, hunks: &[Hunk], results: &mut Vec<HunkResult>) -> String {
+        let lines: Vec<&str> = original.split_inclusive('\n').collect();
+        let keys: Vec<Cow<str>> = lines.iter().map(|line| self.key(trim_eol(line))).collect();
+        let eol = if lines.first().is_some_and(|line| line.ends_with("\r\n")) { "\r\n" } else { "\n" };
+
+        let mut out = String::with_capacity(original.len());
+        let mut cursor = 0;
+        let mut drift = 0;
+        for hunk in hunks {
+            let expected = hunk.old_start - usize::from(hunk.old_lines > 0);
+            let Some(place) = self.place(hunk, &keys, cursor, expected, drift) else {
+                let at = expected.saturating_add_signed(drift).min(lines.len());
+                let window = at.saturating_sub(SURROUNDING)..(at + hunk.old_lines + SURROUNDING).min(lines.len());
+                results.push(HunkResult {
+                    old_start: hunk.old_start,
+                    applied_at: None,
+                    offset: 0,
+                    fuzz: 0,
+                    expected: old_side(&hunk.lines).map(|line| line.content.clone()).collect(),
+                    actual: lines[window.clone()].iter().map(|line| trim_eol(line).to_string()).collect(),
+                    actual_start: window.start + 1,
+                });
+                continue;
+            };
+
+            lines[cursor..place.start].iter().for_each(|line| out.push_str(line));
+            let mut at = place.start;
+            for line in &hunk.lines[place.front..hunk.lines.len() - place.back] {
+                match line.kind {
+                    LineKind::Context => {
+                        out.push_str(lines[at]);
+                        at += 1;
+                    }
+                    LineKind::Delete => at += 1,
+                    LineKind::Insert => {
+                        out.push_str(&line.content);
+                        if !line.no_newline {
+                            out.push_str(eol);
+                        }
+                    }
+                }
+            }
+            results.push(HunkResult {
+                old_start: hunk.old_start,
+                // Context dropped by fuzz may lie before the start of the file
+                applied_at: Some(place.start.saturating_sub(place.front) + 1),
+                offset: place.offset,
+                fuzz: place.front.max(place.back),
+                expected: Vec::new(),
+                actual: Vec::new(),
+                actual_start: 0,
+            });
+            cursor = at;
+            drift = place.offset;
+        }
+        lines[cursor..].iter().for_each(|line| out.push_str(line));
+        out
+    }
+
+    /// Find where `hunk`'s old lines are in `keys`, at or after `cursor`:
+    /// nearest to `expected` moved by the previous hunk's offset, with as
+    /// little context ignored as possible
+    fn place(&self, hunk: &Hunk, keys: &[Cow<str>], cursor: usize, expected: usize, drift: isize) -> Option<Placement> {
+        let leading = hunk.lines.iter().take_while(|line| line.kind == LineKind::Context).count();
+        let trailing = hunk.lines.iter().rev().take_while(|line| line.kind == LineKind::Context).count();
+        let old_lines = old_side(&hunk.lines).count();
+        for fuzz in 0..=self.config.fuzz {
+            let (front, back) = (fuzz.min(leading), fuzz.min(trailing));
+            // Fuzz must ignore more context than before and keep some
+            if fuzz > 0 && ((front < fuzz && back < fuzz) || front + back >= old_lines) {
+                break;
+            }
+            let pattern: Vec<Cow<str>> = old_side(&hunk.lines[front..hunk.lines.len() - back])
+                .map(|line| self.key(&line.content))
+                .collect();
+            let target = (expected + front).saturating_add_signed(drift);
+            let fits = |start: usize| {
+                start >= cursor && start + pattern.len() <= keys.len() && keys[start..start + pattern.len()] == pattern[..]
+            };
+            let limit = self.config.max_offset.unwrap_or(keys.len() + 1);
+            for distance in 0..=limit {
+                let after = target + distance;
+                let before = target.checked_sub(distance).filter(|&start| start >= cursor);
+                if before.is_none() && after + pattern.len() > keys.len() {
+                    break;
+                }
+                if let Some(start) = [Some(after), before].into_iter().flatten().find(|&start| fits(start)) {
+                    return Some(Placement {
+                        start,
+                        front,
+                        back,
+                        offset: start as isize - (expected + front) as isize,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// What lines are compared by
+    fn key<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        if self.config.ignore_whitespace {
+            Cow::Owned(line.split_whitespace().collect::<Vec<_>>().join(" "))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+}
+
+impl Default for PatchTool {
+    fn default() -> Self {
+        Self::new(PatchConfig::default())
+    }
+}
+
+/// Parse the file changes in a unified diff. Text around them, such as a
+/// commit message, is ignored. `strip` is as in [`PatchConfig`].
+pub fn parse_patch(text: &str, strip: Option<usize>) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    // Whether the last file came from a `diff --git` header and has not
+    // seen its `---`/`+++` lines yet
+    let mut git_header = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            let (old, new) = rest.rsplit_once(" b/").map_or((rest, rest), |(old, new)| (old, new));
+            files.push(FilePatch {
+                old_path: Some(strip_path(old, strip, "a/")),
+                new_path: Some(strip_path(new, strip.map(|n| n.saturating_sub(1)), "")),
+                hunks: Vec::new(),
+            });
+            git_header = true;
+        } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+            return Err(Error::invalid_arguments("Binary patches are not supported"));
+        } else if let Some(file) = files.last_mut().filter(|_| git_header) {
+            if line.starts_with("new file mode") {
+                file.old_path = None;
+            } else if line.starts_with("deleted file mode") {
+                file.new_path = None;
+            } else if let Some(path) = line.strip_prefix("rename from ") {
+                file.old_path = Some(PathBuf::from(unquote(path)));
+            } else if let Some(path) = line.strip_prefix("rename to ") {
+                file.new_path = Some(PathBuf::from(unquote(path)));
+            }
+        }
+
+        if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.get(i).and_then(|next| next.strip_prefix("+++ "))
+        {
+            i += 1;
+            let old = header_path(old, strip, "a/");
+            let new = header_path(new, strip, "b/");
+            match files.last_mut().filter(|_| git_header) {
+                // The git header already named the files
+                Some(file) => {
+                    file.old_path = old.and(file.old_path.take());
+                    file.new_path = new.and(file.new_path.take());
+                }
+                None => files.push(FilePatch {
+                    old_path: old,
+                    new_path: new,
+                    hunks: Vec::new(),
+                }),
+            }
+            git_header = false;
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let Some(file) = files.last_mut() else {
+                return Err(Error::invalid_arguments(format!("Hunk before any file header at line {}", i)));
+            };
+            let hunk = parse_hunk(header, &lines, &mut i)?;
+            file.hunks.push(hunk);
+            git_header = false;
+        }
+    }
+
+    files.retain(|file| !file.hunks.is_empty() || file.operation() != PatchOperation::Modify);
+    if let Some(file) = files.iter().find(|file| file.old_path.is_none() && file.new_path.is_none()) {
+        return Err(Error::invalid_arguments(format!(
+            "A file in the patch has no name ({} hunks)",
+            file.hunks.len()
+        )));
+    }
+    Ok(files)
+}
+
+/// Parse a hunk from its header, after `@@ `, and the lines from `*i`,
+/// leaving `*i` after its last line
+fn parse_hunk(header: &str, lines: &[&str], i: &mut usize) -> Result<Hunk> {
+    let header_line = *i;
+    let invalid = || Error::invalid_arguments(format!("Invalid hunk header at line {}: @@ {}", header_line, header));
+    let mut ranges = header.split_whitespace();
+    let old = ranges.next().and_then(|r| r.strip_prefix('-')).and_then(parse_range).ok_or_else(invalid)?;
+    let new = ranges.next().and_then(|r| r.strip_prefix('+')).and_then(parse_range).ok_or_else(invalid)?;
+    // Only an empty side may start at line 0
+    if (old.0 == 0 && old.1 > 0) || (new.0 == 0 && new.1 > 0) {
+        return Err(invalid());
+    }
+
+    let mut hunk = Hunk {
+        old_start: old.0,
+        old_lines: old.1,
+        new_start: new.0,
+        new_lines: new.1,
+        lines: Vec::new(),
+    };
+    let (mut old_line, mut new_line) = (old.0.max(1), new.0.max(1));
+    let (mut old_left, mut new_left) = (old.1, new.1);
+    while old_left > 0 || new_left > 0 || lines.get(*i).is_some_and(|line| line.starts_with('\\')) {
+        let Some(&line) = lines.get(*i) else {
+            return Err(Error::invalid_arguments(format!("Hunk at line {} ends early", header_line)));
+        };
+        *i += 1;
+        let (kind, content) = match line.split_at_checked(1) {
+            Some(("\\", _)) => {
+                if let Some(last) = hunk.lines.last_mut() {
+                    last.no_newline = true;
+                }
+                continue;
+            }
+            Some((" ", content)) => (LineKind::Context, content),
+            Some(("-", content)) => (LineKind::Delete, content),
+            Some(("+", content)) => (LineKind::Insert, content),
+            // Editors strip the space of empty context lines
+            None => (LineKind::Context, ""),
+            Some(_) => {
+                return Err(Error::invalid_arguments(format!("Invalid line in hunk at line {}: {}", *i, line)));
+            }
+        };
+        let (takes_old, takes_new) = (kind != LineKind::Insert, kind != LineKind::Delete);
+        if (takes_old && old_left == 0) || (takes_new && new_left == 0) {
+            return Err(Error::invalid_arguments(format!(
+                "Hunk at line {} has more lines than its header says",
+                header_line
+            )));
+        }
+        hunk.lines.push(HunkLine {
+            kind,
+            old_line: takes_old.then_some(old_line),
+            new_line: takes_new.then_some(new_line),
+            content: content.to_string(),
+            no_newline: false,
+            highlights: Vec::new(),
+        });
+        if takes_old {
+            old_left -= 1;
+            old_line += 1;
+        }
+        if takes_new {
+            new_left -= 1;
+            new_line += 1;
+        }
+    }
+    Ok(hunk)
+}
+
+/// `start,len` or `start` from a hunk header
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// The path of a `---` or `+++` line, `None` for `/dev/null`
+fn header_path(field: &str, strip: Option<usize>, prefix: &str) -> Option<PathBuf> {
+    // A tab separates the timestamp of `diff -u`
+    let path = field.split('\t').next().unwrap_or(field).trim_end();
+    (path != "/dev/null").then(|| strip_path(path, strip, prefix))
+}
+
+/// `path` without `strip` leading components, or without `prefix`
+fn strip_path(path: &str, strip: Option<usize>, prefix: &str) -> PathBuf {
+    let path = unquote(path);
+    match strip {
+        Some(n) => path.splitn(n + 1, '/').nth(n).unwrap_or(path).into(),
+        None => path.strip_prefix(prefix).unwrap_or(path).into(),
+    }
+}
+
+/// A path without the quotes git puts around unusual names
+fn unquote(path: &str) -> &str {
+    path.strip_prefix('"').and_then(|p| p.strip_suffix('"')).unwrap_or(path)
+}
+
+/// The lines of a hunk found in the old file
+fn old_side(lines: &[HunkLine]) -> impl Iterator<Item = &HunkLine> {
+    lines.iter().filter(|line| line.kind != LineKind::Insert)
+}
+
+/// A line without its line feed
+fn trim_eol(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Contents of `path` after `writes`, `None` if it does not exist
+fn read(path: &Path, writes: &Writes) -> std::result::Result<Option<String>, String> {
+    if let Some((_, contents)) = writes.iter().rev().find(|(written, _)| written == path) {
+        return Ok(contents.clone());
+    }
+    match fs::read(path) {
+        Ok(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| format!("{} is not UTF-8 text", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const GIT_PATCH: &str = "From 1a2b Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Tidy up
+
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,2 +1,2 @@
+ fn a() {}
+-fn b() {}
+\\ No newline at end of file
++fn b() {}
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 4444444..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/old name.txt b/new name.txt
+similarity index 100%
+rename from old name.txt
+rename to new name.txt
+";
+
+    fn patch_tool(root: &Path, config: PatchConfig) -> PatchTool {
+        PatchTool::new(PatchConfig {
+            root: root.to_path_buf(),
+            journal: Journal::new(root),
+            ..config
+        })
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let files = parse_patch(GIT_PATCH, None).unwrap();
+        let summary: Vec<_> = files.iter().map(|f| (f.operation(), f.path().to_str().unwrap(), f.hunks.len())).collect();
+        assert_eq!(
+            summary,
+            [
+                (PatchOperation::Modify, "src/lib.rs", 1),
+                (PatchOperation::Create, "new.txt", 1),
+                (PatchOperation::Delete, "gone.txt", 1),
+                (PatchOperation::Rename, "new name.txt", 0),
+            ]
+        );
+        let lines = &files[0].hunks[0].lines;
+        assert_eq!(lines.iter().map(|l| l.kind).collect::<Vec<_>>(), [LineKind::Context, LineKind::Delete, LineKind::Insert]);
+        assert!(lines[1].no_newline && !lines[2].no_newline);
+        assert_eq!(files[3].old_path.as_deref(), Some(Path::new("old name.txt")));
+
+        let plain = parse_patch("--- x.txt\t2024-01-01 10:00\n+++ x.txt\t2024-01-02 10:00\n@@ -1 +1 @@\n-a\n+b\n", Some(0)).unwrap();
+        assert_eq!(plain[0].path(), Path::new("x.txt"));
+        assert!(parse_patch("--- x\n+++ x\n@@ -1,2 +1 @@\n-a\n", None).is_err());
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -0,1 +1 @@\n-a\n+b\n", None).is_err());
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1 +0,1 @@\n-a\n+b\n", None).is_err());
+    }
+
+    #[test]
+    fn test_offset_fuzz_and_dry_run() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "intro\nextra\none\ntwo\nthree\nfour\nfive\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "one\ntwo\nthree\nfour\nFIVE\n").unwrap();
+        let hunk = "@@ -1,5 +1,5 @@\n one\n two\n-three\n+THREE\n four\n five\n";
+        let patch = format!("--- a/a.txt\n+++ b/a.txt\n{hunk}--- a/b.txt\n+++ b/b.txt\n{hunk}");
+
+        let dry = patch_tool(dir.path(), PatchConfig { dry_run: true, ..Default::default() }).apply(&patch).unwrap();
+        assert!(dry.is_applied());
+        assert!(dry.changeset.is_none());
+        assert!(fs::read_to_string(dir.path().join("a.txt")).unwrap().contains("three"));
+
+        let result = patch_tool(dir.path(), PatchConfig::default()).apply(&patch).unwrap();
+        assert!(result.is_applied());
+        let placed: Vec<_> = result.files.iter().map(|f| (f.hunks[0].applied_at, f.hunks[0].offset, f.hunks[0].fuzz)).collect();
+        assert_eq!(placed, [(Some(3), 2, 0), (Some(1), 0, 1)]);
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "intro\nextra\none\ntwo\nTHREE\nfour\nfive\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.txt")).unwrap(), "one\ntwo\nTHREE\nfour\nFIVE\n");
+        assert_eq!(result.changeset.unwrap().files.len(), 2);
+
+        // Fuzz drops leading context that is missing at the top of the file
+        fs::write(dir.path().join("c.txt"), "x\ny\n").unwrap();
+        let patch = "--- a/c.txt\n+++ b/c.txt\n@@ -1,3 +1,3 @@\n a\n-x\n+X\n y\n";
+        let result = patch_tool(dir.path(), PatchConfig::default()).apply(patch).unwrap();
+        let hunk = &result.files[0].hunks[0];
+        assert_eq!((hunk.applied_at, hunk.fuzz), (Some(1), 1));
+        assert_eq!(fs::read_to_string(dir.path().join("c.txt")).unwrap(), "X\ny\n");
+    }
+
+    #[test]
+    fn test_conflicts_write_nothing() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "1\n2\n3\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "w\nx\ny\nz\n").unwrap();
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -2 +2 @@\n-2\n+two\n\
+                     --- a/b.txt\n+++ b/b.txt\n@@ -3,2 +3,2 @@\n-q\n+Q\n r\n\
+                     --- a/missing.txt\n+++ b/missing.txt\n@@ -1 +1 @@\n-a\n+b\n";
+        let result = patch_tool(dir.path(), PatchConfig::default()).apply(patch).unwrap();
+
+        assert!(!result.is_applied());
+        assert!(result.changeset.is_none());
+        assert!(result.files[0].is_applied());
+        let conflict = &result.files[1].hunks[0];
+        assert_eq!(conflict.applied_at, None);
+        assert_eq!(conflict.expected, ["q", "r"]);
+        assert_eq!(conflict.actual_start, 1);
+        assert_eq!(conflict.actual, ["w", "x", "y", "z"]);
+        assert!(result.files[2].error.as_deref().unwrap().contains("does not exist"));
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "1\n2\n3\n");
+
+        let outside = patch_tool(dir.path(), PatchConfig::default())
+            .apply("--- /dev/null\n+++ b/../escape.txt\n@@ -0,0 +1 @@\n+x\n")
+            .unwrap();
+        assert!(outside.files[0].error.as_deref().unwrap().contains("outside"));
+    }
+
+    #[test]
+    fn test_create_delete_rename_and_undo() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "fn a() {}\nfn b() {}").unwrap();
+        fs::write(root.join("gone.txt"), "bye\n").unwrap();
+        fs::write(root.join("old name.txt"), "same\n").unwrap();
+
+        let result = patch_tool(root, PatchConfig::default()).apply(GIT_PATCH).unwrap();
+        assert!(result.is_applied(), "{:?}", result.files);
+        assert_eq!(fs::read_to_string(root.join("src/lib.rs")).unwrap(), "fn a() {}\nfn b() {}\n");
+        assert_eq!(fs::read_to_string(root.join("new.txt")).unwrap(), "hello\n");
+        assert_eq!(fs::read_to_string(root.join("new name.txt")).unwrap(), "same\n");
+        assert!(!root.join("gone.txt").exists() && !root.join("old name.txt").exists());
+
+        // Applying it again fails on the files it created
+        let again = patch_tool(root, PatchConfig::default()).apply(GIT_PATCH).unwrap();
+        assert!(again.files[1].error.as_deref().unwrap().contains("already exists"));
+
+        Journal::new(root).undo(&result.changeset.unwrap().id, false).unwrap();
+        assert_eq!(fs::read_to_string(root.join("src/lib.rs")).unwrap(), "fn a() {}\nfn b() {}");
+        assert_eq!(fs::read_to_string(root.join("gone.txt")).unwrap(), "bye\n");
+        assert!(root.join("old name.txt").exists() && !root.join("new name.txt").exists() && !root.join("new.txt").exists());
+    }
+}
@@ -0,0 +1,183 @@
+//! Child process execution with cancellation and timeout
+//!
+//! `Command::output()` blocks until the child exits, which leaves no way to
+//! stop a hung `npx tsc`. [`run_command`] polls the child instead and kills
+//! its whole process group when the token is cancelled or the deadline passes.
+
+use super::cancel::CancelToken;
+use crate::{Error, Result};
+use std::env;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often a running child is checked for exit or cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Run a command to completion, capturing stdout and stderr.
+///
+/// `input` is written to the child's stdin when provided.
+pub(crate) fn run_command(
+    cmd: &mut Command,
+    input: Option<&[u8]>,
+    cancel: &CancelToken,
+    timeout: Option<Duration>,
+) -> Result<Output> {
+    cancel.check()?;
+
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // Give the child its own process group so that grandchildren
+    // (npx -> node -> tsc) are killed along with it.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    let mut child = cmd.spawn()?;
+
+    if let (Some(data), Some(mut stdin)) = (input, child.stdin.take()) {
+        let data = data.to_vec();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&data);
+        });
+    }
+
+    let stdout = spawn_reader(child.stdout.take());
+    let stderr = spawn_reader(child.stderr.take());
+    let start = Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.is_cancelled() {
+            kill(&mut child);
+            return Err(Error::Cancelled);
+        }
+        if let Some(limit) = timeout
+            && start.elapsed() > limit
+        {
+            kill(&mut child);
+            return Err(Error::tool(format!(
+                "Command timed out after {}ms",
+                limit.as_millis()
+            )));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Whether `name` resolves to an executable on `PATH`
+pub fn command_exists(name: &str) -> bool {
+    find_command(name).is_some()
+}
+
+/// The first executable called `name` on `PATH`
+pub fn find_command(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).map(|dir| dir.join(name)).find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    let exts = env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string());
+    path.is_file()
+        || exts
+            .split(';')
+            .any(|ext| path.with_extension(ext.trim_start_matches('.')).is_file())
+}
+
+fn spawn_reader<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // Negative pid targets the process group created in run_command
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command_captures_output() {
+        let output = run_command(
+            Command::new("sh").args(["-c", "cat; echo err >&2"]),
+            Some(b"hello"),
+            &CancelToken::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn test_run_command_cancel_kills_child() {
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+
+        let start = Instant::now();
+        let result = run_command(Command::new("sleep").arg("30"), None, &token, None);
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_command_exists() {
+        assert!(command_exists("sh"));
+        assert!(!command_exists("orchestrator-no-such-command"));
+    }
+
+    #[test]
+    fn test_run_command_timeout() {
+        let result = run_command(
+            Command::new("sleep").arg("30"),
+            None,
+            &CancelToken::new(),
+            Some(Duration::from_millis(50)),
+        );
+        assert!(matches!(result, Err(Error::Tool(_))));
+    }
+}
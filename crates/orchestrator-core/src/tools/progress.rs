@@ -0,0 +1,157 @@
+//! Progress reporting for long-running tools
+//!
+//! Tools bump counters on a [`ProgressReporter`] as they walk files. The
+//! reporter calls its sink with a [`Progress`] snapshot at most once per
+//! interval, so hot loops can report every file without flooding the client.
+//! A default reporter has no sink and costs a single branch per call.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default minimum time between two progress reports
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Snapshot of the work a tool has done so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub files_scanned: usize,
+    pub matches: usize,
+    pub elapsed_ms: u64,
+}
+
+type Sink = dyn Fn(Progress) + Send + Sync;
+
+struct Inner {
+    sink: Box<Sink>,
+    started: Instant,
+    interval_ms: u64,
+    files_scanned: AtomicUsize,
+    matches: AtomicUsize,
+    last_report_ms: AtomicU64,
+}
+
+/// Throttled progress counter. Clones share the same counters and sink.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    inner: Option<Arc<Inner>>,
+}
+
+impl ProgressReporter {
+    /// Report through `sink`, at most once per [`DEFAULT_PROGRESS_INTERVAL`]
+    pub fn new(sink: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self::with_interval(DEFAULT_PROGRESS_INTERVAL, sink)
+    }
+
+    pub fn with_interval(interval: Duration, sink: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self {
+            inner: Some(Arc::new(Inner {
+                sink: Box::new(sink),
+                started: Instant::now(),
+                interval_ms: interval.as_millis() as u64,
+                files_scanned: AtomicUsize::new(0),
+                matches: AtomicUsize::new(0),
+                last_report_ms: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Count one more file as scanned
+    pub fn file_scanned(&self) {
+        if let Some(inner) = &self.inner {
+            inner.files_scanned.fetch_add(1, Ordering::Relaxed);
+            self.tick();
+        }
+    }
+
+    /// Count `count` more matches
+    pub fn matches_found(&self, count: usize) {
+        if let Some(inner) = &self.inner {
+            inner.matches.fetch_add(count, Ordering::Relaxed);
+            self.tick();
+        }
+    }
+
+    /// Report the current counters if the interval has passed since the last report
+    pub fn tick(&self) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let now = inner.started.elapsed().as_millis() as u64;
+        let last = inner.last_report_ms.load(Ordering::Relaxed);
+        if now < last + inner.interval_ms {
+            return;
+        }
+        // Only the thread that wins the swap reports, so parallel walkers
+        // do not emit duplicate snapshots.
+        if inner
+            .last_report_ms
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            (inner.sink)(self.snapshot());
+        }
+    }
+
+    /// Current counters, without reporting them
+    pub fn snapshot(&self) -> Progress {
+        match &self.inner {
+            Some(inner) => Progress {
+                files_scanned: inner.files_scanned.load(Ordering::Relaxed),
+                matches: inner.matches.load(Ordering::Relaxed),
+                elapsed_ms: inner.started.elapsed().as_millis() as u64,
+            },
+            None => Progress::default(),
+        }
+    }
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("enabled", &self.is_enabled())
+            .field("progress", &self.snapshot())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_is_throttled() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let progress = ProgressReporter::with_interval(Duration::from_millis(20), move |p| {
+            sink.lock().unwrap().push(p)
+        });
+
+        for _ in 0..1000 {
+            progress.file_scanned();
+        }
+        progress.matches_found(3);
+        assert!(reports.lock().unwrap().len() <= 1);
+
+        std::thread::sleep(Duration::from_millis(25));
+        progress.tick();
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!(last.files_scanned, 1000);
+        assert_eq!(last.matches, 3);
+    }
+
+    #[test]
+    fn test_disabled_progress_is_noop() {
+        let progress = ProgressReporter::default();
+        progress.file_scanned();
+        assert!(!progress.is_enabled());
+        assert_eq!(progress.snapshot(), Progress::default());
+    }
+}
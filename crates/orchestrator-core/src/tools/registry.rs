@@ -0,0 +1,265 @@
+//! Tool registry
+//!
+//! Every MCP tool is declared once as a [`Tool`]: its name, description,
+//! typed arguments and typed output. Input and output schemas are derived
+//! from those types, so `tools/list` and `tools/call` cannot drift apart.
+
+use super::{CancelToken, Journal, ProgressReporter};
+use crate::{Error, Result};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Per-call state handed to [`Tool::execute`]
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub cancel: CancelToken,
+    pub progress: ProgressReporter,
+    /// Client session the call belongs to
+    pub session_id: Option<String>,
+    /// JSON RPC id of the call
+    pub request_id: Option<String>,
+}
+
+impl ToolContext {
+    pub fn new(cancel: CancelToken) -> Self {
+        Self {
+            cancel,
+            ..Self::default()
+        }
+    }
+
+    /// Report progress of long-running tools through `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Attribute file changes to `session_id` and the call `request_id`
+    pub fn with_origin(mut self, session_id: Option<String>, request_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self.request_id = request_id;
+        self
+    }
+
+    /// Journal of the project at `root`, recording changes made by this call
+    pub fn journal(&self, root: impl Into<PathBuf>) -> Journal {
+        Journal::new(root)
+            .with_session_id(self.session_id.clone())
+            .with_request_id(self.request_id.clone())
+    }
+}
+
+/// A tool callable over `tools/call`
+pub trait Tool: Send + Sync + 'static {
+    /// Arguments, deserialized from `params.arguments`
+    type Args: DeserializeOwned + JsonSchema;
+    /// Result, sent as `structuredContent`
+    type Output: Serialize + JsonSchema;
+
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+
+    /// Whether the tool can run on this host, e.g. its external binary is installed
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, args: Self::Args, ctx: &ToolContext) -> Result<Self::Output>;
+}
+
+/// Tool entry as advertised by `tools/list`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub input_schema: Value,
+    pub output_schema: Value,
+}
+
+/// Object-safe view of [`Tool`] so tools with different types share a registry
+trait DynTool: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn definition(&self) -> ToolDefinition;
+    fn is_available(&self) -> bool;
+    fn call(&self, arguments: Value, ctx: &ToolContext) -> Result<Value>;
+}
+
+impl<T: Tool> DynTool for T {
+    fn name(&self) -> &'static str {
+        Tool::name(self)
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: Tool::name(self),
+            description: self.description(),
+            input_schema: schema_of::<T::Args>(),
+            output_schema: schema_of::<T::Output>(),
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        Tool::is_available(self)
+    }
+
+    fn call(&self, arguments: Value, ctx: &ToolContext) -> Result<Value> {
+        let args = serde_json::from_value(arguments)
+            .map_err(|e| Error::invalid_arguments(e.to_string()))?;
+        Ok(serde_json::to_value(self.execute(args, ctx)?)?)
+    }
+}
+
+fn schema_of<T: JsonSchema>() -> Value {
+    let schema = SchemaGenerator::default().into_root_schema_for::<T>();
+    serde_json::to_value(schema).unwrap_or_default()
+}
+
+/// Ordered set of tools; `tools/list` reports them in registration order
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn DynTool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tool, replacing any earlier tool with the same name
+    pub fn register<T: Tool>(&mut self, tool: T) -> &mut Self {
+        self.tools.retain(|t| t.name() != Tool::name(&tool));
+        self.tools.push(Box::new(tool));
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.iter().any(|t| t.name() == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.tools.iter().map(|t| t.name())
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|t| t.definition()).collect()
+    }
+
+    /// Names of tools that cannot run on this host
+    pub fn unavailable(&self) -> Vec<&'static str> {
+        self.tools
+            .iter()
+            .filter(|t| !t.is_available())
+            .map(|t| t.name())
+            .collect()
+    }
+
+    /// Deserialize `arguments` and run the named tool
+    pub fn call(&self, name: &str, arguments: Value, ctx: &ToolContext) -> Result<Value> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| Error::UnknownTool(name.to_string()))?;
+        tool.call(arguments, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct EchoArgs {
+        /// Text to echo back
+        text: String,
+    }
+
+    #[derive(Serialize, JsonSchema)]
+    struct EchoOutput {
+        text: String,
+    }
+
+    struct Echo;
+
+    impl Tool for Echo {
+        type Args = EchoArgs;
+        type Output = EchoOutput;
+
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn description(&self) -> &'static str {
+            "Echo the input"
+        }
+
+        fn execute(&self, args: EchoArgs, ctx: &ToolContext) -> Result<EchoOutput> {
+            ctx.cancel.check()?;
+            Ok(EchoOutput { text: args.text })
+        }
+    }
+
+    #[test]
+    fn test_registry_definitions_and_call() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Echo);
+
+        let defs = registry.definitions();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].input_schema["required"], json!(["text"]));
+        assert_eq!(
+            defs[0].input_schema["properties"]["text"]["description"],
+            "Text to echo back"
+        );
+        assert_eq!(defs[0].output_schema["type"], "object");
+
+        let ctx = ToolContext::default();
+        let out = registry.call("echo", json!({"text": "hi"}), &ctx).unwrap();
+        assert_eq!(out, json!({"text": "hi"}));
+
+        assert!(matches!(
+            registry.call("echo", json!({}), &ctx),
+            Err(Error::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            registry.call("nope", json!({}), &ctx),
+            Err(Error::UnknownTool(_))
+        ));
+    }
+
+    struct Offline;
+
+    impl Tool for Offline {
+        type Args = EchoArgs;
+        type Output = EchoOutput;
+
+        fn name(&self) -> &'static str {
+            "offline"
+        }
+
+        fn description(&self) -> &'static str {
+            "Needs a binary that is not installed"
+        }
+
+        fn is_available(&self) -> bool {
+            false
+        }
+
+        fn execute(&self, args: EchoArgs, _ctx: &ToolContext) -> Result<EchoOutput> {
+            Ok(EchoOutput { text: args.text })
+        }
+    }
+
+    #[test]
+    fn test_registry_unavailable() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Echo).register(Offline);
+        assert_eq!(registry.unavailable(), vec!["offline"]);
+    }
+}
@@ -1,11 +1,24 @@
 //! Sed-like find and replace tool with timeout protection
-
-use crate::Result;
-use regex::Regex;
+//!
+//! [`SedTool::replace_atomic`] computes every replacement before touching a
+//! file and writes them as one [`ChangeSet`], which can be undone later.
+//!
+//! Files are edited as bytes, so line endings, a UTF-8 byte order mark and
+//! any ASCII-compatible encoding survive untouched. UTF-16 files with a
+//! byte order mark are decoded, edited and encoded again.
+
+use super::cancel::CancelToken;
+use super::changeset::{self, ChangeSet, Journal, Snapshot, Transaction};
+use super::diff::unified_diff;
+use super::progress::ProgressReporter;
+use super::walk::{WalkConfig, Walker};
+use crate::constants::tool;
+use crate::{Error, Result};
+use regex::bytes::{NoExpand, Regex, RegexBuilder};
+use std::borrow::Cow;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use walkdir::WalkDir;
 
 /// Configuration for sed operations
 #[derive(Debug, Clone)]
@@ -18,10 +31,32 @@ pub struct SedConfig {
     pub backup: bool,
     /// Dry run (don't actually modify files)
     pub dry_run: bool,
+    /// Include hidden files
+    pub include_hidden: bool,
+    /// Honor .gitignore, .ignore and .orchestratorignore
+    pub respect_ignore: bool,
     /// File patterns to include
     pub include_patterns: Vec<String>,
     /// File patterns to exclude
     pub exclude_patterns: Vec<String>,
+    /// Named file types to include, as in ripgrep's `--type` (`rust`, `ts`, `py`, ...)
+    pub file_types: Vec<String>,
+    /// Named file types to skip
+    pub exclude_file_types: Vec<String>,
+    /// Records the files changed by journaled replacements
+    pub journal: Journal,
+    /// Attach a unified diff with this many context lines to each result
+    pub diff_context: Option<usize>,
+    /// Match against the whole file instead of line by line, so a pattern
+    /// can span lines. `^` and `$` still match at line boundaries; `(?s)`
+    /// lets `.` match newlines too.
+    pub multiline: bool,
+    /// Treat the pattern and replacement as plain text
+    pub literal: bool,
+    /// Only replace within these lines (1-based, inclusive)
+    pub line_range: Option<(usize, usize)>,
+    /// Replace at most this many matches per file
+    pub max_replacements: Option<usize>,
 }
 
 impl Default for SedConfig {
@@ -31,13 +66,18 @@ impl Default for SedConfig {
             max_file_size: 10 * 1024 * 1024, // 10MB
             backup: false,
             dry_run: false,
+            include_hidden: false,
+            respect_ignore: true,
             include_patterns: vec![],
-            exclude_patterns: vec![
-                "**/node_modules/**".to_string(),
-                "**/.git/**".to_string(),
-                "**/target/**".to_string(),
-                "**/dist/**".to_string(),
-            ],
+            exclude_patterns: vec!["**/.git/**".to_string()],
+            file_types: vec![],
+            exclude_file_types: vec![],
+            journal: Journal::default(),
+            diff_context: None,
+            multiline: false,
+            literal: false,
+            line_range: None,
+            max_replacements: None,
         }
     }
 }
@@ -49,16 +89,51 @@ pub struct SedResult {
     pub replacements: usize,
     pub original_lines: Vec<String>,
     pub modified_lines: Vec<String>,
+    /// Unified diff of the change, if `diff_context` is set
+    pub diff: Option<String>,
+}
+
+/// Replacements written together by [`SedTool::replace_atomic`]
+#[derive(Debug, Clone, Default)]
+pub struct SedChange {
+    pub results: Vec<SedResult>,
+    /// `None` for dry runs and when nothing matched
+    pub changeset: Option<ChangeSet>,
+}
+
+/// A replacement computed in memory, with the file's bytes before and after
+struct FileEdit {
+    result: SedResult,
+    original: Vec<u8>,
+    modified: Vec<u8>,
 }
 
 /// Sed-like find and replace tool
 pub struct SedTool {
     config: SedConfig,
+    cancel: CancelToken,
+    progress: ProgressReporter,
 }
 
 impl SedTool {
     pub fn new(config: SedConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            cancel: CancelToken::default(),
+            progress: ProgressReporter::default(),
+        }
+    }
+
+    /// Abort the operation when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Report files scanned and matches found to `progress`
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
     }
 
     /// Replace pattern in a single file
@@ -68,60 +143,22 @@ impl SedTool {
         replacement: &str,
         file_path: &Path,
     ) -> Result<Option<SedResult>> {
-        // Check file size
-        if let Ok(metadata) = fs::metadata(file_path) {
-            if metadata.len() > self.config.max_file_size {
-                return Ok(None);
-            }
-        }
-
-        let content = fs::read_to_string(file_path)?;
-        let regex = Regex::new(pattern)?;
-        
-        let mut replacements = 0;
-        let original_lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        let mut modified_lines = Vec::new();
-
-        for line in content.lines() {
-            if regex.is_match(line) {
-                let new_line = regex.replace_all(line, replacement).to_string();
-                if new_line != line {
-                    replacements += 1;
-                }
-                modified_lines.push(new_line);
-            } else {
-                modified_lines.push(line.to_string());
-            }
-        }
-
-        if replacements == 0 {
+        let regex = self.regex(pattern)?;
+        let Some(edit) = self.compute(&regex, replacement, file_path)? else {
             return Ok(None);
-        }
+        };
 
         // Write changes if not dry run
         if !self.config.dry_run {
             // Create backup if requested
             if self.config.backup {
                 let backup_path = format!("{}.bak", file_path.display());
-                fs::write(&backup_path, &content)?;
+                fs::write(&backup_path, &edit.original)?;
             }
-
-            let new_content = modified_lines.join("\n");
-            // Preserve trailing newline if original had one
-            let new_content = if content.ends_with('\n') {
-                format!("{}\n", new_content)
-            } else {
-                new_content
-            };
-            fs::write(file_path, new_content)?;
+            fs::write(file_path, &edit.modified)?;
         }
 
-        Ok(Some(SedResult {
-            file: file_path.display().to_string(),
-            replacements,
-            original_lines,
-            modified_lines,
-        }))
+        Ok(Some(edit.result))
     }
 
     /// Replace pattern in multiple files in a directory
@@ -134,27 +171,20 @@ impl SedTool {
         let start = Instant::now();
         let mut results = Vec::new();
 
-        let walker = WalkDir::new(directory)
-            .follow_links(false)
-            .into_iter()
-            .filter_entry(|e| self.should_include(e.path()));
+        // Fail fast on a bad pattern instead of skipping every file
+        self.regex(pattern)?;
+
+        for entry in self.walker()?.files(directory) {
+            self.cancel.check()?;
 
-        for entry in walker {
             // Check timeout
             if start.elapsed() > self.config.timeout {
                 break;
             }
 
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => continue,
-            };
-
-            if !entry.file_type().is_file() {
-                continue;
-            }
-
+            self.progress.file_scanned();
             if let Ok(Some(result)) = self.replace_in_file(pattern, replacement, entry.path()) {
+                self.progress.matches_found(result.replacements);
                 results.push(result);
             }
         }
@@ -162,31 +192,293 @@ impl SedTool {
         Ok(results)
     }
 
-    /// Check if a path should be included
-    fn should_include(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-
-        // Check exclude patterns
-        for pattern in &self.config.exclude_patterns {
-            if glob::Pattern::new(pattern)
-                .map(|p| p.matches(&path_str))
-                .unwrap_or(false)
-            {
-                return false;
+    /// Replace pattern in `target`, a file or a directory, as one change set:
+    /// every replacement is computed first, then all files are written or
+    /// none are. Times out without changing anything. Backups are not
+    /// written; the journal keeps the original contents instead.
+    pub fn replace_atomic(&self, pattern: &str, replacement: &str, target: &Path) -> Result<SedChange> {
+        let start = Instant::now();
+        let regex = self.regex(pattern)?;
+        let files = self.targets(target)?;
+
+        let mut change = SedChange::default();
+        let mut transaction = Transaction::new(tool::SED_REPLACE);
+        for path in files {
+            self.cancel.check()?;
+            if start.elapsed() > self.config.timeout {
+                return Err(Error::tool(format!(
+                    "Timed out after {} files; no files were changed",
+                    change.results.len()
+                )));
+            }
+
+            self.progress.file_scanned();
+            // Binary and unreadable files are skipped as in directory mode
+            if let Ok(Some(edit)) = self.compute(&regex, replacement, &path) {
+                self.progress.matches_found(edit.result.replacements);
+                transaction.write(path, edit.modified);
+                change.results.push(edit.result);
             }
         }
 
-        // Check include patterns (if specified)
-        if !self.config.include_patterns.is_empty() {
-            return self.config.include_patterns.iter().any(|pattern| {
-                glob::Pattern::new(pattern)
-                    .map(|p| p.matches(&path_str))
-                    .unwrap_or(false)
+        if !self.config.dry_run && !transaction.is_empty() {
+            change.changeset = Some(transaction.commit(&self.config.journal)?);
+        }
+        Ok(change)
+    }
+
+    /// Replace pattern in `target`, a file or a directory, writing each file
+    /// as soon as its replacement is computed and recording every file
+    /// written as one change set. Stops at the timeout, keeping the files
+    /// already written.
+    pub fn replace_journaled(&self, pattern: &str, replacement: &str, target: &Path) -> Result<SedChange> {
+        let start = Instant::now();
+        let regex = self.regex(pattern)?;
+        let files = self.targets(target)?;
+
+        let mut change = SedChange::default();
+        let mut snapshot = Snapshot::new(tool::SED_REPLACE);
+        let mut failure = None;
+        for path in files {
+            if self.cancel.is_cancelled() || start.elapsed() > self.config.timeout {
+                break;
+            }
+
+            self.progress.file_scanned();
+            let Ok(Some(edit)) = self.compute(&regex, replacement, &path) else {
+                continue;
+            };
+            let written = snapshot.add(&path, edit.original.clone()).and_then(|()| {
+                if self.config.backup {
+                    fs::write(format!("{}.bak", path.display()), &edit.original)?;
+                }
+                Ok(changeset::write_atomic(&path, &edit.modified)?)
             });
+            // Keep the journal entry for the files already written
+            if let Err(e) = written {
+                failure = Some(e);
+                break;
+            }
+            self.progress.matches_found(edit.result.replacements);
+            change.results.push(edit.result);
+        }
+
+        change.changeset = snapshot.commit(&self.config.journal)?;
+        self.cancel.check()?;
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(change),
+        }
+    }
+
+    /// `target` itself if it is a file, otherwise the files under it
+    fn targets(&self, target: &Path) -> Result<Vec<PathBuf>> {
+        if fs::metadata(target)?.is_file() {
+            Ok(vec![target.to_path_buf()])
+        } else {
+            Ok(self.walker()?.files(target).map(|entry| entry.into_path()).collect())
+        }
+    }
+
+    fn regex(&self, pattern: &str) -> Result<Regex> {
+        let source = if self.config.literal {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        Ok(RegexBuilder::new(&source)
+            .multi_line(self.config.multiline)
+            .crlf(true)
+            .build()?)
+    }
+
+    /// Apply the replacement to `file_path` in memory; `None` if nothing changed
+    fn compute(&self, regex: &Regex, replacement: &str, file_path: &Path) -> Result<Option<FileEdit>> {
+        // Check file size
+        if let Ok(metadata) = fs::metadata(file_path)
+            && metadata.len() > self.config.max_file_size
+        {
+            return Ok(None);
+        }
+
+        let raw = fs::read(file_path)?;
+        // Binary files are never edited
+        let Some((encoding, text)) = Encoding::decode(&raw) else {
+            return Ok(None);
+        };
+
+        // Text the replacement inserts uses the file's line endings
+        let replacement = if text.windows(2).find(|w| w[1] == b'\n').is_some_and(|w| w[0] == b'\r') {
+            replacement.replace("\r\n", "\n").replace('\n', "\r\n")
+        } else {
+            replacement.to_string()
+        };
+        let replacement = replacement.as_bytes();
+
+        let region = match self.config.line_range {
+            Some(range) => line_span(&text, range),
+            None => 0..text.len(),
+        };
+        let mut budget = self.config.max_replacements.unwrap_or(usize::MAX);
+        let mut replacements = 0;
+        let mut modified = Vec::with_capacity(text.len());
+        modified.extend_from_slice(&text[..region.start]);
+        if self.config.multiline {
+            let (new, count) = self.replace(regex, &text[region.clone()], replacement, budget);
+            modified.extend_from_slice(&new);
+            replacements = count;
+        } else {
+            for line in text[region.clone()].split_inclusive(|&b| b == b'\n') {
+                let body = line.strip_suffix(b"\n").map_or(line, |l| l.strip_suffix(b"\r").unwrap_or(l));
+                let (new, count) = self.replace(regex, body, replacement, budget);
+                modified.extend_from_slice(&new);
+                modified.extend_from_slice(&line[body.len()..]);
+                replacements += count;
+                budget -= count;
+            }
         }
+        modified.extend_from_slice(&text[region.end..]);
 
-        true
+        if replacements == 0 || modified == text {
+            return Ok(None);
+        }
+
+        let content = String::from_utf8_lossy(&text);
+        let new_content = String::from_utf8_lossy(&modified);
+        let file = file_path.display().to_string();
+        let diff = self
+            .config
+            .diff_context
+            .map(|context| unified_diff(&content, &new_content, &file, &file, context));
+        let result = SedResult {
+            file,
+            replacements,
+            original_lines: content.lines().map(|s| s.to_string()).collect(),
+            modified_lines: new_content.lines().map(|s| s.to_string()).collect(),
+            diff,
+        };
+        Ok(Some(FileEdit {
+            result,
+            original: raw,
+            modified: encoding.encode(&modified),
+        }))
+    }
+
+    /// Replace up to `limit` matches in `text`, returning the new text and
+    /// the number replaced
+    fn replace<'t>(&self, regex: &Regex, text: &'t [u8], replacement: &[u8], limit: usize) -> (Cow<'t, [u8]>, usize) {
+        let count = regex.find_iter(text).take(limit).count();
+        // `replacen` treats a limit of zero as unlimited
+        if count == 0 {
+            return (Cow::Borrowed(text), 0);
+        }
+        let new = if self.config.literal {
+            regex.replacen(text, count, NoExpand(replacement))
+        } else {
+            regex.replacen(text, count, replacement)
+        };
+        (new, count)
     }
+
+    fn walker(&self) -> Result<Walker> {
+        Walker::new(WalkConfig {
+            respect_ignore: self.config.respect_ignore,
+            include_hidden: self.config.include_hidden,
+            include_patterns: self.config.include_patterns.clone(),
+            exclude_patterns: self.config.exclude_patterns.clone(),
+            file_types: self.config.file_types.clone(),
+            exclude_file_types: self.config.exclude_file_types.clone(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Parse a line range such as `10-40`, `10-` (to the end) or `7`
+pub fn parse_line_range(range: &str) -> Result<(usize, usize)> {
+    let invalid = || Error::invalid_arguments(format!("Invalid line range '{}'", range));
+    let (start, end) = match range.split_once('-') {
+        Some((start, "")) => (start.trim().parse().map_err(|_| invalid())?, usize::MAX),
+        Some((start, end)) => (
+            start.trim().parse().map_err(|_| invalid())?,
+            end.trim().parse().map_err(|_| invalid())?,
+        ),
+        None => {
+            let line = range.trim().parse().map_err(|_| invalid())?;
+            (line, line)
+        }
+    };
+    if start == 0 || start > end {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Byte span of lines `start..=end` (1-based), including the last newline
+fn line_span(text: &[u8], (start, end): (usize, usize)) -> std::ops::Range<usize> {
+    let mut line_starts = std::iter::once(0)
+        .chain(memchr::memchr_iter(b'\n', text).map(|i| i + 1))
+        .chain(std::iter::repeat(text.len()));
+    let from = line_starts.nth(start - 1).unwrap_or(text.len());
+    let to = line_starts.nth(end - start).unwrap_or(text.len());
+    from.min(text.len())..to.min(text.len())
+}
+
+/// How a file's text is stored, recognized by its byte order mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// UTF-8 without a byte order mark, or any ASCII-compatible encoding,
+    /// edited byte for byte
+    Bytes,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+impl Encoding {
+    /// Detect the encoding and return the text as bytes to edit, UTF-8 for
+    /// UTF-16 files. `None` for binary files.
+    fn decode(raw: &[u8]) -> Option<(Self, Vec<u8>)> {
+        let (encoding, text) = if let Some(text) = raw.strip_prefix(UTF8_BOM) {
+            (Self::Utf8Bom, text.to_vec())
+        } else if let Some(units) = raw.strip_prefix(b"\xFF\xFE") {
+            (Self::Utf16Le, decode_utf16(units, u16::from_le_bytes)?)
+        } else if let Some(units) = raw.strip_prefix(b"\xFE\xFF") {
+            (Self::Utf16Be, decode_utf16(units, u16::from_be_bytes)?)
+        } else {
+            (Self::Bytes, raw.to_vec())
+        };
+        memchr::memchr(0, &text).is_none().then_some((encoding, text))
+    }
+
+    fn encode(self, text: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Bytes => text.to_vec(),
+            Self::Utf8Bom => [UTF8_BOM, text].concat(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let text = String::from_utf8_lossy(text);
+                let mut raw = Vec::with_capacity(2 + text.len() * 2);
+                for unit in std::iter::once(0xFEFF).chain(text.encode_utf16()) {
+                    raw.extend_from_slice(&if self == Self::Utf16Le {
+                        unit.to_le_bytes()
+                    } else {
+                        unit.to_be_bytes()
+                    });
+                }
+                raw
+            }
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<Vec<u8>> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let text: String = char::decode_utf16(units).collect::<std::result::Result<_, _>>().ok()?;
+    Some(text.into_bytes())
 }
 
 impl Default for SedTool {
@@ -231,11 +523,20 @@ mod tests {
         let tool = SedTool::new(SedConfig {
             dry_run: true,
             exclude_patterns: vec![],
+            diff_context: Some(3),
             ..Default::default()
         });
         
         let result = tool.replace_in_file("hello", "hi", &file).unwrap();
         assert!(result.is_some());
+        let name = file.display();
+        assert_eq!(
+            result.unwrap().diff.unwrap(),
+            format!(
+                "--- {0}\n+++ {0}\n@@ -1 +1 @@\n-hello world\n\\ No newline at end of file\n+hi world\n\\ No newline at end of file\n",
+                name
+            )
+        );
 
         // File should not be modified
         let content = fs::read_to_string(&file).unwrap();
@@ -261,4 +562,110 @@ mod tests {
         assert!(content.contains("Color::srgb"));
         assert!(!content.contains("Color::rgb"));
     }
+
+    #[test]
+    fn test_sed_preserves_line_endings_and_bom() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("win.txt");
+        fs::write(&file, b"\xEF\xBB\xBFone\r\ntwo\r\nthree").unwrap();
+
+        let tool = SedTool::default();
+        let result = tool.replace_in_file("o$", "0", &file).unwrap().unwrap();
+        assert_eq!(result.replacements, 1);
+        assert_eq!(fs::read(&file).unwrap(), b"\xEF\xBB\xBFone\r\ntw0\r\nthree");
+
+        let utf16 = dir.path().join("utf16.txt");
+        let encoded: Vec<u8> = [0xFEFF].into_iter().chain("héllo\n".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        fs::write(&utf16, &encoded).unwrap();
+        tool.replace_in_file("é", "e", &utf16).unwrap().unwrap();
+        let expected: Vec<u8> = [0xFEFF].into_iter().chain("hello\n".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        assert_eq!(fs::read(&utf16).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sed_multiline_literal_and_ranges() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        let source = "fn a(\r\n    x: u8,\r\n) {}\r\nfn b(x: u8) {}\r\nfn c(x: u8) {}\r\n";
+
+        // A multi-line signature collapses; inserted newlines follow the file
+        fs::write(&file, source).unwrap();
+        let tool = SedTool::new(SedConfig {
+            multiline: true,
+            ..Default::default()
+        });
+        tool.replace_in_file(r"fn a\(\s*x: u8,\s*\)", "fn a(x: u8)\n", &file).unwrap().unwrap();
+        assert!(fs::read_to_string(&file).unwrap().starts_with("fn a(x: u8)\r\n {}\r\nfn b"));
+
+        // Literal text, only on lines 4-5, at most once
+        fs::write(&file, source).unwrap();
+        let tool = SedTool::new(SedConfig {
+            literal: true,
+            line_range: Some(parse_line_range("4-").unwrap()),
+            max_replacements: Some(1),
+            ..Default::default()
+        });
+        let result = tool.replace_in_file("(x: u8)", "($0)", &file).unwrap().unwrap();
+        assert_eq!(result.replacements, 1);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "fn a(\r\n    x: u8,\r\n) {}\r\nfn b($0) {}\r\nfn c(x: u8) {}\r\n"
+        );
+
+        assert_eq!(parse_line_range("10-40").unwrap(), (10, 40));
+        assert_eq!(parse_line_range("7").unwrap(), (7, 7));
+        assert!(parse_line_range("0-3").is_err());
+        assert!(parse_line_range("5-2").is_err());
+    }
+
+    #[test]
+    fn test_sed_atomic_and_undo() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.rs"), "old_name();\n").unwrap();
+        fs::write(dir.path().join("b.rs"), "fn old_name() {}\n").unwrap();
+        fs::write(dir.path().join("c.rs"), "untouched\n").unwrap();
+
+        let tool = SedTool::new(SedConfig {
+            include_hidden: true,
+            exclude_patterns: vec![],
+            journal: Journal::new(dir.path()),
+            ..Default::default()
+        });
+        let change = tool.replace_atomic("old_name", "new_name", dir.path()).unwrap();
+        assert_eq!(change.results.len(), 2);
+        let changeset = change.changeset.unwrap();
+        assert_eq!(changeset.files.len(), 2);
+        assert_eq!(fs::read_to_string(dir.path().join("b.rs")).unwrap(), "fn new_name() {}\n");
+
+        let journal = Journal::new(dir.path());
+        journal.undo(&changeset.id, false).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a.rs")).unwrap(), "old_name();\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b.rs")).unwrap(), "fn old_name() {}\n");
+
+        let change = tool.replace_journaled("old_name", "other_name", dir.path()).unwrap();
+        assert_eq!(change.changeset.unwrap().files.len(), 2);
+        assert_eq!(fs::read_to_string(dir.path().join("a.rs")).unwrap(), "other_name();\n");
+        journal.undo_last(false).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a.rs")).unwrap(), "old_name();\n");
+    }
+
+    #[test]
+    fn test_sed_never_edits_the_journal() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "foo\n").unwrap();
+        let tool = SedTool::new(SedConfig {
+            include_hidden: true,
+            respect_ignore: false,
+            exclude_patterns: vec![],
+            journal: Journal::new(dir.path()),
+            ..Default::default()
+        });
+        let first = tool.replace_atomic("foo", "bar", dir.path()).unwrap().changeset.unwrap();
+
+        // The journal holds "foo" as a pre-image, which must stay as it is
+        let second = tool.replace_atomic("foo", "baz", dir.path()).unwrap();
+        assert!(second.results.is_empty());
+        Journal::new(dir.path()).undo(&first.id, false).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "foo\n");
+    }
 }
@@ -0,0 +1,394 @@
+//! Shared file walker
+//!
+//! Every tool that walks a directory goes through [`Walker`], so grep, glob,
+//! mgrep, sed and file_stats agree on which files exist. By default it
+//! honors `.gitignore`, `.git/info/exclude`, the global git excludes,
+//! `.ignore` and `.orchestratorignore` (same syntax as `.gitignore`), and
+//! skips hidden files. `.git` directories, the undo journal and the trigram
+//! index are never entered.
+//!
+//! Exclude and include globs are matched against both the absolute path and
+//! the path relative to the walk root, so `**/dist/**` and `src/**/*.rs`
+//! both work. Files can also be selected by ripgrep's named file types
+//! (`rust`, `ts`, `py`, ...).
+
+use super::cancel::CancelToken;
+use super::changeset::JOURNAL_DIR;
+use super::index::INDEX_FILE;
+use crate::{Error, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Instant;
+
+/// Project-level ignore file, read in every directory like `.gitignore`
+pub const ORCHESTRATOR_IGNORE: &str = ".orchestratorignore";
+
+/// Which files a [`Walker`] visits
+#[derive(Debug, Clone)]
+pub struct WalkConfig {
+    /// Honor `.gitignore`, `.ignore`, `.orchestratorignore` and git excludes
+    pub respect_ignore: bool,
+    /// Include hidden files and directories
+    pub include_hidden: bool,
+    /// Follow symbolic links
+    pub follow_links: bool,
+    /// Maximum depth below the root (the root is depth 0)
+    pub max_depth: Option<usize>,
+    /// Files must match one of these globs; empty means every file.
+    /// Directories are always descended into.
+    pub include_patterns: Vec<String>,
+    /// Files and directories matching any of these globs are skipped
+    pub exclude_patterns: Vec<String>,
+    /// Files must be of one of these named types; empty means any type
+    pub file_types: Vec<String>,
+    /// Files of these named types are skipped
+    pub exclude_file_types: Vec<String>,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        Self {
+            respect_ignore: true,
+            include_hidden: false,
+            follow_links: false,
+            max_depth: None,
+            include_patterns: vec![],
+            exclude_patterns: vec![],
+            file_types: vec![],
+            exclude_file_types: vec![],
+        }
+    }
+}
+
+/// Ignore-aware directory walker
+#[derive(Debug, Clone)]
+pub struct Walker {
+    config: WalkConfig,
+    include: GlobSet,
+    exclude: GlobSet,
+    types: Types,
+    cancel: CancelToken,
+    deadline: Option<Instant>,
+    /// Set when a walk stops at the deadline; shared by clones
+    expired: Arc<AtomicBool>,
+}
+
+impl Walker {
+    /// Fails if an include or exclude pattern is not a valid glob, or a
+    /// file type is unknown
+    pub fn new(config: WalkConfig) -> Result<Self> {
+        let include = build_globs(&config.include_patterns)?;
+        let exclude = build_globs(&config.exclude_patterns)?;
+        let types = build_types(&config.file_types, &config.exclude_file_types)?;
+        Ok(Self {
+            config,
+            include,
+            exclude,
+            types,
+            cancel: CancelToken::default(),
+            deadline: None,
+            expired: Arc::default(),
+        })
+    }
+
+    /// Stop walking when `cancel` is triggered
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Stop walking once `deadline` has passed
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Whether a walk stopped early because the deadline passed
+    pub fn expired(&self) -> bool {
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    /// Entries under `root` in a stable, name-sorted order, root first.
+    /// Unreadable entries are skipped.
+    pub fn walk(&self, root: &Path) -> impl Iterator<Item = DirEntry> + use<> {
+        let include = self.include.clone();
+        let cancel = self.cancel.clone();
+        let deadline = self.deadline;
+        let expired = self.expired.clone();
+        let root_path = root.to_path_buf();
+        self.builder(root, None)
+            .build()
+            .take_while(move |_| {
+                if deadline.is_some_and(|d| Instant::now() > d) {
+                    expired.store(true, Ordering::Relaxed);
+                }
+                !cancel.is_cancelled() && !expired.load(Ordering::Relaxed)
+            })
+            .filter_map(|entry| entry.ok())
+            .filter(move |entry| {
+                !is_file(entry) || include.is_empty() || matches(&include, &root_path, entry.path(), false)
+            })
+    }
+
+    /// Regular files under `root`, filtered by the include patterns
+    pub fn files(&self, root: &Path) -> impl Iterator<Item = DirEntry> + use<> {
+        self.walk(root).filter(is_file)
+    }
+
+    /// Regular files under `root`, walked on every core and sorted by path
+    /// so the result matches [`Walker::files`].
+    pub fn files_parallel(&self, root: &Path) -> Vec<DirEntry> {
+        let (tx, rx) = mpsc::channel();
+        self.builder(root, None).build_parallel().run(|| {
+            let tx = tx.clone();
+            let include = self.include.clone();
+            let cancel = self.cancel.clone();
+            let deadline = self.deadline;
+            let expired = self.expired.clone();
+            let root = root.to_path_buf();
+            Box::new(move |entry| {
+                if deadline.is_some_and(|d| Instant::now() > d) {
+                    expired.store(true, Ordering::Relaxed);
+                }
+                if cancel.is_cancelled() || expired.load(Ordering::Relaxed) {
+                    return WalkState::Quit;
+                }
+                if let Ok(entry) = entry
+                    && is_file(&entry)
+                    && (include.is_empty() || matches(&include, &root, entry.path(), false))
+                {
+                    let _ = tx.send(entry);
+                }
+                WalkState::Continue
+            })
+        });
+        drop(tx);
+
+        let mut files: Vec<DirEntry> = rx.into_iter().collect();
+        files.sort_by(|a, b| a.path().cmp(b.path()));
+        files
+    }
+
+    /// Whether walking `root` would yield the file `path`. Only the
+    /// directories on the way to `path` are read.
+    pub fn contains(&self, root: &Path, path: &Path) -> bool {
+        let root_path = root.to_path_buf();
+        self.builder(root, Some(path.to_path_buf()))
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(is_file)
+            .any(|entry| {
+                entry.path() == path
+                    && (self.include.is_empty() || matches(&self.include, &root_path, path, false))
+            })
+    }
+
+    /// `towards` limits the walk to the ancestors of that path
+    fn builder(&self, root: &Path, towards: Option<PathBuf>) -> WalkBuilder {
+        let respect = self.config.respect_ignore;
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .hidden(!self.config.include_hidden)
+            .follow_links(self.config.follow_links)
+            .max_depth(self.config.max_depth)
+            .git_ignore(respect)
+            .git_global(respect)
+            .git_exclude(respect)
+            .ignore(respect)
+            .parents(respect)
+            // Honor .gitignore in directories that are not (yet) git repos
+            .require_git(false)
+            .types(self.types.clone())
+            .sort_by_file_name(|a, b| a.cmp(b));
+        if respect {
+            builder.add_custom_ignore_filename(ORCHESTRATOR_IGNORE);
+        }
+
+        let exclude = self.exclude.clone();
+        let root = root.to_path_buf();
+        builder.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if is_dir && entry.depth() > 0 && (entry.file_name() == ".git" || is_internal(entry.path())) {
+                return false;
+            }
+            if let Some(target) = &towards
+                && !target.starts_with(entry.path())
+            {
+                return false;
+            }
+            exclude.is_empty() || !matches(&exclude, &root, entry.path(), is_dir)
+        });
+        builder
+    }
+}
+
+impl Default for Walker {
+    fn default() -> Self {
+        Self::new(WalkConfig::default()).expect("default walk config has no patterns")
+    }
+}
+
+/// The undo journal and the trigram index, never walked even with hidden
+/// and ignored files included: editing the journal's pre-images would
+/// corrupt later undos
+fn is_internal(path: &Path) -> bool {
+    let index = Path::new(INDEX_FILE).parent().unwrap_or(Path::new(INDEX_FILE));
+    path.ends_with(JOURNAL_DIR) || path.ends_with(index)
+}
+
+fn is_file(entry: &DirEntry) -> bool {
+    entry.file_type().is_some_and(|t| t.is_file())
+}
+
+fn build_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| Error::invalid_arguments(format!("Invalid glob pattern '{}': {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| Error::invalid_arguments(format!("Invalid glob patterns: {}", e)))
+}
+
+fn build_types(select: &[String], negate: &[String]) -> Result<Types> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for name in select {
+        builder.select(name);
+    }
+    for name in negate {
+        builder.negate(name);
+    }
+    builder.build().map_err(|e| match e {
+        ignore::Error::UnrecognizedFileType(name) => {
+            Error::invalid_arguments(format!("Unknown file type '{}'", name))
+        }
+        e => Error::invalid_arguments(format!("Invalid file types: {}", e)),
+    })
+}
+
+/// Match the absolute and root-relative forms of `path`. A directory also
+/// matches when the pattern covers everything inside it (`**/dist/**`), so
+/// excluded trees are pruned instead of walked.
+fn matches(globs: &GlobSet, root: &Path, path: &Path, is_dir: bool) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let candidates = [path, relative];
+    candidates.iter().any(|p| {
+        globs.is_match(p) || (is_dir && globs.is_match(format!("{}/", p.to_string_lossy())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn names(walker: &Walker, root: &Path) -> Vec<String> {
+        walker
+            .files(root)
+            .map(|e| e.path().strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_honors_ignore_files() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for path in ["src/main.rs", "out/bundle.js", "gen/api.pb.rs", ".venv/lib.py", ".hidden", "notes.md"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "out/\n").unwrap();
+        fs::write(root.join(ORCHESTRATOR_IGNORE), "*.pb.rs\n").unwrap();
+
+        let walker = Walker::default();
+        assert_eq!(names(&walker, root), vec!["notes.md", "src/main.rs"]);
+        let parallel: Vec<_> = walker.files_parallel(root).iter().map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(parallel, vec![root.join("notes.md"), root.join("src/main.rs")]);
+        assert!(walker.contains(root, &root.join("src/main.rs")));
+        assert!(!walker.contains(root, &root.join("out/bundle.js")));
+        assert!(!walker.contains(root, &root.join(".venv/lib.py")));
+        assert!(!walker.expired());
+
+        // A walk past its deadline stops and says so
+        let late = Walker::default().with_deadline(Instant::now());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(late.files_parallel(root).is_empty());
+        assert!(late.expired());
+
+        let everything = Walker::new(WalkConfig {
+            respect_ignore: false,
+            include_hidden: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(names(&everything, root).len(), 8);
+    }
+
+    #[test]
+    fn test_walk_include_and_exclude() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for path in ["src/a.rs", "src/b.ts", "dist/c.rs", ".git/config"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+
+        let walker = Walker::new(WalkConfig {
+            include_hidden: true,
+            include_patterns: vec!["*.rs".to_string()],
+            exclude_patterns: vec!["**/dist/**".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(names(&walker, root), vec!["src/a.rs"]);
+
+        let relative = Walker::new(WalkConfig {
+            include_patterns: vec!["src/**".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(names(&relative, root), vec!["src/a.rs", "src/b.ts"]);
+
+        assert!(matches!(
+            Walker::new(WalkConfig {
+                exclude_patterns: vec!["[".to_string()],
+                ..Default::default()
+            }),
+            Err(Error::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_walk_file_types() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for path in ["src/a.rs", "src/b.ts", "web/c.tsx", "tool.py"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+
+        let types = |select: &[&str], negate: &[&str]| {
+            Walker::new(WalkConfig {
+                file_types: select.iter().map(|s| s.to_string()).collect(),
+                exclude_file_types: negate.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        assert_eq!(names(&types(&["ts"], &[]).unwrap(), root), vec!["src/b.ts", "web/c.tsx"]);
+        assert_eq!(names(&types(&["rust", "py"], &[]).unwrap(), root), vec!["src/a.rs", "tool.py"]);
+        assert_eq!(names(&types(&[], &["ts"]).unwrap(), root), vec!["src/a.rs", "tool.py"]);
+        assert!(matches!(types(&["cobol-ish"], &[]), Err(Error::InvalidArguments(_))));
+    }
+}
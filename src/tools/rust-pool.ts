@@ -12,6 +12,16 @@ import { getBinaryPath } from "../utils/binary.js";
 import { log } from "../core/agents/logger.js";
 import { LOG_PREFIX } from "../shared/index.js";
 
+/**
+ * Progress reported by long-running tools (grep, mgrep, sed, file_stats)
+ */
+export interface RustToolProgress {
+    filesScanned: number;
+    matches: number;
+    elapsedMs: number;
+    message?: string;
+}
+
 interface PooledProcess {
     proc: ChildProcess;
     busy: boolean;
@@ -26,6 +36,7 @@ export class RustToolPool {
     private processes: PooledProcess[] = [];
     private maxSize = 4;
     private idleTimeout = 30_000; // 30 seconds
+    private pingTimeout = 2_000; // 2 seconds
     private cleanupInterval: NodeJS.Timeout | null = null;
     private shuttingDown = false;
 
@@ -37,7 +48,11 @@ export class RustToolPool {
     /**
      * Call a Rust tool using pooled connection
      */
-    async call(name: string, args: Record<string, unknown>): Promise<string> {
+    async call(
+        name: string,
+        args: Record<string, unknown>,
+        onProgress?: (progress: RustToolProgress) => void
+    ): Promise<string> {
         if (this.shuttingDown) {
             throw new Error("Pool is shutting down");
         }
@@ -62,7 +77,7 @@ export class RustToolPool {
 
         // Use the process
         try {
-            return await this.sendRequest(pooled, name, args);
+            return await this.sendRequest(pooled, name, args, onProgress);
         } finally {
             this.release(pooled);
         }
@@ -148,7 +163,8 @@ export class RustToolPool {
     private async sendRequest(
         pooled: PooledProcess,
         name: string,
-        args: Record<string, unknown>
+        args: Record<string, unknown>,
+        onProgress?: (progress: RustToolProgress) => void
     ): Promise<string> {
         pooled.busy = true;
         pooled.lastUsed = Date.now();
@@ -159,6 +175,17 @@ export class RustToolPool {
             const timeout = setTimeout(() => {
                 pooled.pendingResolve = undefined;
                 pooled.pendingReject = undefined;
+                pooled.proc.stdout?.removeListener("data", onData);
+                // Tell the server to stop work (and kill child processes) for this request
+                try {
+                    pooled.proc.stdin?.write(JSON.stringify({
+                        jsonrpc: "2.0",
+                        method: "notifications/cancelled",
+                        params: { requestId, reason: "Request timeout" },
+                    }) + "\n");
+                } catch {
+                    // Ignore - process may already be gone
+                }
                 reject(new Error("Request timeout"));
             }, 60_000);
 
@@ -171,10 +198,38 @@ export class RustToolPool {
                 for (let i = lines.length - 1; i >= 0; i--) {
                     try {
                         const response = JSON.parse(lines[i]);
+                        if (response.method === "notifications/progress") {
+                            const params = response.params ?? {};
+                            if (onProgress && params.progressToken === requestId && i === lines.length - 1) {
+                                onProgress({
+                                    filesScanned: params.filesScanned ?? 0,
+                                    matches: params.matches ?? 0,
+                                    elapsedMs: params.elapsedMs ?? 0,
+                                    message: params.message,
+                                });
+                            }
+                            continue;
+                        }
                         if (response.id === requestId && (response.result || response.error)) {
                             clearTimeout(timeout);
                             pooled.proc.stdout?.removeListener("data", onData);
 
+                            if (response.error) {
+                                // JSON-RPC error: keep the stable code so callers can branch on it
+                                resolve(JSON.stringify({
+                                    error: response.error.message,
+                                    code: response.error.code,
+                                    ...(response.error.data ?? {}),
+                                }));
+                                return;
+                            }
+
+                            const structured = response?.result?.structuredContent;
+                            if (structured !== undefined) {
+                                resolve(JSON.stringify(structured));
+                                return;
+                            }
+
                             const text = response?.result?.content?.[0]?.text;
                             resolve(text || JSON.stringify(response.result));
                             return;
@@ -192,7 +247,11 @@ export class RustToolPool {
                 jsonrpc: "2.0",
                 id: requestId,
                 method: "tools/call",
-                params: { name, arguments: args },
+                params: {
+                    name,
+                    arguments: args,
+                    ...(onProgress ? { _meta: { progressToken: requestId } } : {}),
+                },
             });
 
             try {
@@ -228,25 +287,95 @@ export class RustToolPool {
             }
 
             for (const pooled of toRemove) {
-                try {
-                    pooled.proc.kill();
-                } catch {
-                    // Ignore
-                }
-                const index = this.processes.indexOf(pooled);
-                if (index !== -1) {
-                    this.processes.splice(index, 1);
-                }
+                this.remove(pooled);
             }
 
             if (toRemove.length > 0) {
                 log(`[${LOG_PREFIX.RUST_POOL}] Cleaned up ${toRemove.length} idle processes`);
             }
+
+            // Recycle idle workers that stopped answering
+            for (const pooled of this.processes.filter(p => !p.busy)) {
+                void this.healthCheck(pooled);
+            }
         }, 10_000);
 
         this.cleanupInterval.unref();
     }
 
+    /**
+     * Kill a process and drop it from the pool
+     */
+    private remove(pooled: PooledProcess): void {
+        try {
+            // SIGTERM lets the server drain running calls and kill its children
+            pooled.proc.kill();
+        } catch {
+            // Ignore
+        }
+        const index = this.processes.indexOf(pooled);
+        if (index !== -1) {
+            this.processes.splice(index, 1);
+        }
+    }
+
+    /**
+     * Ping an idle process and remove it if it does not answer in time
+     */
+    private async healthCheck(pooled: PooledProcess): Promise<void> {
+        pooled.busy = true;
+        const alive = await this.ping(pooled);
+        pooled.busy = false;
+
+        if (!alive) {
+            log(`[${LOG_PREFIX.RUST_POOL}] Worker did not answer ping, recycling`);
+            this.remove(pooled);
+        }
+    }
+
+    /**
+     * Send a JSON-RPC `ping`; resolves false on timeout or write failure
+     */
+    private ping(pooled: PooledProcess): Promise<boolean> {
+        return new Promise((resolve) => {
+            const requestId = ++pooled.requestId;
+            let buffer = "";
+
+            const finish = (alive: boolean) => {
+                clearTimeout(timer);
+                pooled.proc.stdout?.removeListener("data", onData);
+                resolve(alive);
+            };
+
+            const onData = (data: Buffer) => {
+                buffer += data.toString();
+                for (const line of buffer.split("\n")) {
+                    try {
+                        if (JSON.parse(line).id === requestId) {
+                            finish(true);
+                            return;
+                        }
+                    } catch {
+                        continue;
+                    }
+                }
+            };
+
+            const timer = setTimeout(() => finish(false), this.pingTimeout);
+            pooled.proc.stdout?.on("data", onData);
+
+            try {
+                pooled.proc.stdin?.write(JSON.stringify({
+                    jsonrpc: "2.0",
+                    id: requestId,
+                    method: "ping",
+                }) + "\n");
+            } catch {
+                finish(false);
+            }
+        });
+    }
+
     /**
      * Shutdown pool
      */
@@ -1,5 +1,5 @@
 import { getBinaryPath } from "../utils/binary.js";
-import { getRustToolPool } from "./rust-pool.js";
+import { getRustToolPool, type RustToolProgress } from "./rust-pool.js";
 import { log } from "../core/agents/logger.js";
 import { LOG_PREFIX } from "../shared/index.js";
 
@@ -7,10 +7,14 @@ import { LOG_PREFIX } from "../shared/index.js";
  * Call Rust tool with connection pooling
  * Performance: ~5-10ms (10x faster than spawning each time)
  */
-export async function callRustTool(name: string, args: Record<string, unknown>): Promise<string> {
+export async function callRustTool(
+    name: string,
+    args: Record<string, unknown>,
+    onProgress?: (progress: RustToolProgress) => void
+): Promise<string> {
     try {
         const pool = getRustToolPool();
-        return await pool.call(name, args);
+        return await pool.call(name, args, onProgress);
     } catch (err) {
         log(`[${LOG_PREFIX.RUST_TOOL}] Pool error: ${err}`);
         throw err;